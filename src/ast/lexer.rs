@@ -2,36 +2,173 @@
 // Define the symbol table
 // Define types of tokens
 
+use std::ops::Range;
+
+use num_bigint::BigInt;
+
+use crate::units::Unit;
+
+pub const KEYWORDS: &[&str] = &[
+    "var", "mut", "if", "else", "elseif", "while", "break", "continue", "fun", "return", "out", "math", "set", "true", "false",
+];
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Keyword(String), // Keywords such as 'let', 'if', 'else', ...
     Identifier(String), // Variable, function names
-    Number(i32), // Integer literals TODO: Add support for floating point numbers
+    Number(BigInt), // Integer literals of any length
+    Float(f64), // Floating point literals such as '1.5' or '2e-3'
+    Imaginary(f64), // Imaginary literals such as '3i' or '0.5i'
+    String(String), // String literals, with escapes already processed. May contain {interpolations}, whose strings keep their escapes
     Operator(String), // Operators such as '+', '-', '*', ...
     Symbol(char), // Symbols such as '(', ')', '{', '}', ...
     Assigner(String), // Assigner such as '=', '+=', ...
+    Unit(String), // A unit after a number or after 'to', such as 'km' or 'm/s^2'
+}
+
+fn push_digits(chars: &mut std::iter::Peekable<std::str::Chars>, number: &mut String) {
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+}
+
+fn is_digit_at(chars: &std::iter::Peekable<std::str::Chars>, offset: usize) -> bool {
+    chars.clone().nth(offset).is_some_and(|c| c.is_ascii_digit())
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+// Reads a unit that follows on the same line, e.g. the 'kg*m/s^2' in '3 kg*m/s^2'. The unit is only
+// consumed if it's known and isn't actually a function call, like the 'h' in 'out 5 h(2)'.
+// The lexer can't tell a unit from a variable of the same name, so a unit that is a single name is
+// resolved by the interpreter: if a variable 'm' is declared, '2 m' is 2*m rather than 2 meters.
+fn read_unit(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let mut ahead = chars.clone();
+    while matches!(ahead.peek(), Some(' ' | '\t')) {
+        ahead.next();
+    }
+
+    let mut unit = String::new();
+    loop {
+        let start = unit.len();
+        while let Some(&c) = ahead.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                unit.push(c);
+                ahead.next();
+            } else {
+                break;
+            }
+        }
+        if unit.len() == start || !unit[start..].starts_with(|c: char| c.is_alphabetic()) {
+            return None;
+        }
+        if ahead.peek() == Some(&'^') {
+            unit.push('^');
+            ahead.next();
+            if ahead.peek() == Some(&'-') {
+                unit.push('-');
+                ahead.next();
+            }
+            push_digits(&mut ahead, &mut unit);
+        }
+        // '*' and '/' only continue the unit if another unit follows right away
+        let next = ahead.peek().copied();
+        match next {
+            Some(c @ ('*' | '/')) if ahead.clone().nth(1).is_some_and(|c| c.is_alphabetic()) => {
+                unit.push(c);
+                ahead.next();
+            },
+            _ => break,
+        }
+    }
+    Unit::parse(&unit)?;
+
+    let mut after = ahead.clone();
+    while matches!(after.peek(), Some(' ' | '\t')) {
+        after.next();
+    }
+    if after.peek() == Some(&'(') {
+        return None;
+    }
+    *chars = ahead;
+    Some(unit)
+}
+
+/// An error in the input, with the range of characters it's about
+pub struct LexError {
+    pub message: String,
+    pub span: Range<usize>,
+    pub unterminated: bool, // The input ends inside a string or a comment, more input could complete it
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    match scan(input) {
+        (_, Some(error)) => Err(error.message),
+        (tokens, None) => Ok(tokens.into_iter().map(|(token, _)| token).collect()),
+    }
+}
+
+/// The tokens with the range of characters each one comes from. Lexing stops at the first error,
+/// the tokens before it are still returned, e.g. to highlight a line while it's being typed.
+pub fn scan(input: &str) -> (Vec<(Token, Range<usize>)>, Option<LexError>) {
     let mut tokens = Vec::new();
     let mut chars = input.chars().peekable();
+    let length = input.chars().count();
+    let position = |chars: &std::iter::Peekable<std::str::Chars>| length - chars.clone().count();
 
     while let Some(&ch) = chars.peek() {
+        let start = position(&chars);
         match ch {
             // Match whitespace and skip it
             ' ' | '\t' | '\n' => { chars.next(); },
-            // Match numbers (TODO: Add support for floating point numbers)
+            // Match numbers: integers, floats and imaginary literals
             '0'..='9' => {
                 let mut number = String::new();
-                while let Some(&c) = chars.peek() {
-                    if c.is_digit(10) {
-                        number.push(c);
-                        chars.next();
-                    } else {
-                        break;
+                let mut is_float = false;
+                push_digits(&mut chars, &mut number);
+
+                // Fractional part. The '.' has to be followed by a digit
+                if chars.peek() == Some(&'.') && is_digit_at(&chars, 1) {
+                    is_float = true;
+                    number.push('.');
+                    chars.next();
+                    push_digits(&mut chars, &mut number);
+                }
+
+                // Exponent, such as 'e10' or 'e-3'
+                if matches!(chars.peek(), Some('e') | Some('E')) {
+                    let has_sign = matches!(chars.clone().nth(1), Some('+') | Some('-'));
+                    let digit_offset = if has_sign { 2 } else { 1 };
+                    if is_digit_at(&chars, digit_offset) {
+                        is_float = true;
+                        for _ in 0..digit_offset {
+                            number.push(chars.next().unwrap());
+                        }
+                        push_digits(&mut chars, &mut number);
+                    }
+                }
+
+                // Imaginary suffix, as long as the 'i' isn't the start of an identifier
+                let is_imaginary = chars.peek() == Some(&'i')
+                    && !chars.clone().nth(1).is_some_and(|c| c.is_alphanumeric() || c == '_');
+                if is_imaginary {
+                    chars.next();
+                    tokens.push((Token::Imaginary(number.parse().unwrap()), start..position(&chars)));
+                } else if is_float {
+                    tokens.push((Token::Float(number.parse().unwrap()), start..position(&chars)));
+                } else {
+                    // The literal only contains digits, so parsing it as an arbitrary precision integer can't fail
+                    tokens.push((Token::Number(number.parse().unwrap()), start..position(&chars)));
+                }
+                if !is_imaginary {
+                    let unit_start = position(&chars);
+                    if let Some(unit) = read_unit(&mut chars) {
+                        tokens.push((Token::Unit(unit), unit_start..position(&chars)));
                     }
                 }
-                tokens.push(Token::Number(number.parse().unwrap()));
             },
             // Match keywords and identifiers
             'a'..='z' | 'A'..='Z' | '_' => {
@@ -44,37 +181,113 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                         break;
                     }
                 }
+                // Trailing primes are part of the name, so f' and f'' refer to derivatives of f
+                while chars.peek() == Some(&'\'') {
+                    ident.push('\'');
+                    chars.next();
+                }
+                // 'to' converts to a unit, e.g. 'd to km'. Otherwise it's an ordinary name
+                let end = position(&chars);
+                if ident == "to" {
+                    if let Some(unit) = read_unit(&mut chars) {
+                        tokens.push((Token::Keyword(ident), start..end));
+                        tokens.push((Token::Unit(unit), end..position(&chars)));
+                        continue;
+                    }
+                }
                 match ident.as_str() {
-                    "var" | "mut" | "if" | "else" | "elseif" | "while" | "break" | "continue" | "fun" | "return" | "out" | "math" => tokens.push(Token::Keyword(ident)),
-                    _ => tokens.push(Token::Identifier(ident)),
+                    name if KEYWORDS.contains(&name) => tokens.push((Token::Keyword(ident), start..end)),
+                    _ => tokens.push((Token::Identifier(ident), start..end)),
                 }
             },
             // Match operators, assigners and the NOT operator
-            '+' | '-' | '*' | '/' | '%' | '=' | '<' | '>' | '~' | '&' | '|' => {
+            '+' | '-' | '*' | '/' | '%' | '^' | '=' | '<' | '>' | '~' | '&' | '|' => {
                 let mut op = String::new();
                 
                 op.push(ch);
                 chars.next();
-                if chars.peek() == Some(&'=') {
+                // '+-' gives a value an uncertainty, '9.81 +- 0.02'
+                if ch == '+' && chars.peek() == Some(&'-') {
+                    op.push('-');
+                    chars.next();
+                } else if chars.peek() == Some(&'=') {
                     op.push('=');
                     chars.next();
                 }
 
-                let is_assigner = match op.as_str() {
-                    "+=" | "-=" | "*=" | "/=" | "%=" | "=" => true,
-                    _ => false,
-                };
+                let is_assigner = matches!(op.as_str(), "+=" | "-=" | "*=" | "/=" | "%=" | "=");
 
-                if is_assigner {
-                    tokens.push(Token::Assigner(op));
-                } else {
-                    tokens.push(Token::Operator(op));
+                let token = if is_assigner { Token::Assigner(op) } else { Token::Operator(op) };
+                tokens.push((token, start..position(&chars)));
+            },
+            '±' => {
+                chars.next();
+                tokens.push((Token::Operator("+-".to_string()), start..position(&chars)));
+            },
+            // '..' separates the bounds of an interval, '[1 .. 2]'
+            '.' if chars.clone().nth(1) == Some('.') => {
+                chars.next();
+                chars.next();
+                tokens.push((Token::Operator("..".to_string()), start..position(&chars)));
+            },
+            // Match string literals
+            '"' => {
+                chars.next(); // Consume the opening quote
+                let mut string = String::new();
+                let unterminated = |chars: &std::iter::Peekable<std::str::Chars>| LexError {
+                    message: "Unterminated string literal".to_string(),
+                    span: start..position(chars),
+                    unterminated: true,
+                };
+                // How deep we are in {interpolations}. Strings inside them are kept as they are,
+                // escapes included, and lexed again when the interpolation is parsed.
+                let mut depth = 0;
+                loop {
+                    let escape_start = position(&chars);
+                    match chars.next() {
+                        Some('"') if depth == 0 => break,
+                        Some('"') => {
+                            string.push('"');
+                            loop {
+                                match chars.next() {
+                                    Some('"') => break,
+                                    Some('\\') => match chars.next() {
+                                        Some(c) => { string.push('\\'); string.push(c) },
+                                        None => return (tokens, Some(unterminated(&chars))),
+                                    },
+                                    Some(c) => string.push(c),
+                                    None => return (tokens, Some(unterminated(&chars))),
+                                }
+                            }
+                            string.push('"');
+                        },
+                        Some('{') if depth == 0 && chars.peek() == Some(&'{') => {
+                            chars.next();
+                            string.push_str("{{");
+                        },
+                        Some('{') => { depth += 1; string.push('{') },
+                        Some('}') if depth > 0 => { depth -= 1; string.push('}') },
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(c @ ('"' | '\\')) => string.push(c),
+                            Some(c) => {
+                                let message = format!("Unknown escape sequence: \\{}", c);
+                                let span = escape_start..position(&chars);
+                                return (tokens, Some(LexError { message, span, unterminated: false }));
+                            },
+                            None => return (tokens, Some(unterminated(&chars))),
+                        },
+                        Some(c) => string.push(c),
+                        None => return (tokens, Some(unterminated(&chars))),
+                    }
                 }
+                tokens.push((Token::String(string), start..position(&chars)));
             },
             // Match symbols
-            ',' | '(' | ')' | '{' | '}' | '!' => {
-                tokens.push(Token::Symbol(ch));
+            ',' | '(' | ')' | '{' | '}' | '[' | ']' | '!' | ':' => {
                 chars.next();
+                tokens.push((Token::Symbol(ch), start..start + 1));
             },
             // Ignore semicolons. This can cause issues if we add some functionality to them
             // other than line endings.
@@ -84,23 +297,27 @@ pub fn tokenize(input: &str) -> Vec<Token> {
             // Comments ([[]] for multiline comments)
             '#' => {
                 // If the next two characters are '[[' then it's a multiline comment
-                if chars.clone().skip(1).next() == Some('[') {
+                if chars.clone().nth(1) == Some('[') {
                     // Skip the '#' and the first '['
                     chars.next();
                     chars.next();
                     // Skip the rest of the comment
+                    let mut closed = false;
                     while let Some(ch) = chars.next() {
-                        if ch == ']' {
-                            if chars.clone().next() == Some(']') {
-                                // Skip the last ']'
-                                chars.next();
-                                break;
-                            }
+                        if ch == ']' && chars.clone().next() == Some(']') {
+                            // Skip the last ']'
+                            chars.next();
+                            closed = true;
+                            break;
                         }
                     }
+                    if !closed {
+                        let message = "Unterminated comment, it has to end with ']]'".to_string();
+                        return (tokens, Some(LexError { message, span: start..position(&chars), unterminated: true }));
+                    }
                 } else {
                     // Skip the rest of the line
-                    while let Some(ch) = chars.next() {
+                    for ch in chars.by_ref() {
                         if ch == '\n' {
                             break;
                         }
@@ -109,9 +326,58 @@ pub fn tokenize(input: &str) -> Vec<Token> {
             },
 
             // Unrecognized characters
-            _ => panic!("Unexpected character: {}", ch),
+            _ => {
+                let message = format!("Unexpected character: {}", ch);
+                return (tokens, Some(LexError { message, span: start..start + 1, unterminated: false }));
+            },
         }
     }
 
-    tokens
-}
\ No newline at end of file
+    (tokens, None)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(name: &str) -> Token {
+        Token::Unit(name.to_string())
+    }
+
+    #[test]
+    fn units_follow_numbers() {
+        assert_eq!(tokenize("5 km").unwrap(), vec![Token::Number(5.into()), unit("km")]);
+        assert_eq!(tokenize("5km").unwrap(), vec![Token::Number(5.into()), unit("km")]);
+        assert_eq!(tokenize("9.81 m/s^2").unwrap(), vec![Token::Float(9.81), unit("m/s^2")]);
+        assert_eq!(tokenize("3 kg*m^2").unwrap(), vec![Token::Number(3.into()), unit("kg*m^2")]);
+    }
+
+    #[test]
+    fn operators_with_spaces_end_a_unit() {
+        let tokens = tokenize("10 km / 2 h").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Number(10.into()), unit("km"), Token::Operator("/".to_string()), Token::Number(2.into()), unit("h"),
+        ]);
+    }
+
+    #[test]
+    fn unknown_units_and_function_calls_are_not_units() {
+        assert_eq!(tokenize("2 x").unwrap(), vec![Token::Number(2.into()), Token::Identifier("x".to_string())]);
+        assert_eq!(tokenize("5 h(2)").unwrap()[1], Token::Identifier("h".to_string()));
+        assert_eq!(tokenize("2i").unwrap(), vec![Token::Imaginary(2.0)]);
+    }
+
+    #[test]
+    fn to_is_only_a_keyword_before_a_unit() {
+        assert_eq!(tokenize("d to m").unwrap()[1..], [Token::Keyword("to".to_string()), unit("m")]);
+        assert_eq!(tokenize("to + 1").unwrap()[0], Token::Identifier("to".to_string()));
+    }
+
+    #[test]
+    fn strings_in_interpolations_are_kept_as_they_are() {
+        let string = |s: &str| vec![Token::String(s.to_string())];
+        assert_eq!(tokenize(r#""{len("abc")}""#).unwrap(), string(r#"{len("abc")}"#));
+        assert_eq!(tokenize(r#""{"a\"b\n"} \"c\"""#).unwrap(), string(r#"{"a\"b\n"} "c""#));
+        assert_eq!(tokenize(r#""{{ {"a"} }}""#).unwrap(), string(r#"{{ {"a"} }}"#));
+        assert!(tokenize(r#""{len("abc)}""#).is_err());
+    }
+}
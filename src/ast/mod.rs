@@ -1,7 +1,81 @@
 mod lexer;
 pub mod parser;
+mod printer;
+
+pub use lexer::{scan, Token, KEYWORDS};
 
 pub fn parse(input: &str) -> Result<Vec<parser::ASTNode>, String> {
-    let tokens = lexer::tokenize(input);
+    let tokens = lexer::tokenize(input)?;
     parser::parse(tokens)
-}
\ No newline at end of file
+}
+
+/// Parses a single expression, e.g. '2*x + 1'
+pub fn parse_expression(input: &str) -> Result<parser::ASTNode, String> {
+    let tokens = lexer::tokenize(input)?;
+    parser::parse_expression(tokens)
+}
+/// Whether the input typed so far can be run, see `completeness`
+pub enum Completeness {
+    Complete, // Also when it has an error, which is reported when it's run
+    Incomplete { indent: usize }, // Ends inside brackets, a string or a comment, or after an operator
+    MayContinue, // Ends with an 'if' without 'else', the next line may start with 'else' or 'elseif'
+}
+
+/// Decides if the input continues on the next line: it does if the lexer or the parser runs out of
+/// input while something is still expected, like the rest of a string, a closing bracket or the
+/// operand after an operator. The indent is the number of open brackets, at least one.
+pub fn completeness(input: &str) -> Completeness {
+    let (tokens, error) = lexer::scan(input);
+    let depth = tokens.iter().fold(0usize, |depth, (token, _)| match token {
+        lexer::Token::Symbol('(' | '[' | '{') => depth + 1,
+        lexer::Token::Symbol(')' | ']' | '}') => depth.saturating_sub(1),
+        _ => depth,
+    });
+    match error {
+        Some(error) if error.unterminated => return Completeness::Incomplete { indent: depth },
+        Some(_) => return Completeness::Complete,
+        None => {},
+    }
+
+    let tokens = tokens.into_iter().map(|(token, _)| token).collect();
+    match parser::parse(tokens).as_deref() {
+        Err(error) if parser::is_unexpected_end(error) => Completeness::Incomplete { indent: depth.max(1) },
+        Ok([.., parser::ASTNode::IfStatement { else_body, .. }]) if else_body.is_empty() => Completeness::MayContinue,
+        _ => Completeness::Complete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_incomplete(input: &str) -> bool {
+        matches!(completeness(input), Completeness::Incomplete { .. })
+    }
+
+    #[test]
+    fn input_that_ends_early_continues() {
+        for input in ["1 +", "(1 +", "f(1,", "x = [1, 2", "var x =", "var", "if x {", "while x < 3", "fun f(x)", "math a {", "\"abc", "out x :"] {
+            assert!(is_incomplete(input), "{:?} should be incomplete", input);
+        }
+    }
+
+    #[test]
+    fn errors_before_the_end_are_complete() {
+        for input in ["1 + 2", "(1 +)", "f(1,)", "1 )", "out \"{1 +}\"", "math a { x + = 1 }", "if x + { out 1 }", "var 1"] {
+            assert!(matches!(completeness(input), Completeness::Complete), "{:?} should be complete", input);
+        }
+    }
+
+    #[test]
+    fn an_if_may_be_followed_by_else() {
+        assert!(matches!(completeness("if x { out 1 }"), Completeness::MayContinue));
+        assert!(matches!(completeness("if x { out 1 } else { out 2 }"), Completeness::Complete));
+    }
+
+    #[test]
+    fn the_indent_is_the_number_of_open_brackets() {
+        assert!(matches!(completeness("if x { f(1,"), Completeness::Incomplete { indent: 2 }));
+        assert!(matches!(completeness("1 +"), Completeness::Incomplete { indent: 1 }));
+    }
+}
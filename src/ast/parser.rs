@@ -1,6 +1,28 @@
-use core::panic;
+use num_bigint::BigInt;
 
-use crate::ast::lexer::Token;
+use crate::ast::lexer::{self, Token};
+
+// Parse errors are strings. Input that ends while something is still expected, like '1 +' or
+// 'if x {', fails with an error starting with this, which the REPL takes as a cue to read another line.
+const UNEXPECTED_END: &str = "Unexpected end of input";
+
+fn unexpected_end(expected: &str) -> String {
+    format!("{}, expected {}", UNEXPECTED_END, expected)
+}
+
+/// Whether the input ended too early, so more input could complete it
+pub fn is_unexpected_end(error: &str) -> bool {
+    error.starts_with(UNEXPECTED_END)
+}
+
+// Tokens between brackets are parsed on their own. Running out of them isn't the end of the input,
+// the closing bracket is simply in the wrong place.
+fn inside<T>(closing: char, result: Result<T, String>) -> Result<T, String> {
+    result.map_err(|error| match is_unexpected_end(&error) {
+        true => format!("Unexpected '{}'{}", closing, &error[UNEXPECTED_END.len()..]),
+        false => error,
+    })
+}
 
 #[derive(Clone, Debug)]
 pub enum AssignmentKind {
@@ -29,24 +51,53 @@ pub enum Operator {
     Gt,
     Ge,
     Neg,
+    Pow,
+    Factorial,
+    PlusMinus, // A value with an uncertainty, 9.81 +- 0.02
 }
 
-fn precedence(op: &Operator) -> i32 {
+pub fn precedence(op: &Operator) -> i32 {
     match op {
         Operator::Or => 1,
         Operator::And => 2,
         Operator::Eq | Operator::Ne => 3,
         Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge => 4,
-        Operator::Add | Operator::Sub => 5,
+        Operator::Add | Operator::Sub | Operator::PlusMinus => 5,
         Operator::Mul | Operator::Div | Operator::Mod => 6,
         Operator::Neg | Operator::Not => 7, // Unary operators
+        Operator::Pow => 8, // Binds tighter than negation, so -2^2 is -(2^2)
+        Operator::Factorial => 9, // Postfix operator
     }
 }
 
+#[derive(Clone, Debug)]
+pub enum StringPart {
+    Text(String),
+    Interpolation { // {expr} or {expr:format}
+        expr: Box<ASTNode>,
+        format: Option<String>,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub enum ASTNode {
     Identifier(String), // Variable, function names
-    Number(i32), // Integer literals TODO: Add support for floating point numbers
+    Number(BigInt), // Integer literals
+    Float(f64), // Floating point literals
+    Imaginary(f64), // Imaginary literals, e.g. 3i
+    Bool(bool), // true or false
+    Quantity { // A number with a unit, e.g. 5 km
+        value: Box<ASTNode>,
+        unit: String,
+    },
+    Convert { // expr to unit, e.g. d to km
+        expr: Box<ASTNode>,
+        unit: String,
+    },
+    Interval { // [lower .. upper]
+        lower: Box<ASTNode>,
+        upper: Box<ASTNode>,
+    },
 
     BinaryOp { // An operation that takes two operands
         left: Box<ASTNode>,
@@ -74,10 +125,18 @@ pub enum ASTNode {
         params: Vec<String>,
         body: Vec<ASTNode>,
     },
+    Lambda { // Anonymous function, e.g. fun(x) { return x^2 }
+        params: Vec<String>,
+        body: Vec<ASTNode>,
+    },
     FunctionCall { // Call to a function
         name: String,
         args: Vec<ASTNode>,
     },
+    NamedArgument { // name: value, in a call of a math system
+        name: String,
+        value: Box<ASTNode>,
+    },
 
     IfStatement { // If statement
         condition: Box<ASTNode>,
@@ -92,16 +151,26 @@ pub enum ASTNode {
     Break {},
     Continue {},
     Return { expr: Box<ASTNode> },
-    Output { expr: Box<ASTNode> },
+    Output { expr: Box<ASTNode>, format: Option<String> }, // out <expr> [: <format>]
+    Setting { name: String, value: String }, // set <name> <value>, e.g. 'set angle deg'
+    Expression(Box<ASTNode>), // An expression used as a statement, e.g. 'f(x)' or '2 + 3' in the REPL
+
+    List(Vec<ASTNode>), // List literal, e.g. [1, 2, 3] or [[1, 2], [3, 4]]
+    String(Vec<StringPart>), // String literal, e.g. "x = {x:.3}"
 
     MathBody { // Math expression
         name: String,
+        unknowns: Option<Vec<String>>, // The variables of a 'solve x, y' clause
         body: Vec<ASTNode>,
     },
     MathExpression {
         left: Box<ASTNode>,
         right: Box<ASTNode>,
-    }
+    },
+    MathObjective { // minimize <expr> or maximize <expr>, in a math block with constraints
+        maximize: bool,
+        expr: Box<ASTNode>,
+    },
 
 
     // TODO: Add more AST nodes
@@ -112,10 +181,19 @@ impl ASTNode {
         match self {
             ASTNode::BinaryOp { left, right, .. } => vec![left, right],
             ASTNode::UnaryOp { expr, .. } => vec![expr],
+            ASTNode::Convert { expr, .. } => vec![expr],
+            ASTNode::Interval { lower, upper } => vec![lower, upper],
             ASTNode::VariableDeclaration { value, .. } => vec![value],
             ASTNode::Assignment { value, .. } => vec![value],
             ASTNode::FunctionDeclaration { body, .. } => body.iter().collect(),
+            ASTNode::Lambda { body, .. } => body.iter().collect(),
             ASTNode::FunctionCall { args, .. } => args.iter().collect(),
+            ASTNode::NamedArgument { value, .. } => vec![value],
+            ASTNode::List(items) => items.iter().collect(),
+            ASTNode::String(parts) => parts.iter().filter_map(|part| match part {
+                StringPart::Interpolation { expr, .. } => Some(expr.as_ref()),
+                StringPart::Text(_) => None,
+            }).collect(),
             ASTNode::IfStatement { condition, body, else_body, else_ifs } => {
                 let mut children = vec![condition.as_ref()];
                 children.extend(body.iter());
@@ -134,67 +212,166 @@ impl ASTNode {
             },
 
             ASTNode::Return { expr } => vec![expr],
-            ASTNode::Output { expr } => vec![expr],
+            ASTNode::Output { expr, .. } => vec![expr],
             ASTNode::MathBody { body, .. } => body.iter().collect(),
             ASTNode::MathExpression { left, right } => vec![left, right],
+            ASTNode::MathObjective { expr, .. } => vec![expr],
             _ => vec![],
         }
     }
 }
 
-fn parse_fn_call(name: String, tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<ASTNode, String> {
-    tokens.next(); // Consume the '(' symbol
-    let mut args = Vec::new();
-    let mut level = 1;
-    let mut current_expr = Vec::new();
-    while let Some(token) = tokens.next() {
-        fn push_expr(expr: &mut Vec<Token>, nodes: &mut Vec<ASTNode>) -> Result<(), String> {
-            if !expr.is_empty() {
-                match parse_expr(&mut expr.iter().peekable(), 0) {
-                    Ok(node) => {
-                        nodes.push(node);
-                        return Ok(())
+// Splits a string literal into text and {interpolations}. '{{' and '}}' are literal braces.
+// An interpolation may end with a format specifier after the last top-level ':', e.g. {x:.3}
+fn parse_string(string: &str) -> Result<ASTNode, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = string.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            },
+            '{' => {
+                let mut inner = String::new();
+                let mut format_start = None;
+                let mut level = 0;
+                loop {
+                    match chars.next() {
+                        Some('}') if level == 0 => break,
+                        Some(c @ ('(' | '[' | '{')) => { level += 1; inner.push(c) },
+                        Some(c @ (')' | ']' | '}')) => { level -= 1; inner.push(c) },
+                        // Strings are copied as they are, braces and colons in them don't count
+                        Some('"') => {
+                            inner.push('"');
+                            loop {
+                                match chars.next() {
+                                    Some('"') => break,
+                                    Some('\\') => inner.extend(std::iter::once('\\').chain(chars.next())),
+                                    Some(c) => inner.push(c),
+                                    None => return Err("Unterminated string in '{' in string".to_string()),
+                                }
+                            }
+                            inner.push('"');
+                        },
+                        Some(':') if level == 0 => {
+                            format_start = Some(inner.len());
+                            inner.push(':');
+                        },
+                        Some(c) => inner.push(c),
+                        None => return Err("Unterminated '{' in string".to_string()),
                     }
-                    Err(err) => return Err(err),
                 }
-            } else {
-                return Err("Expected expression".to_string());
-            }
+
+                let (expr_source, format) = match format_start {
+                    Some(index) => (&inner[..index], Some(inner[index + 1..].trim().to_string())),
+                    None => (inner.as_str(), None),
+                };
+                let expr_tokens = lexer::tokenize(expr_source)?;
+                let mut expr_tokens = expr_tokens.iter().peekable();
+                let expr = inside('}', parse_expr(&mut expr_tokens, 0))?;
+                if let Some(token) = expr_tokens.next() {
+                    return Err(format!("Unexpected token {:?} in string interpolation", token));
+                }
+
+                if !text.is_empty() {
+                    parts.push(StringPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(StringPart::Interpolation { expr: Box::new(expr), format });
+            },
+            '}' => return Err("Unmatched '}' in string, use '}}' for a literal brace".to_string()),
+            _ => text.push(ch),
         }
+    }
+
+    if !text.is_empty() {
+        parts.push(StringPart::Text(text));
+    }
+    Ok(ASTNode::String(parts))
+}
+
+// Parses a comma separated list of expressions up to the closing symbol (')' or ']').
+// The opening symbol must already be consumed. Commas inside nested parentheses or
+// brackets belong to the nested expression.
+fn parse_expr_list(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>, closing: char) -> Result<Vec<ASTNode>, String> {
+    fn push_expr(expr: &[Token], nodes: &mut Vec<ASTNode>) -> Result<(), String> {
+        // A named argument, e.g. 'ohm(v: 12, r: 4)'
+        if let [Token::Identifier(name), Token::Symbol(':'), value @ ..] = expr {
+            let value = parse_expr(&mut value.iter().peekable(), 0)?;
+            nodes.push(ASTNode::NamedArgument { name: name.clone(), value: Box::new(value) });
+            Ok(())
+        } else if let Some(index) = interval_separator(expr) {
+            // The bounds of an interval, e.g. '[1 .. 2]'
+            let lower = parse_expr(&mut expr[..index].iter().peekable(), 0)?;
+            let upper = parse_expr(&mut expr[index + 1..].iter().peekable(), 0)?;
+            nodes.push(ASTNode::Interval { lower: Box::new(lower), upper: Box::new(upper) });
+            Ok(())
+        } else if !expr.is_empty() {
+            nodes.push(parse_expr(&mut expr.iter().peekable(), 0)?);
+            Ok(())
+        } else {
+            Err("Expected expression".to_string())
+        }
+    }
+
+    let mut exprs = Vec::new();
+    let mut level = 0;
+    let mut current_expr = Vec::new();
+    for token in tokens.by_ref() {
         match token {
-            Token::Symbol('(') => level += 1,
-            Token::Symbol(')') => {
-                if level == 1 {
-                    match push_expr(&mut current_expr, &mut args) {
-                        Ok(_) => {},
-                        Err(err) => return Err(err),
-                    }
+            Token::Symbol('(') | Token::Symbol('[') => level += 1,
+            Token::Symbol(')') | Token::Symbol(']') if level > 0 => level -= 1,
+            Token::Symbol(c) if *c == closing => {
+                // An empty list, e.g. 'f()' or '[]'
+                if exprs.is_empty() && current_expr.is_empty() {
+                    return Ok(exprs);
                 }
-                level -= 1;
-            }
-            Token::Symbol(',') => {
-                match push_expr(&mut current_expr, &mut args) {
-                    Ok(_) => {},
-                    Err(err) => return Err(err),
-                }
-                current_expr = Vec::new();
+                inside(closing, push_expr(&current_expr, &mut exprs))?;
+                return Ok(exprs);
             },
-            _ => {
-                current_expr.push(token.clone());
+            Token::Symbol(',') if level == 0 => {
+                inside(',', push_expr(&current_expr, &mut exprs))?;
+                current_expr = Vec::new();
+                continue;
             },
+            _ => {},
         }
-        if level == 0 {
-            break;
+        current_expr.push(token.clone());
+    }
+
+    Err(unexpected_end(&format!("'{}'", closing)))
+}
+
+// The position of the '..' between the bounds of an interval, outside of any nested brackets
+fn interval_separator(expr: &[Token]) -> Option<usize> {
+    let mut level = 0;
+    for (i, token) in expr.iter().enumerate() {
+        match token {
+            Token::Symbol('(') | Token::Symbol('[') => level += 1,
+            Token::Symbol(')') | Token::Symbol(']') => level -= 1,
+            Token::Operator(op) if op == ".." && level == 0 => return Some(i),
+            _ => {},
         }
     }
+    None
+}
 
+fn parse_fn_call(name: String, tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<ASTNode, String> {
+    tokens.next(); // Consume the '(' symbol
+    let args = parse_expr_list(tokens, ')')?;
     Ok(ASTNode::FunctionCall { name, args })
 }
 
 fn parse_parantheses(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<ASTNode, String> {
     let mut expr_tokens = Vec::new();
     let mut level = 1;
-    while let Some(token) = tokens.next() {
+    for token in tokens.by_ref() {
         match token {
             Token::Symbol('(') => level += 1,
             Token::Symbol(')') => {
@@ -207,30 +384,40 @@ fn parse_parantheses(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>)
         }
         expr_tokens.push(token.clone());
     }
-    parse_expr(&mut expr_tokens.iter().peekable(), 0)
+    if level > 0 {
+        return Err(unexpected_end("')'"));
+    }
+    inside(')', parse_expr(&mut expr_tokens.iter().peekable(), 0))
 }
 
 fn parse_expr(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>, min_prec: i32) -> Result<ASTNode, String> {
     // Parse the left-hand side expression (either a number, identifier, or a parenthesized expression)
     let mut left: ASTNode = match tokens.next() {
-        Some(Token::Number(value)) => ASTNode::Number(*value),
+        Some(Token::Number(value)) => with_unit(ASTNode::Number(value.clone()), tokens),
+        Some(Token::Float(value)) => with_unit(ASTNode::Float(*value), tokens),
+        Some(Token::Imaginary(value)) => ASTNode::Imaginary(*value),
+        Some(Token::Keyword(keyword)) if keyword == "true" || keyword == "false" => ASTNode::Bool(keyword == "true"),
+        // An anonymous function, e.g. 'fun(x) { return x^2 }'
+        Some(Token::Keyword(keyword)) if keyword == "fun" => {
+            let (params, body) = parse_function(tokens)?;
+            ASTNode::Lambda { params, body }
+        },
+        Some(Token::String(value)) => parse_string(value)?,
         Some(Token::Identifier(name)) => {
             match tokens.peek() {
-                Some(&Token::Symbol('(')) => {
-                    match parse_fn_call(name.clone(), tokens) {
-                        Ok(node) => node,
-                        Err(err) => return Err(err),
-                    }
-                },
+                Some(&Token::Symbol('(')) => parse_fn_call(name.clone(), tokens)?,
                 _ => ASTNode::Identifier(name.clone()),
             }
         },
-        Some(Token::Symbol('(')) => {
-            match parse_parantheses(tokens) {
-                Ok(node) => node,
-                Err(err) => return Err(err),
+        Some(Token::Symbol('(')) => parse_parantheses(tokens)?,
+        Some(Token::Symbol('[')) => {
+            let mut items = parse_expr_list(tokens, ']')?;
+            // '[1 .. 2]' is an interval, not a list with an interval
+            match items[..] {
+                [ASTNode::Interval { .. }] => items.remove(0),
+                _ => ASTNode::List(items),
             }
-        }
+        },
         Some(Token::Operator(op)) => {
             let op_enum = match op.as_str() {
                 "-" => Operator::Neg,
@@ -246,17 +433,29 @@ fn parse_expr(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>, min_pre
                 Err(err) => return Err(err),
             }
         },
-        _ => return Err(format!("Unexpected token {:?}", tokens.peek())),
+        Some(token) => return Err(format!("Unexpected token {:?}", token)),
+        None => return Err(unexpected_end("an expression")),
     };
 
+    // Postfix factorial binds tighter than any other operator
+    while let Some(&Token::Symbol('!')) = tokens.peek() {
+        tokens.next(); // Consume the '!' symbol
+        left = ASTNode::UnaryOp {
+            op: Operator::Factorial,
+            expr: Box::new(left),
+        };
+    }
+
     // Process all operators following the left-hand side, respecting precedence
-    while let Some(&Token::Operator(ref op_str)) = tokens.peek() {
+    while let Some(Token::Operator(op_str)) = tokens.peek() {
         let op_enum = match op_str.as_str() {
             "+" => Operator::Add,
             "-" => Operator::Sub,
             "*" => Operator::Mul,
             "/" => Operator::Div,
             "%" => Operator::Mod,
+            "^" => Operator::Pow,
+            "+-" => Operator::PlusMinus,
             "&" => Operator::And,
             "|" => Operator::Or,
             "<" => Operator::Lt,
@@ -275,8 +474,13 @@ fn parse_expr(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>, min_pre
 
         tokens.next(); // Consume the operator
 
-        // Recursively parse the right-hand side of the expression, considering the next operator's precedence
-        let right = parse_expr(tokens, prec + 1);
+        // Recursively parse the right-hand side of the expression, considering the next operator's precedence.
+        // Exponentiation is right-associative, so 2^3^2 is 2^(3^2)
+        let next_prec = match op_enum {
+            Operator::Pow => prec,
+            _ => prec + 1,
+        };
+        let right = parse_expr(tokens, next_prec);
         match right {
             Ok(node) => {
                 left = ASTNode::BinaryOp {
@@ -289,13 +493,89 @@ fn parse_expr(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>, min_pre
         }
     }
 
+    // A conversion applies to the whole expression, 'a + b to km' converts the sum
+    if min_prec == 0 {
+        if let Some(Token::Keyword(keyword)) = tokens.peek() {
+            if keyword == "to" {
+                tokens.next();
+                let unit = match tokens.next() {
+                    Some(Token::Unit(unit)) => unit,
+                    Some(_) => return Err("Expected a unit after 'to'".to_string()),
+                    None => return Err(unexpected_end("a unit after 'to'")),
+                };
+                left = ASTNode::Convert { expr: Box::new(left), unit: unit.clone() };
+            }
+        }
+    }
+
     Ok(left)
 }
 
+// A number literal followed by a unit is a quantity
+fn with_unit(value: ASTNode, tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> ASTNode {
+    match tokens.peek() {
+        Some(Token::Unit(unit)) => {
+            tokens.next();
+            ASTNode::Quantity { value: Box::new(value), unit: unit.clone() }
+        },
+        _ => value,
+    }
+}
+
+// Parses the parameter list and body of a function: '(a, b) { ... }'
+fn parse_function(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<(Vec<String>, Vec<ASTNode>), String> {
+    let mut params = Vec::new();
+    if let Some(&Token::Symbol('(')) = tokens.peek() {
+        tokens.next(); // Consume the '(' symbol
+        loop {
+            match tokens.next() {
+                Some(Token::Identifier(param)) => params.push(param.clone()),
+                Some(Token::Symbol(')')) if params.is_empty() => break,
+                Some(_) => return Err("Expected a parameter name".to_string()),
+                None => return Err(unexpected_end("')'")),
+            }
+            match tokens.next() {
+                Some(Token::Symbol(',')) => {},
+                Some(Token::Symbol(')')) => break,
+                Some(_) => return Err("Expected ',' or ')'".to_string()),
+                None => return Err(unexpected_end("',' or ')'")),
+            }
+        }
+    }
+
+    // The body is a code block starting with '{' and ending with '}'
+    let mut body = Vec::new();
+    if let Some(&Token::Symbol('{')) = tokens.peek() {
+        tokens.next(); // Consume the '{' symbol
+        // There can be {} nested, so we need to keep track of the nesting level
+        let mut level = 1;
+        for token in tokens.by_ref() {
+            match token {
+                Token::Symbol('{') => level += 1,
+                Token::Symbol('}') => level -= 1,
+                _ => {},
+            }
+            if level == 0 {
+                break;
+            }
+            body.push(token.clone());
+        }
+        if level > 0 {
+            return Err(unexpected_end("'}'"));
+        }
+    } else if tokens.peek().is_none() {
+        return Err(unexpected_end("a code block"));
+    } else {
+        return Err("Expected a code block".to_string());
+    }
+
+    Ok((params, inside('}', parse(body))?))
+}
+
 fn get_body_nodes(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<Vec<Token>, String> {
     let mut nodes = Vec::new();
     let mut level = 1;
-    while let Some(token) = tokens.next() {
+    for token in tokens.by_ref() {
         match token {
             Token::Symbol('{') => level += 1,
             Token::Symbol('}') => {
@@ -309,6 +589,9 @@ fn get_body_nodes(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) ->
 
         nodes.push(token.clone());
     }
+    if level > 0 {
+        return Err(unexpected_end("'}'"));
+    }
     
     Ok(nodes)
 }
@@ -317,50 +600,73 @@ fn parse_body(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Resu
     let nodes = get_body_nodes(tokens);
 
     match nodes {
-        Ok(nodes) => parse(nodes),
+        Ok(nodes) => inside('}', parse(nodes)),
         Err(err) => Err(err),
     }
 }
 
+fn is_comparison(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::BinaryOp { op: Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge, .. })
+}
+
+// Splits chained comparisons like '0 <= x <= 4' into '0 <= x' and 'x <= 4'.
+// Comparisons are left-associative, so the chain is parsed as '(0 <= x) <= 4'.
+fn split_comparisons(node: ASTNode, constraints: &mut Vec<ASTNode>) {
+    match node {
+        ASTNode::BinaryOp { left, op, right } if is_comparison(&left) => {
+            let middle = match left.as_ref() {
+                ASTNode::BinaryOp { right, .. } => right.as_ref().clone(),
+                _ => unreachable!(),
+            };
+            split_comparisons(*left, constraints);
+            constraints.push(ASTNode::BinaryOp { left: Box::new(middle), op, right });
+        },
+        other => constraints.push(other),
+    }
+}
+
 fn parse_math_body(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<Vec<ASTNode>, String> {
     let nodes = get_body_nodes(tokens);
 
     match nodes {
         Ok(nodes) => {
-            // Inside a math block can only be math expressions like this:
+            // Inside a math block can be equations, constraints and an objective:
             // a + b = c + d
-            // a + b = c
-            // c = a + b
-            // i.e. both sides of the equation are a math expression parsed using parse_expr
-            // there can be multiple equations, so we need to loop until there's no more
+            // x + y <= 4
+            // 0 <= x <= 4
+            // minimize 3*x + 2*y
+            // i.e. both sides are math expressions parsed using parse_expr.
+            // There can be multiple of them, so we need to loop until there's no more
 
             let mut output_nodes = Vec::new();
             let mut nodes = nodes.iter().peekable();
-            while let Some(node) = nodes.next() {
-                let mut tokens_left = Vec::new();
-                tokens_left.push(node.clone());
-                while let Some(token) = nodes.next() {
-                    match token {
-                        Token::Assigner(op) => {
-                            if op.as_str() == "=" {
-                                break;
-                            }
-                        },
-                        _ => tokens_left.push(token.clone()),
+            while nodes.peek().is_some() {
+                if let Some(Token::Identifier(word)) = nodes.peek() {
+                    if word == "minimize" || word == "maximize" {
+                        let maximize = word == "maximize";
+                        nodes.next(); // Consume the 'minimize' or 'maximize'
+                        let expr = inside('}', parse_expr(&mut nodes, 0))?;
+                        output_nodes.push(ASTNode::MathObjective { maximize, expr: Box::new(expr) });
+                        continue;
                     }
                 }
-                let left_expr = parse_expr(&mut tokens_left.iter().peekable(), 0);
-                match left_expr {
-                    Ok(_) => {},
-                    Err(err) => return Err(err),
-                };
 
-                let right_expr = parse_expr(&mut nodes, 0);
-                match right_expr {
-                    Ok(_) => {},
-                    Err(err) => return Err(err),
-                };
-                output_nodes.push(ASTNode::MathExpression { left: Box::new(left_expr.unwrap()), right: Box::new(right_expr.unwrap()) });
+                let left_expr = inside('}', parse_expr(&mut nodes, 0))?;
+                if let ASTNode::BinaryOp { left, op: Operator::Eq, right } = left_expr {
+                    output_nodes.push(ASTNode::MathExpression { left, right });
+                    continue;
+                }
+                if is_comparison(&left_expr) {
+                    split_comparisons(left_expr, &mut output_nodes);
+                    continue;
+                }
+
+                match nodes.next() {
+                    Some(Token::Assigner(op)) if op == "=" => {},
+                    _ => return Err(format!("Expected '=' or a comparison after {} in math block", left_expr)),
+                }
+                let right_expr = inside('}', parse_expr(&mut nodes, 0))?;
+                output_nodes.push(ASTNode::MathExpression { left: Box::new(left_expr), right: Box::new(right_expr) });
             }
 
             Ok(output_nodes)
@@ -369,11 +675,41 @@ fn parse_math_body(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) ->
     }
 }
 
+pub fn parse_expression(tokens: Vec<Token>) -> Result<ASTNode, String> {
+    let mut tokens = tokens.iter().peekable();
+    let expr = parse_expr(&mut tokens, 0)?;
+    match tokens.next() {
+        Some(token) => Err(format!("Unexpected token {:?} after the expression", token)),
+        None => Ok(expr),
+    }
+}
+
+// Whether the next statement is an expression: anything that isn't an assignment or starts with a
+// statement keyword. 'fun' followed by '(' is a lambda and 'fun name(' a declaration.
+fn starts_expression(tokens: &std::iter::Peekable<std::slice::Iter<Token>>) -> bool {
+    let mut ahead = tokens.clone();
+    match (ahead.next(), ahead.next()) {
+        (Some(Token::Identifier(_)), Some(Token::Assigner(_))) => false,
+        (Some(Token::Keyword(keyword)), next) => match keyword.as_str() {
+            "true" | "false" => true,
+            "fun" => next == Some(&Token::Symbol('(')),
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
 pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
     let mut nodes = Vec::new();
     let mut tokens = tokens.iter().peekable();
 
-    while let Some(token) = tokens.next() {
+    while let Some(&token) = tokens.peek() {
+        if starts_expression(&tokens) {
+            let expr = parse_expr(&mut tokens, 0)?;
+            nodes.push(ASTNode::Expression(Box::new(expr)));
+            continue;
+        }
+        tokens.next();
         match token {
             Token::Identifier(name) => {
                 match tokens.peek() {
@@ -394,12 +730,6 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                             Err(err) => return Err(err),
                         }
                     },
-                    Some(&Token::Symbol('(')) => {
-                        match parse_fn_call(name.clone(), &mut tokens) {
-                            Ok(node) => nodes.push(node),
-                            Err(err) => return Err(err),
-                        }
-                    },
                     _ => {
                         return Err(format!("Unexpected token \"{}\"", name));
                     },
@@ -410,7 +740,8 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                     "var" => {
                         let name = match tokens.next() {
                             Some(Token::Identifier(name)) => name.clone(),
-                            _ => return Err("Expected an identifier".to_string()),
+                            Some(_) => return Err("Expected an identifier".to_string()),
+                            None => return Err(unexpected_end("an identifier")),
                         };
 
                         match tokens.peek() {
@@ -427,14 +758,15 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                                 }
                             },
                             _ => {
-                                nodes.push(ASTNode::VariableDeclaration { mutable: false, name, value: Box::new(ASTNode::Number(0)) });
+                                nodes.push(ASTNode::VariableDeclaration { mutable: false, name, value: Box::new(ASTNode::Number(BigInt::from(0))) });
                             },
                         }
                     },
                     "mut" => {
                         let name = match tokens.next() {
                             Some(Token::Identifier(name)) => name.clone(),
-                            _ => return Err("Expected an identifier".to_string()),
+                            Some(_) => return Err("Expected an identifier".to_string()),
+                            None => return Err(unexpected_end("an identifier")),
                         };
                         
                         match tokens.peek() {
@@ -450,55 +782,21 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                                     return Err("Unexpected assignment operator during variable declaration".to_string());
                                 }
                             },
-                            _ => {
+                            Some(_) => {
                                 return Err("Expected an assignment operator during variable declaration".to_string());
                             },
+                            None => return Err(unexpected_end("'='")),
                         }
                     },
                     "fun" => {
                         let name = match tokens.next() {
                             Some(Token::Identifier(name)) => name.clone(),
-                            _ => return Err("Expected an identifier".to_string()),
+                            Some(_) => return Err("Expected an identifier".to_string()),
+                            None => return Err(unexpected_end("an identifier")),
                         };
 
-                        let mut params = Vec::new();
-                        if let Some(&Token::Symbol('(')) = tokens.peek() {
-                            tokens.next(); // Consume the '(' symbol
-                            while let Some(&Token::Identifier(ref param)) = tokens.next() {
-                                params.push(param.clone());
-                                match tokens.next() {
-                                    Some(Token::Symbol(',')) => {},
-                                    Some(Token::Symbol(')')) => break,
-                                    _ => return Err("Expected ',' or ')'".to_string()),
-                                }
-                            }
-                        }
-
-                        // The body is a code block starting with '{' and ending with '}'
-                        let mut body = Vec::new();
-                        if let Some(&Token::Symbol('{')) = tokens.peek() {
-                            tokens.next(); // Consume the '{' symbol
-                            // There can be {} nested, so we need to keep track of the nesting level
-                            let mut level = 1;
-                            while let Some(token) = tokens.next() {
-                                match token {
-                                    Token::Symbol('{') => level += 1,
-                                    Token::Symbol('}') => level -= 1,
-                                    _ => {},
-                                }
-                                if level == 0 {
-                                    break;
-                                }
-                                body.push(token.clone());
-                            }
-                        } else {
-                            return Err("Expected a code block".to_string());
-                        }
-                        
-                        match parse(body) {
-                            Ok(mut nodes) => nodes.push(ASTNode::FunctionDeclaration { name, params, body: nodes.clone() }),
-                            Err(err) => return Err(err),
-                        }
+                        let (params, body) = parse_function(&mut tokens)?;
+                        nodes.push(ASTNode::FunctionDeclaration { name, params, body });
                     },
                     "if" => {
                         let mut condition_tokens = Vec::new();
@@ -512,18 +810,19 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                             }
                         }
                         
-                        let condition = parse_expr(&mut condition_tokens.iter().peekable(), 0);
-                        match condition {
-                            Err(err) => return Err(err),
-                            _ => {},
+                        if tokens.peek().is_none() {
+                            return Err(unexpected_end("a code block"));
+                        }
+                        let condition = inside('{', parse_expr(&mut condition_tokens.iter().peekable(), 0));
+                        if let Err(err) = condition {
+                            return Err(err);
                         }
                         if tokens.next() != Some(&Token::Symbol('{')) {
                             return Err("(if) Expected a code block".to_string());
                         }
                         let body = parse_body(&mut tokens);
-                        match body {
-                            Err(err) => return Err(err),
-                            _ => {},
+                        if let Err(err) = body {
+                            return Err(err);
                         }
                         let mut else_body = Vec::new();
                         let mut else_ifs = Vec::new();
@@ -546,19 +845,24 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                                                 }
                                                 tokens.next();
                                             }
-                                            let condition = parse_expr(&mut condition_tokens.iter().peekable(), 0);
+                                            if tokens.peek().is_none() {
+                                                return Err(unexpected_end("a code block"));
+                                            }
+                                            let condition = inside('{', parse_expr(&mut condition_tokens.iter().peekable(), 0))?;
                                             if tokens.next() != Some(&Token::Symbol('{')) {
                                                 return Err("Expected a code block".to_string());
                                             }
                                             match parse_body(&mut tokens) {
-                                                Ok(nodes) => else_ifs.push((Box::new(condition.unwrap()), nodes)),
+                                                Ok(nodes) => else_ifs.push((Box::new(condition), nodes)),
                                                 Err(err) => return Err(err),
                                             }
                                         },
                                         "else" => {
                                             tokens.next(); // Consume the 'else' keyword
-                                            if tokens.next() != Some(&Token::Symbol('{')) {
-                                                return Err("Expected a code block".to_string());
+                                            match tokens.next() {
+                                                Some(Token::Symbol('{')) => {},
+                                                Some(_) => return Err("Expected a code block".to_string()),
+                                                None => return Err(unexpected_end("a code block")),
                                             }
                                             match parse_body(&mut tokens) {
                                                 Ok(nodes) => else_body = nodes,
@@ -588,14 +892,16 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                             }
                             tokens.next();
                         }
-                        let condition = parse_expr(&mut condition_tokens.iter().peekable(), 0);
+                        if tokens.peek().is_none() {
+                            return Err(unexpected_end("a code block"));
+                        }
+                        let condition = inside('{', parse_expr(&mut condition_tokens.iter().peekable(), 0));
                         if tokens.next() != Some(&Token::Symbol('{')) {
                             return Err("Expected a code block".to_string());
                         }
                         let body = parse_body(&mut tokens);
-                        match body {
-                            Err(err) => return Err(err),
-                            _ => {},
+                        if let Err(err) = body {
+                            return Err(err);
                         }
 
                         match condition {
@@ -617,26 +923,79 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<ASTNode>, String> {
                         }
                     },
                     "out" => {
-                        let expr = parse_expr(&mut tokens, 0);
-                        match expr {
-                            Ok(node) => nodes.push(ASTNode::Output { expr: Box::new(node) }),
-                            Err(err) => return Err(err),
+                        let expr = parse_expr(&mut tokens, 0)?;
+                        // An optional display format, e.g. 'out z : polar'
+                        let mut format = None;
+                        if let Some(&Token::Symbol(':')) = tokens.peek() {
+                            tokens.next(); // Consume the ':' symbol
+                            match tokens.next() {
+                                Some(Token::Identifier(name)) => format = Some(name.clone()),
+                                Some(_) => return Err("Expected a format name after ':'".to_string()),
+                                None => return Err(unexpected_end("a format name after ':'")),
+                            }
                         }
+                        nodes.push(ASTNode::Output { expr: Box::new(expr), format });
+                    },
+                    "set" => {
+                        let name = match tokens.next() {
+                            Some(Token::Identifier(name)) => name.clone(),
+                            Some(_) => return Err("Expected the name of a setting after 'set'".to_string()),
+                            None => return Err(unexpected_end("the name of a setting")),
+                        };
+                        // Values are words like 'deg' or numbers like the 6 of 'set digits 6'
+                        let value = match tokens.next() {
+                            Some(Token::Identifier(value)) => value.clone(),
+                            Some(Token::Number(value)) => value.to_string(),
+                            Some(_) => return Err(format!("Expected a value for the setting '{}'", name)),
+                            None => return Err(unexpected_end(&format!("a value for the setting '{}'", name))),
+                        };
+                        nodes.push(ASTNode::Setting { name, value });
                     },
                     "math" => {
                         let name = match tokens.next() {
                             Some(Token::Identifier(name)) => name.clone(),
-                            _ => return Err("Expected an identifier".to_string()),
+                            Some(_) => return Err("Expected an identifier".to_string()),
+                            None => return Err(unexpected_end("an identifier")),
                         };
 
-                        if tokens.next() != Some(&Token::Symbol('{')) {
-                            return Err("Expected a code block".to_string());
+                        // An optional list of the variables to solve for: 'solve x, y' or 'solve for x, y'
+                        let mut unknowns = None;
+                        if let Some(Token::Identifier(word)) = tokens.peek() {
+                            if word == "solve" {
+                                tokens.next(); // Consume the 'solve'
+                                if let Some(Token::Identifier(word)) = tokens.peek() {
+                                    if word == "for" {
+                                        tokens.next(); // Consume the 'for'
+                                    }
+                                }
+                                let mut names = vec![];
+                                while let Some(Token::Identifier(name)) = tokens.next() {
+                                    names.push(name.clone());
+                                    match tokens.peek() {
+                                        Some(Token::Symbol(',')) => { tokens.next(); },
+                                        _ => break,
+                                    }
+                                }
+                                if names.is_empty() && tokens.peek().is_none() {
+                                    return Err(unexpected_end("the variables to solve for"));
+                                }
+                                if names.is_empty() {
+                                    return Err("Expected the variables to solve for after 'solve'".to_string());
+                                }
+                                unknowns = Some(names);
+                            }
+                        }
+
+                        match tokens.next() {
+                            Some(Token::Symbol('{')) => {},
+                            Some(_) => return Err("Expected a code block".to_string()),
+                            None => return Err(unexpected_end("a code block")),
                         }
 
                         let body = parse_math_body(&mut tokens);
 
                         match body {
-                            Ok(body) => nodes.push(ASTNode::MathBody { name, body }),
+                            Ok(body) => nodes.push(ASTNode::MathBody { name, unknowns, body }),
                             Err(err) => return Err(err),
                         }
                     }
@@ -0,0 +1,141 @@
+use std::fmt;
+
+use num_traits::Signed;
+
+use crate::ast::parser::{precedence, ASTNode, Operator, StringPart};
+
+// Prints expressions back as source code, e.g. for symbolic results like the output of diff().
+// Parentheses are only added where the precedence rules of the parser require them,
+// so printing and parsing an expression again gives the same tree.
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Sub | Operator::Neg => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Mod => "%",
+            Operator::And => "&",
+            Operator::Or => "|",
+            Operator::Not => "~",
+            Operator::Eq => "==",
+            Operator::Ne => "~=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Pow => "^",
+            Operator::Factorial => "!",
+            Operator::PlusMinus => "+-",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+// The precedence of the operator at the root of an expression. Atoms bind tightest.
+fn node_precedence(node: &ASTNode) -> i32 {
+    const ATOM: i32 = 10;
+    match node {
+        ASTNode::BinaryOp { op, .. } | ASTNode::UnaryOp { op, .. } => precedence(op),
+        // Negative literals print with a leading '-', so they behave like a negation
+        ASTNode::Number(n) if n.is_negative() => precedence(&Operator::Neg),
+        ASTNode::Float(n) | ASTNode::Imaginary(n) if n.is_sign_negative() => precedence(&Operator::Neg),
+        // 5 km is a product, (5 km)^2 needs parentheses
+        ASTNode::Quantity { .. } => precedence(&Operator::Mul),
+        ASTNode::Convert { .. } => 0,
+        _ => ATOM,
+    }
+}
+
+fn is_negation(node: &ASTNode) -> bool {
+    node_precedence(node) == precedence(&Operator::Neg) && !matches!(node, ASTNode::UnaryOp { op: Operator::Not, .. })
+}
+
+fn write_operand(f: &mut fmt::Formatter, node: &ASTNode, parenthesize: bool) -> fmt::Result {
+    if parenthesize {
+        write!(f, "({})", node)
+    } else {
+        write!(f, "{}", node)
+    }
+}
+
+impl fmt::Display for ASTNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ASTNode::Identifier(name) => write!(f, "{}", name),
+            ASTNode::Number(n) => write!(f, "{}", n),
+            // Debug formatting always keeps the decimal point, so floats stay floats when parsed again
+            ASTNode::Float(n) => write!(f, "{:?}", n),
+            ASTNode::Imaginary(n) => write!(f, "{:?}i", n),
+            ASTNode::Bool(b) => write!(f, "{}", b),
+            ASTNode::Quantity { value, unit } => write!(f, "{} {}", value, unit),
+            ASTNode::Convert { expr, unit } => write!(f, "{} to {}", expr, unit),
+            ASTNode::Interval { lower, upper } => write!(f, "[{} .. {}]", lower, upper),
+            ASTNode::String(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => {
+                            let escaped = text.replace('\\', "\\\\").replace('"', "\\\"")
+                                .replace('\n', "\\n").replace('\t', "\\t")
+                                .replace('{', "{{").replace('}', "}}");
+                            write!(f, "{}", escaped)?;
+                        },
+                        StringPart::Interpolation { expr, format: Some(format) } => write!(f, "{{{}:{}}}", expr, format)?,
+                        StringPart::Interpolation { expr, format: None } => write!(f, "{{{}}}", expr)?,
+                    }
+                }
+                write!(f, "\"")
+            },
+            ASTNode::List(items) => {
+                let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            ASTNode::FunctionCall { name, args } => {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            },
+            ASTNode::BinaryOp { left, op, right } => {
+                let prec = precedence(op);
+                let (left_prec, right_prec) = (node_precedence(left), node_precedence(right));
+                match op {
+                    // Right-associative: (a^b)^c needs parentheses, a^b^c and a^-b don't
+                    Operator::Pow => {
+                        write_operand(f, left, left_prec <= prec)?;
+                        write!(f, "{}", op)?;
+                        write_operand(f, right, right_prec < prec && !is_negation(right))
+                    },
+                    _ => {
+                        write_operand(f, left, left_prec < prec)?;
+                        // Spaces around '+', '-' and comparisons, but not around '*', '/' and '%': 2*x + 1
+                        if prec >= precedence(&Operator::Mul) {
+                            write!(f, "{}", op)?;
+                        } else {
+                            write!(f, " {} ", op)?;
+                        }
+                        write_operand(f, right, right_prec <= prec)
+                    },
+                }
+            },
+            ASTNode::UnaryOp { op: Operator::Factorial, expr } => {
+                write_operand(f, expr, node_precedence(expr) < precedence(&Operator::Factorial))?;
+                write!(f, "!")
+            },
+            ASTNode::UnaryOp { op, expr } => {
+                write!(f, "{}", op)?;
+                // '--x' would be read as a single operator, so nested negations get parentheses
+                write_operand(f, expr, node_precedence(expr) < precedence(op) || is_negation(expr))
+            },
+            ASTNode::Lambda { params, body } => match &body[..] {
+                [ASTNode::Return { expr }] => write!(f, "fun({}) {{ return {} }}", params.join(", "), expr),
+                _ => write!(f, "fun({}) {{ ... }}", params.join(", ")),
+            },
+            ASTNode::NamedArgument { name, value } => write!(f, "{}: {}", name, value),
+            ASTNode::MathExpression { left, right } => write!(f, "{} = {}", left, right),
+            ASTNode::MathObjective { maximize: true, expr } => write!(f, "maximize {}", expr),
+            ASTNode::MathObjective { maximize: false, expr } => write!(f, "minimize {}", expr),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use num_complex::Complex64;
+
+use super::{linalg, quantity, uncertainty};
+use crate::vm::number::Number;
+use crate::vm::settings::{self, Angle};
+use crate::vm::uncertain::{Interval, Uncertain};
+use crate::vm::symbol::DataType;
+
+// Built-in functions and constants. These are looked up after user-defined symbols,
+// so a script can shadow any of them with its own variable or function.
+
+pub const FUNCTIONS: &[&str] = &[
+    "re", "im", "conj", "abs", "arg", "polar",
+    "sqrt", "exp", "ln", "log",
+    "sin", "cos", "tan", "asin", "acos", "atan",
+    "floor", "ceil", "round",
+    "len",
+];
+
+pub const CONSTANTS: &[&str] = &["pi", "e"];
+
+// The functions that take or return angles, which are affected by 'set angle deg'
+const TRIG_FUNCTIONS: &[&str] = &["sin", "cos", "tan", "asin", "acos", "atan", "arg", "polar"];
+
+pub fn constant(name: &str) -> Option<DataType> {
+    match name {
+        "pi" => Some(float(std::f64::consts::PI)),
+        "e" => Some(float(std::f64::consts::E)),
+        _ => None,
+    }
+}
+
+/// The number of arguments the built-in `name` takes, None if there is no such built-in
+pub fn arity(name: &str) -> Option<usize> {
+    if !FUNCTIONS.contains(&name) && !linalg::FUNCTIONS.contains(&name) {
+        return None;
+    }
+    match name {
+        "polar" | "solve" | "dot" | "cross" => Some(2),
+        // log(x, base) also exists, but on its own log is the common logarithm of one value
+        _ => Some(1),
+    }
+}
+
+/// Calls the built-in function `name`. Returns `None` if there is no such built-in.
+pub fn call(name: &str, args: &[DataType]) -> Option<Result<DataType, String>> {
+    if linalg::FUNCTIONS.contains(&name) {
+        return Some(linalg::call(name, args));
+    }
+    if !FUNCTIONS.contains(&name) {
+        return None;
+    }
+    if args.iter().any(|arg| matches!(arg, DataType::Quantity(..))) {
+        return Some(quantity::call(name, args));
+    }
+    // Quantities carry their own angle unit, the angle setting is for plain numbers
+    if settings::get().angle == Angle::Degrees && TRIG_FUNCTIONS.contains(&name) {
+        return Some(call_degrees(name, args));
+    }
+    Some(call_radians(name, args))
+}
+
+fn call_radians(name: &str, args: &[DataType]) -> Result<DataType, String> {
+    if args.iter().any(|arg| arg.is_uncertain()) {
+        return uncertainty::call(name, args);
+    }
+
+    match name {
+        "log" if args.len() == 2 => log_base(&args[0], &args[1]),
+        "polar" => polar(args),
+        "len" => len(args),
+        _ => match args {
+            [value] => call_unary(name, value),
+            _ => Err(format!("{}() takes 1 argument, got {}", name, args.len())),
+        },
+    }
+}
+
+fn call_unary(name: &str, value: &DataType) -> Result<DataType, String> {
+    if !value.is_numeric() {
+        return Err(format!("{}() expects a number, got {}", name, value));
+    }
+
+    match name {
+        "re" => match value {
+            DataType::Complex(c) => Ok(float(c.re)),
+            real => Ok(real.clone()),
+        },
+        "im" => match value {
+            DataType::Complex(c) => Ok(float(c.im)),
+            _ => Ok(DataType::Number(Number::Int(0))),
+        },
+        "conj" => match value {
+            DataType::Complex(c) => Ok(DataType::Complex(c.conj())),
+            real => Ok(real.clone()),
+        },
+        "abs" => match value {
+            DataType::Number(n) if n.is_negative() => Ok(DataType::Number(-n.clone())),
+            DataType::Number(n) => Ok(DataType::Number(n.clone())),
+            complex => Ok(float(to_complex(complex).norm())),
+        },
+        "arg" => Ok(float(to_complex(value).arg())),
+        "sqrt" => {
+            if let DataType::Number(n) = value {
+                if let Some(root) = integer_sqrt(n) {
+                    return Ok(DataType::Number(root));
+                }
+            }
+            Ok(real_or_complex(value, |x| x >= 0.0, f64::sqrt, Complex64::sqrt))
+        },
+        "exp" => Ok(real_or_complex(value, |_| true, f64::exp, Complex64::exp)),
+        "ln" => Ok(real_or_complex(value, |x| x > 0.0, f64::ln, Complex64::ln)),
+        "log" => Ok(real_or_complex(value, |x| x > 0.0, f64::log10, |c| c.ln() / std::f64::consts::LN_10)),
+        "sin" => Ok(real_or_complex(value, |_| true, f64::sin, Complex64::sin)),
+        "cos" => Ok(real_or_complex(value, |_| true, f64::cos, Complex64::cos)),
+        "tan" => Ok(real_or_complex(value, |_| true, f64::tan, Complex64::tan)),
+        "asin" => Ok(real_or_complex(value, |x| x.abs() <= 1.0, f64::asin, Complex64::asin)),
+        "acos" => Ok(real_or_complex(value, |x| x.abs() <= 1.0, f64::acos, Complex64::acos)),
+        "atan" => Ok(real_or_complex(value, |_| true, f64::atan, Complex64::atan)),
+        "floor" => round_with(name, value, f64::floor),
+        "ceil" => round_with(name, value, f64::ceil),
+        "round" => round_with(name, value, f64::round),
+        _ => unreachable!("{} is listed in FUNCTIONS but not implemented", name),
+    }
+}
+
+// The trig functions in degrees. sin and cos are exact at multiples of 90, so cos(90) is 0
+// and not 6.1e-17 like cos(pi/2), and polar(1, 90) is i.
+fn call_degrees(name: &str, args: &[DataType]) -> Result<DataType, String> {
+    let value = match (name, args) {
+        ("polar", [r, theta]) => return polar_degrees(r, theta),
+        (_, [value]) => value,
+        _ => return call_radians(name, args),
+    };
+    if let DataType::Number(n) = value {
+        let degrees = n.to_f64();
+        if degrees % 90.0 == 0.0 && (name == "sin" || name == "cos") {
+            let quarter = (degrees / 90.0).rem_euclid(4.0) as usize;
+            let (sin, cos) = [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0), (-1.0, 0.0)][quarter];
+            return Ok(float(if name == "sin" { sin } else { cos }));
+        }
+    }
+    let radians = std::f64::consts::PI / 180.0;
+    match name {
+        "sin" | "cos" | "tan" => call_radians(name, &[scale(value, radians)]),
+        _ => Ok(scale(&call_radians(name, std::slice::from_ref(value))?, 1.0 / radians)),
+    }
+}
+
+fn polar_degrees(r: &DataType, theta: &DataType) -> Result<DataType, String> {
+    let (Some(r), Some(_)) = (r.to_f64(), theta.to_f64()) else {
+        return Err(format!("polar() expects real numbers, got {} and {}", r, theta));
+    };
+    match (call_degrees("cos", std::slice::from_ref(theta))?, call_degrees("sin", std::slice::from_ref(theta))?) {
+        (DataType::Number(cos), DataType::Number(sin)) => Ok(DataType::from_complex(Complex64::new(r * cos.to_f64(), r * sin.to_f64()))),
+        _ => unreachable!("cos and sin of a real number are real"),
+    }
+}
+
+// Multiplies a number by a real factor, keeping its type
+fn scale(value: &DataType, factor: f64) -> DataType {
+    match value {
+        DataType::Number(n) => float(n.to_f64() * factor),
+        DataType::Complex(c) => DataType::from_complex(c * factor),
+        DataType::Uncertain(u) => DataType::Uncertain(*u * Uncertain::exact(factor)),
+        DataType::Interval(i) => DataType::Interval(*i * Interval::exact(factor)),
+        other => other.clone(),
+    }
+}
+
+fn float(value: f64) -> DataType {
+    DataType::Number(Number::Float(value))
+}
+
+fn to_complex(value: &DataType) -> Complex64 {
+    // Callers have already checked that the value is numeric
+    value.to_complex().unwrap()
+}
+
+// Evaluates a function on reals while the argument is in the real domain of the function,
+// and switches to the complex version of the function outside of it, e.g. sqrt(-1) = i
+fn real_or_complex(
+    value: &DataType,
+    in_real_domain: fn(f64) -> bool,
+    real: fn(f64) -> f64,
+    complex: fn(Complex64) -> Complex64,
+) -> DataType {
+    match value {
+        DataType::Number(n) if in_real_domain(n.to_f64()) => float(real(n.to_f64())),
+        other => DataType::from_complex(complex(to_complex(other))),
+    }
+}
+
+// Square roots of perfect squares stay integers, so sqrt(16) is 4 and not 4.0
+fn integer_sqrt(n: &Number) -> Option<Number> {
+    if !n.is_integer() || n.is_negative() {
+        return None;
+    }
+    let root = n.to_bigint().sqrt();
+    if root.clone() * root.clone() == n.to_bigint() {
+        Some(Number::from_bigint(root))
+    } else {
+        None
+    }
+}
+
+fn round_with(name: &str, value: &DataType, round: fn(f64) -> f64) -> Result<DataType, String> {
+    match value {
+        DataType::Number(n) if n.is_integer() => Ok(value.clone()),
+        DataType::Number(n) => Ok(DataType::Number(Number::Float(round(n.to_f64())).into_integer())),
+        _ => Err(format!("{}() expects a real number, got {}", name, value)),
+    }
+}
+
+fn log_base(value: &DataType, base: &DataType) -> Result<DataType, String> {
+    if !value.is_numeric() || !base.is_numeric() {
+        return Err(format!("log() expects numbers, got {} and {}", value, base));
+    }
+    let value = real_or_complex(value, |x| x > 0.0, f64::ln, Complex64::ln);
+    let base = real_or_complex(base, |x| x > 0.0, f64::ln, Complex64::ln);
+    Ok(value / base)
+}
+
+// len() counts the characters of a string, the items of a vector or list and the rows of a matrix
+fn len(args: &[DataType]) -> Result<DataType, String> {
+    let length = match args {
+        [DataType::String(s)] => s.chars().count(),
+        [DataType::Vector(v)] => v.len(),
+        [DataType::Matrix(m)] => m.nrows(),
+        [DataType::List(items)] => items.len(),
+        [other] => return Err(format!("len() expects a string, vector or list, got {}", other.type_name())),
+        _ => return Err(format!("len() takes 1 argument, got {}", args.len())),
+    };
+    Ok(DataType::Number(Number::Int(length as i64)))
+}
+
+// polar(r, theta) builds the complex number r*e^(theta*i)
+fn polar(args: &[DataType]) -> Result<DataType, String> {
+    match args {
+        [r, theta] => match (r.to_f64(), theta.to_f64()) {
+            (Some(r), Some(theta)) => Ok(DataType::from_complex(Complex64::from_polar(r, theta))),
+            _ => Err(format!("polar() expects real numbers, got {} and {}", r, theta)),
+        },
+        _ => Err(format!("polar() takes 2 arguments, got {}", args.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    fn value_of(code: &str) -> Result<DataType, String> {
+        Interpreter::new().value_of(crate::ast::parse_expression(code)?)
+    }
+
+    fn complex(re: f64, im: f64) -> DataType {
+        DataType::Complex(Complex64::new(re, im))
+    }
+
+    #[test]
+    fn complex_arithmetic() {
+        assert_eq!(value_of("(1 + 2i) * (3 - 1i)"), Ok(complex(5.0, 5.0)));
+        assert_eq!(value_of("(1 + 1i)^4"), Ok(float(-4.0)));
+        assert_eq!(value_of("(1i)^-1"), Ok(complex(0.0, -1.0)));
+        assert_eq!(value_of("1i < 2i"), Err("Cannot compare i and 2i".to_string()));
+    }
+
+    #[test]
+    fn complex_numbers_without_imaginary_part_are_real() {
+        assert_eq!(value_of("(1i)^2"), Ok(float(-1.0)));
+        assert_eq!(value_of("1i * 1i"), Ok(float(-1.0)));
+        assert_eq!(value_of("(1 + 1i) - 1i"), Ok(float(1.0)));
+        assert_eq!(value_of("(2 + 3i) * conj(2 + 3i)"), Ok(float(13.0)));
+    }
+
+    #[test]
+    fn complex_parts() {
+        assert_eq!(value_of("re(3 + 4i)"), Ok(float(3.0)));
+        assert_eq!(value_of("im(3 + 4i)"), Ok(float(4.0)));
+        assert_eq!(value_of("im(3)"), Ok(DataType::Number(Number::Int(0))));
+        assert_eq!(value_of("conj(3 + 4i)"), Ok(complex(3.0, -4.0)));
+        assert_eq!(value_of("abs(3 + 4i)"), Ok(float(5.0)));
+        assert_eq!(value_of("arg(-1)"), Ok(float(std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn functions_become_complex_outside_their_real_domain() {
+        assert_eq!(value_of("sqrt(16)"), Ok(DataType::Number(Number::Int(4))));
+        assert_eq!(value_of("sqrt(-1)"), Ok(complex(0.0, 1.0)));
+        assert_eq!(value_of("sqrt(-4)"), Ok(complex(0.0, 2.0)));
+        assert_eq!(value_of("ln(-1)"), Ok(complex(0.0, std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn logarithms_with_a_base() {
+        assert_eq!(value_of("log(100)"), Ok(float(2.0)));
+        assert_eq!(value_of("log(8, 2)"), Ok(float(3.0)));
+        assert_eq!(value_of("log(81, 3)"), Ok(float(4.0)));
+        assert_eq!(value_of("log(-1, e)"), Ok(complex(0.0, std::f64::consts::PI)));
+        assert!(value_of("log(\"a\", 2)").is_err());
+    }
+
+    #[test]
+    fn angles_follow_the_angle_setting() {
+        settings::set("angle", "deg").unwrap();
+        assert_eq!(value_of("arg(1i)"), Ok(float(90.0)));
+        assert_eq!(value_of("arg(-1)"), Ok(float(180.0)));
+        assert_eq!(value_of("polar(2, 90)"), Ok(complex(0.0, 2.0)));
+        assert_eq!(value_of("polar(2, 180)"), Ok(float(-2.0)));
+        assert_eq!(value_of("sin(90)"), Ok(float(1.0)));
+        settings::reset();
+        assert_eq!(value_of("arg(1i)"), Ok(float(std::f64::consts::FRAC_PI_2)));
+    }
+}
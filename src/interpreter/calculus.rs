@@ -0,0 +1,339 @@
+use nalgebra::{DMatrix, DVector};
+
+use super::{builtins, ode, Interpreter};
+use crate::ast::parser::ASTNode;
+use crate::symbolic;
+use crate::vm::{number::Number, symbol::DataType};
+
+// Numeric calculus built-ins. They take functions as arguments, which can be user functions, lambdas
+// like 'fun(x) { return x^2 }', or an expression followed by the variable it depends on.
+
+pub const FUNCTIONS: &[&str] = &["integrate", "odesolve"];
+
+// Integration stops once the estimated error is below this, relative to the value of the integral
+const RELATIVE_TOLERANCE: f64 = 1e-10;
+const ABSOLUTE_TOLERANCE: f64 = 1e-12;
+const MAX_INTERVALS: usize = 1000;
+
+// 15-point Gauss-Kronrod rule. The nodes with odd indices together with the center form
+// the embedded 7-point Gauss rule, the difference between both is the error estimate.
+// The constants are written with the precision they are usually published with.
+#[allow(clippy::excessive_precision)]
+const KRONROD_NODES: [f64; 8] = [
+    0.991455371120812639206854697526329, 0.949107912342758524526189684047851,
+    0.864864423359769072789712788640926, 0.741531185599394439863864773280788,
+    0.586087235467691130294144845693013, 0.405845151377397166906606412076961,
+    0.207784955007898467600689403773245, 0.0,
+];
+#[allow(clippy::excessive_precision)]
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224963732008058970, 0.063092092629978553290700663189204,
+    0.104790010322250183839876322541518, 0.140653259715525918745189590510238,
+    0.169004726639267902826583426598550, 0.190350578064785409913256402421014,
+    0.204432940075298892414161999234649, 0.209482141084727828012999174891714,
+];
+#[allow(clippy::excessive_precision)]
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.129484966168869693270611432679082, 0.279705391489276667901467771423780,
+    0.381830050505118944950369775488975, 0.417959183673469387755102040816327,
+];
+
+impl Interpreter {
+    /// Calls a calculus built-in. Returns `None` if `name` isn't one of them.
+    pub(super) fn call_calculus(&mut self, name: &str, args: &[ASTNode]) -> Option<Result<DataType, String>> {
+        match name {
+            "integrate" => Some(self.integrate(args)),
+            "odesolve" => Some(self.odesolve(args)),
+            _ => None,
+        }
+    }
+
+    // integrate(f, a, b) or integrate(expr, x, a, b), optionally followed by the largest acceptable error
+    fn integrate(&mut self, args: &[ASTNode]) -> Result<DataType, String> {
+        let usage = "integrate() takes a function and two bounds, or an expression, a variable and two bounds, \
+            optionally followed by the largest acceptable error";
+        let (integrand, bounds) = match args {
+            // A built-in function, e.g. integrate(sin, 0, pi)
+            [ASTNode::Identifier(name), bounds @ ..] if bounds.len() <= 3 && self.is_builtin_function(name) => {
+                let call = symbolic::call(name, vec![ASTNode::Identifier("x".to_string())]);
+                (self.expression_function(&call, "x"), bounds)
+            },
+            [f, bounds @ ..] if bounds.len() <= 3 && self.is_function_value(f) => (self.compute_expr(f.clone())?, bounds),
+            [expr, ASTNode::Identifier(x), bounds @ ..] => (self.expression_function(expr, x), bounds),
+            _ => return Err(usage.to_string()),
+        };
+        let (a, b, limit) = match bounds {
+            [a, b] => (a, b, None),
+            [a, b, limit] => (a, b, Some(self.real_arg(limit)?)),
+            _ => return Err(usage.to_string()),
+        };
+        let (a, b) = (self.real_arg(a)?, self.real_arg(b)?);
+        if limit.is_some_and(|limit| limit <= 0.0 || !limit.is_finite()) {
+            return Err("The largest acceptable error of integrate() must be a positive number".to_string());
+        }
+
+        let mut f = |x: f64| self.call_real(&integrand, x);
+        let (value, error) = gauss_kronrod(&mut f, a, b, limit)?;
+        if error > limit.unwrap_or_else(|| tolerance(value)) {
+            return Err(format!("integrate() did not converge, the estimated error is {:e}", error));
+        }
+        Ok(DataType::Number(Number::Float(value)))
+    }
+
+    // Whether a name that isn't declared is a built-in function, like the 'sin' in 'integrate(sin, 0, pi)'
+    fn is_builtin_function(&self, name: &str) -> bool {
+        self.vm.get_variable(name).is_err() && Interpreter::builtin_names().contains(&name)
+    }
+
+    // Whether the argument evaluates to a function, like a user function or 'fun(x) { return x^2 }'
+    fn is_function_value(&mut self, arg: &ASTNode) -> bool {
+        matches!(self.compute_expr(arg.clone()), Ok(DataType::Function(..)))
+    }
+
+    // odesolve(f, y0, t0, t1, step) with RK4, or odesolve(f, y0, t0, t1) with adaptive steps.
+    // f(t, y) is the derivative of y, which is a number or a vector for systems of equations.
+    // Returns a matrix with a row [t, y...] for every step.
+    fn odesolve(&mut self, args: &[ASTNode]) -> Result<DataType, String> {
+        let (f, y0, t0, t1, step) = match args {
+            [f, y0, t0, t1] => (f, y0, t0, t1, None),
+            [f, y0, t0, t1, step] => (f, y0, t0, t1, Some(step)),
+            _ => return Err(format!("odesolve() takes 4 or 5 arguments, got {}", args.len())),
+        };
+        let f = self.compute_expr(f.clone())?;
+        let (y0, is_system) = match self.compute_expr(y0.clone())? {
+            DataType::Number(n) => (DVector::from_element(1, n.to_f64()), false),
+            DataType::Vector(v) => (v, true),
+            other => Err(format!("The initial value must be a number or a vector, got {}", other))?,
+        };
+        let (t0, t1) = (self.real_arg(t0)?, self.real_arg(t1)?);
+        let step = match step {
+            Some(step) => Some(self.real_arg(step)?),
+            None => None,
+        };
+
+        let size = y0.len();
+        let mut derivative = |t: f64, y: &DVector<f64>| {
+            let y_value = if is_system { DataType::Vector(y.clone()) } else { DataType::Number(Number::Float(y[0])) };
+            match self.call_value(&f, vec![DataType::Number(Number::Float(t)), y_value])? {
+                DataType::Number(n) if size == 1 => Ok(DVector::from_element(1, n.to_f64())),
+                DataType::Vector(v) if v.len() == size => Ok(v),
+                other => Err(format!("Expected the derivative to be {}, got {} at t = {}", if is_system { "a vector of the same size as y" } else { "a number" }, other, t)),
+            }
+        };
+        let samples = match step {
+            Some(step) => ode::rk4(&mut derivative, y0, t0, t1, step)?,
+            None => ode::dormand_prince(&mut derivative, y0, t0, t1)?,
+        };
+
+        let rows: Vec<Vec<f64>> = samples.into_iter().map(|(t, y)| std::iter::once(t).chain(y.iter().cloned()).collect()).collect();
+        Ok(DataType::Matrix(DMatrix::from_fn(rows.len(), size + 1, |i, j| rows[i][j])))
+    }
+
+    /// Replaces the calculus built-ins in an expression by their values, e.g. before solving a math block.
+    pub(super) fn evaluate_calculus(&mut self, node: &ASTNode) -> Result<ASTNode, String> {
+        match node {
+            ASTNode::FunctionCall { name, .. } if FUNCTIONS.contains(&name.as_str()) && self.vm.get_function(name).is_err() => {
+                match self.compute_expr(node.clone()) {
+                    Ok(DataType::Number(n)) => Ok(symbolic::from_number(n)),
+                    Ok(other) => Err(format!("{} is {}, expected a real number", node, other)),
+                    Err(e) => Err(format!("Can't evaluate {}: {}", node, e)),
+                }
+            },
+            ASTNode::BinaryOp { left, op, right } => Ok(symbolic::binary(
+                self.evaluate_calculus(left)?,
+                op.clone(),
+                self.evaluate_calculus(right)?,
+            )),
+            ASTNode::UnaryOp { op, expr } => Ok(ASTNode::UnaryOp {
+                op: op.clone(),
+                expr: Box::new(self.evaluate_calculus(expr)?),
+            }),
+            ASTNode::FunctionCall { name, args } => {
+                let mut evaluated = vec![];
+                for arg in args {
+                    evaluated.push(self.evaluate_calculus(arg)?);
+                }
+                Ok(symbolic::call(name, evaluated))
+            },
+            other => Ok(other.clone()),
+        }
+    }
+
+    // A function of `var` that returns the expression
+    pub(super) fn expression_function(&self, expr: &ASTNode, var: &str) -> DataType {
+        let body = vec![ASTNode::Return { expr: Box::new(expr.clone()) }];
+        DataType::Function(vec![var.to_string()], body, self.vm.capture_scope())
+    }
+
+    /// The functions given as arguments of a built-in like 'plot', with their names: user functions,
+    /// lambdas and built-ins, or expressions followed by the variable they depend on.
+    pub(super) fn function_args(&mut self, args: &[ASTNode]) -> Result<Vec<(String, DataType)>, String> {
+        let mut functions = vec![];
+        match args {
+            // Expressions in a variable, e.g. plot(x^2, x^3, x, -1, 1)
+            [exprs @ .., last @ ASTNode::Identifier(var)] if !exprs.is_empty() && self.is_variable(last) => {
+                for expr in exprs {
+                    functions.push((expr.to_string(), self.expression_function(expr, var)));
+                }
+            },
+            exprs => {
+                for expr in exprs {
+                    functions.push(self.named_function(expr)?);
+                }
+            },
+        }
+        Ok(functions)
+    }
+
+    fn named_function(&mut self, expr: &ASTNode) -> Result<(String, DataType), String> {
+        match expr {
+            // A built-in function, e.g. plot(sin, 0, pi)
+            ASTNode::Identifier(name) if self.vm.get_variable(name).is_err() => {
+                let call = symbolic::call(name, vec![ASTNode::Identifier("x".to_string())]);
+                Ok((name.clone(), self.expression_function(&call, "x")))
+            },
+            _ => match self.compute_expr(expr.clone())? {
+                DataType::Function(params, body, scope) => {
+                    let name = match expr {
+                        ASTNode::Identifier(name) => name.clone(),
+                        _ => format!("fun({})", params.join(", ")),
+                    };
+                    Ok((name, DataType::Function(params, body, scope)))
+                },
+                other => Err(format!("Expected a function, got {}", other)),
+            },
+        }
+    }
+
+    /// Whether the argument is an identifier that isn't defined, which names the variable of expressions
+    pub(super) fn is_variable(&self, arg: &ASTNode) -> bool {
+        match arg {
+            ASTNode::Identifier(name) => {
+                self.vm.get_variable(name).is_err()
+                    && builtins::constant(name).is_none()
+                    && !Interpreter::builtin_names().contains(&name.as_str())
+            },
+            _ => false,
+        }
+    }
+
+    pub(super) fn real_arg(&mut self, arg: &ASTNode) -> Result<f64, String> {
+        match self.compute_expr(arg.clone())? {
+            DataType::Number(n) => Ok(n.to_f64()),
+            other => Err(format!("Expected a real number, got {}", other)),
+        }
+    }
+
+    // Calls a function of one real variable that returns a real number
+    pub(super) fn call_real(&mut self, function: &DataType, x: f64) -> Result<f64, String> {
+        match self.call_value(function, vec![DataType::Number(Number::Float(x))])? {
+            DataType::Number(n) => Ok(n.to_f64()),
+            other => Err(format!("Expected the function to return a real number, got {} at {}", other, x)),
+        }
+    }
+}
+
+fn tolerance(value: f64) -> f64 {
+    ABSOLUTE_TOLERANCE.max(RELATIVE_TOLERANCE * value.abs())
+}
+
+// The 15-point Kronrod estimate of the integral over [a, b] and its difference to the 7-point Gauss rule
+fn kronrod_15(f: &mut dyn FnMut(f64) -> Result<f64, String>, a: f64, b: f64) -> Result<(f64, f64), String> {
+    let center = (a + b) / 2.0;
+    let half_length = (b - a) / 2.0;
+
+    let center_value = f(center)?;
+    let mut kronrod = center_value * KRONROD_WEIGHTS[7];
+    let mut gauss = center_value * GAUSS_WEIGHTS[3];
+    for i in 0..7 {
+        let dx = half_length * KRONROD_NODES[i];
+        let sum = f(center - dx)? + f(center + dx)?;
+        kronrod += KRONROD_WEIGHTS[i] * sum;
+        if i % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[i / 2] * sum;
+        }
+    }
+    Ok((kronrod * half_length, ((kronrod - gauss) * half_length).abs()))
+}
+
+// Globally adaptive integration: the interval with the largest error is split in half until the
+// total error is below `limit`, or the default tolerance. Returns the integral and the estimated error.
+fn gauss_kronrod(f: &mut dyn FnMut(f64) -> Result<f64, String>, a: f64, b: f64, limit: Option<f64>) -> Result<(f64, f64), String> {
+    if !a.is_finite() || !b.is_finite() {
+        return Err("The bounds of an integral must be finite".to_string());
+    }
+
+    let (value, error) = kronrod_15(f, a, b)?;
+    let mut intervals = vec![(a, b, value, error)];
+    loop {
+        let value: f64 = intervals.iter().map(|interval| interval.2).sum();
+        let error: f64 = intervals.iter().map(|interval| interval.3).sum();
+        if !value.is_finite() {
+            return Err("The integral diverges".to_string());
+        }
+        if error <= limit.unwrap_or_else(|| tolerance(value)) || intervals.len() >= MAX_INTERVALS {
+            return Ok((value, error));
+        }
+
+        let worst = (0..intervals.len()).max_by(|&i, &j| intervals[i].3.total_cmp(&intervals[j].3)).unwrap();
+        let (a, b, _, _) = intervals.swap_remove(worst);
+        let middle = (a + b) / 2.0;
+        // The interval can't be split any further
+        if middle == a || middle == b {
+            return Ok((value, error));
+        }
+        for (a, b) in [(a, middle), (middle, b)] {
+            let (value, error) = kronrod_15(f, a, b)?;
+            intervals.push((a, b, value, error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integral(code: &str) -> Result<f64, String> {
+        match Interpreter::new().value_of(crate::ast::parse_expression(code)?)? {
+            DataType::Number(n) => Ok(n.to_f64()),
+            other => Err(format!("Expected a number, got {}", other)),
+        }
+    }
+
+    #[test]
+    fn integrates_expressions_and_functions() {
+        assert!((integral("integrate(x^2, x, 0, 3)").unwrap() - 9.0).abs() < 1e-9);
+        assert!((integral("integrate(sin, 0, pi)").unwrap() - 2.0).abs() < 1e-9);
+        assert!((integral("integrate(fun(t) { return 1/sqrt(t) }, 0, 1)").unwrap() - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_acceptable_error_can_be_given() {
+        assert!((integral("integrate(x^2, x, 0, 3, 1e-3)").unwrap() - 9.0).abs() < 1e-3);
+        assert!((integral("integrate(sin, 0, pi, 0.1)").unwrap() - 2.0).abs() < 0.1);
+        assert!(integral("integrate(x^2, x, 0, 3, 0)").is_err());
+    }
+
+    #[test]
+    fn integrals_that_dont_converge_are_errors() {
+        let error = integral("integrate(fun(t) { return 1/t }, 0, 1)").unwrap_err();
+        assert!(error.starts_with("integrate() did not converge, the estimated error is"), "{}", error);
+    }
+
+    #[test]
+    fn odesolve_returns_a_row_per_step() {
+        // y' = y with y(0) = 1 is solved by e^t, with RK4 and a step and with adaptive steps
+        for code in ["odesolve(fun(t, y) { return y }, 1, 0, 2, 0.01)", "odesolve(fun(t, y) { return y }, 1, 0, 2)"] {
+            let Ok(DataType::Matrix(m)) = Interpreter::new().value_of(crate::ast::parse_expression(code).unwrap()) else {
+                panic!("Expected a matrix from {}", code);
+            };
+            assert_eq!(m.ncols(), 2);
+            assert_eq!((m[(0, 0)], m[(m.nrows() - 1, 0)]), (0.0, 2.0));
+            for row in m.row_iter() {
+                assert!((row[1] - row[0].exp()).abs() < 1e-6, "{}: y({}) = {}", code, row[0], row[1]);
+            }
+        }
+        let error = Interpreter::new().value_of(crate::ast::parse_expression("odesolve(fun(t, y) { return [y, y] }, 1, 0, 1)").unwrap()).unwrap_err();
+        assert!(error.starts_with("Expected the derivative to be a number"), "{}", error);
+    }
+}
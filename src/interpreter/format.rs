@@ -0,0 +1,87 @@
+use num_bigint::BigInt;
+
+use crate::vm::number::{self, format_float, Number};
+use crate::vm::symbol::{format_complex, format_matrix, DataType};
+
+// Format specifiers used by string interpolation ("{x:.3}") and by 'out x : polar'.
+//
+//   rect       the default, same as no specifier
+//   polar      complex numbers as r*e^(θi)
+//   hex, bin, oct
+//              integers in another base, e.g. 0xff
+//   .N         N digits after the decimal point
+//   e, .Ne     scientific notation, optionally with N digits after the decimal point
+//
+// Precision specifiers are applied to every number inside a complex number, vector or matrix.
+
+pub fn format_value(value: &DataType, spec: Option<&str>) -> Result<String, String> {
+    let spec = spec.unwrap_or("");
+    match spec {
+        "" | "rect" => return Ok(value.to_string()),
+        "polar" => return Ok(value.display_polar()),
+        "hex" | "bin" | "oct" => return format_radix(value, spec),
+        _ => {},
+    }
+
+    let format_float = float_formatter(spec)?;
+    format_with(value, &format_float)
+}
+
+// Parses a precision specifier ("e", ".N" or ".Ne") into a function that formats a single float
+fn float_formatter(spec: &str) -> Result<impl Fn(f64) -> String, String> {
+    let (digits, scientific) = match spec.strip_suffix('e') {
+        Some(digits) => (digits, true),
+        None => (spec, false),
+    };
+    let precision = match digits {
+        "" if scientific => None,
+        _ => match digits.strip_prefix('.').and_then(|n| n.parse::<usize>().ok()) {
+            Some(precision) => Some(precision),
+            None => return Err(format!("Unknown format specifier '{}'", spec)),
+        },
+    };
+
+    Ok(move |x: f64| match (precision, scientific) {
+        (Some(precision), true) => format!("{:.*e}", precision, x),
+        (Some(precision), false) => format!("{:.*}", precision, x),
+        (None, _) => format!("{:e}", x),
+    })
+}
+
+fn format_with(value: &DataType, format_float: &dyn Fn(f64) -> String) -> Result<String, String> {
+    match value {
+        DataType::Number(n) => Ok(format_float(n.to_f64())),
+        DataType::Complex(c) => Ok(format_complex(c, format_float)),
+        DataType::Quantity(value, unit) => Ok(unit.format(*value, format_float)),
+        DataType::Uncertain(u) => Ok(u.format(format_float)),
+        DataType::Interval(i) => Ok(i.format(format_float)),
+        DataType::Vector(v) => {
+            let items: Vec<String> = v.iter().map(|x| format_float(*x)).collect();
+            Ok(format!("[{}]", items.join(", ")))
+        },
+        DataType::Matrix(m) => Ok(format_matrix(m, format_float)),
+        DataType::List(items) => {
+            let mut formatted = vec![];
+            for item in items {
+                formatted.push(format_with(item, format_float)?);
+            }
+            Ok(format!("[{}]", formatted.join(", ")))
+        },
+        other => Err(format!("Cannot apply a number format to {}", other.type_name())),
+    }
+}
+
+fn format_radix(value: &DataType, spec: &str) -> Result<String, String> {
+    let n: BigInt = match value {
+        DataType::Number(n) if n.is_integer() => n.to_bigint(),
+        DataType::Number(Number::Float(n)) => return Err(format!("'{}' expects an integer, got {}", spec, format_float(*n))),
+        other => return Err(format!("'{}' expects an integer, got {}", spec, other.type_name())),
+    };
+
+    let base = match spec {
+        "hex" => 16,
+        "bin" => 2,
+        _ => 8,
+    };
+    Ok(number::format_radix(&n, base))
+}
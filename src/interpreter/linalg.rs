@@ -0,0 +1,286 @@
+use nalgebra::{DMatrix, DVector};
+use num_complex::Complex64;
+
+use crate::ast::parser::Operator;
+use crate::vm::number::Number;
+use crate::vm::symbol::DataType;
+
+// Vectors and matrices. All linear algebra is done on f64 using nalgebra.
+
+pub const FUNCTIONS: &[&str] = &["transpose", "det", "inv", "eig", "solve", "dot", "cross", "identity"];
+
+// The largest identity matrix that can be created, 1000x1000 already takes 8 MB
+const MAX_IDENTITY_SIZE: u32 = 1000;
+
+/// Builds a value from the items of a list literal. A list of real numbers becomes a vector,
+/// a list of equally long vectors becomes a matrix (one vector per row), anything else stays a list.
+pub fn from_list(items: Vec<DataType>) -> Result<DataType, String> {
+    if items.is_empty() {
+        return Ok(DataType::List(items));
+    }
+
+    if items.iter().all(|item| item.is_number()) {
+        let values = items.iter().map(|item| item.to_f64().unwrap());
+        return Ok(DataType::Vector(DVector::from_iterator(items.len(), values)));
+    }
+
+    if items.iter().all(|item| matches!(item, DataType::Vector(_))) {
+        let rows: Vec<&DVector<f64>> = items.iter().map(|item| match item {
+            DataType::Vector(v) => v,
+            _ => unreachable!(),
+        }).collect();
+        let ncols = rows[0].len();
+        if rows.iter().any(|row| row.len() != ncols) {
+            return Err("All rows of a matrix must have the same length".to_string());
+        }
+        return Ok(DataType::Matrix(DMatrix::from_fn(rows.len(), ncols, |i, j| rows[i][j])));
+    }
+
+    Ok(DataType::List(items))
+}
+
+/// Applies a binary operator where at least one side is a vector, matrix or list.
+pub fn binary_op(left: DataType, op: &Operator, right: DataType) -> Result<DataType, String> {
+    match op {
+        Operator::Eq => return Ok(DataType::Bool(left == right)),
+        Operator::Ne => return Ok(DataType::Bool(left != right)),
+        _ => {},
+    }
+
+    match (op, &left, &right) {
+        (Operator::Add | Operator::Sub, DataType::Vector(a), DataType::Vector(b)) => {
+            check_same_shape(a.shape(), b.shape())?;
+            Ok(DataType::Vector(if matches!(op, Operator::Add) { a + b } else { a - b }))
+        },
+        (Operator::Add | Operator::Sub, DataType::Matrix(a), DataType::Matrix(b)) => {
+            check_same_shape(a.shape(), b.shape())?;
+            Ok(DataType::Matrix(if matches!(op, Operator::Add) { a + b } else { a - b }))
+        },
+        (Operator::Mul, DataType::Vector(_), DataType::Vector(_)) => {
+            Err("Cannot multiply two vectors, use dot() or cross()".to_string())
+        },
+        (Operator::Mul, _, DataType::Number(_)) | (Operator::Div, _, DataType::Number(_)) => {
+            let scalar = right.to_f64().unwrap();
+            if matches!(op, Operator::Div) && scalar == 0.0 {
+                return Err("Division by zero".to_string());
+            }
+            let factor = if matches!(op, Operator::Mul) { scalar } else { 1.0 / scalar };
+            scale(&left, factor)
+        },
+        (Operator::Mul, DataType::Number(n), _) => scale(&right, n.to_f64()),
+        (Operator::Mul, _, _) => {
+            let (a, b) = (to_matrix(&left)?, to_matrix(&right)?);
+            if a.ncols() != b.nrows() {
+                return Err(format!("Cannot multiply a {}x{} matrix by a {}x{} matrix", a.nrows(), a.ncols(), b.nrows(), b.ncols()));
+            }
+            Ok(from_product(a * b))
+        },
+        (Operator::Pow, DataType::Matrix(m), DataType::Number(n)) if n.is_integer() => matrix_pow(m, n),
+        _ => Err(format!("Unsupported operation {:?} between {} and {}", op, left.type_name(), right.type_name())),
+    }
+}
+
+/// Calls the linear algebra built-in `name`.
+pub fn call(name: &str, args: &[DataType]) -> Result<DataType, String> {
+    match (name, args) {
+        ("transpose", [value]) => Ok(from_matrix(to_matrix(value)?.transpose())),
+        ("det", [value]) => {
+            let m = to_square_matrix(value)?;
+            Ok(float(m.determinant()))
+        },
+        ("inv", [value]) => match to_square_matrix(value)?.try_inverse() {
+            Some(inverse) => Ok(DataType::Matrix(inverse)),
+            None => Err("Matrix is singular and has no inverse".to_string()),
+        },
+        ("eig", [value]) => eigenvalues(to_square_matrix(value)?),
+        ("solve", [a, b]) => solve(to_matrix(a)?, b),
+        ("dot", [DataType::Vector(a), DataType::Vector(b)]) => {
+            check_same_shape(a.shape(), b.shape())?;
+            Ok(float(a.dot(b)))
+        },
+        ("cross", [DataType::Vector(a), DataType::Vector(b)]) => {
+            if a.len() != 3 || b.len() != 3 {
+                return Err("cross() expects two vectors of length 3".to_string());
+            }
+            Ok(DataType::Vector(a.cross(b)))
+        },
+        ("identity", [DataType::Number(n)]) => match n.to_u32() {
+            Some(n) if n > MAX_IDENTITY_SIZE => Err(format!("identity() creates matrices up to {0}x{0}, got {1}", MAX_IDENTITY_SIZE, n)),
+            Some(n) => Ok(DataType::Matrix(DMatrix::identity(n as usize, n as usize))),
+            None => Err(format!("identity() expects a non-negative integer, got {}", n)),
+        },
+        _ => {
+            let types: Vec<&str> = args.iter().map(DataType::type_name).collect();
+            Err(format!("Invalid arguments for {}(): {}", name, types.join(", ")))
+        },
+    }
+}
+
+fn float(value: f64) -> DataType {
+    DataType::Number(Number::Float(value))
+}
+
+fn check_same_shape(a: (usize, usize), b: (usize, usize)) -> Result<(), String> {
+    if a != b {
+        return Err(format!("Dimension mismatch: {}x{} and {}x{}", a.0, a.1, b.0, b.1));
+    }
+    Ok(())
+}
+
+fn to_matrix(value: &DataType) -> Result<DMatrix<f64>, String> {
+    match value {
+        DataType::Matrix(m) => Ok(m.clone()),
+        DataType::Vector(v) => Ok(DMatrix::from_column_slice(v.len(), 1, v.as_slice())),
+        other => Err(format!("Expected a matrix or vector, got {}", other.type_name())),
+    }
+}
+
+fn to_square_matrix(value: &DataType) -> Result<DMatrix<f64>, String> {
+    let m = to_matrix(value)?;
+    if !m.is_square() {
+        return Err(format!("Expected a square matrix, got a {}x{} matrix", m.nrows(), m.ncols()));
+    }
+    Ok(m)
+}
+
+// Single column matrices are returned as vectors
+fn from_matrix(m: DMatrix<f64>) -> DataType {
+    if m.ncols() == 1 {
+        DataType::Vector(m.column(0).into())
+    } else {
+        DataType::Matrix(m)
+    }
+}
+
+// A 1x1 product, such as a row vector times a column vector, is a plain number
+fn from_product(m: DMatrix<f64>) -> DataType {
+    if m.shape() == (1, 1) {
+        float(m[(0, 0)])
+    } else {
+        from_matrix(m)
+    }
+}
+
+fn scale(value: &DataType, factor: f64) -> Result<DataType, String> {
+    match value {
+        DataType::Vector(v) => Ok(DataType::Vector(v * factor)),
+        DataType::Matrix(m) => Ok(DataType::Matrix(m * factor)),
+        other => Err(format!("Cannot scale a {}", other.type_name())),
+    }
+}
+
+// M^n by repeated squaring. Negative powers invert the matrix first.
+fn matrix_pow(m: &DMatrix<f64>, n: &Number) -> Result<DataType, String> {
+    if !m.is_square() {
+        return Err(format!("Only square matrices can be raised to a power, got a {}x{} matrix", m.nrows(), m.ncols()));
+    }
+    let base = if n.is_negative() {
+        m.clone().try_inverse().ok_or("Matrix is singular and has no inverse")?
+    } else {
+        m.clone()
+    };
+    let mut exponent = match (if n.is_negative() { -n.clone() } else { n.clone() }).to_u32() {
+        Some(exponent) => exponent,
+        None => return Err(format!("Exponent {} is too large", n)),
+    };
+
+    let mut result = DMatrix::identity(m.nrows(), m.ncols());
+    let mut square = base;
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = &result * &square;
+        }
+        square = &square * &square;
+        exponent /= 2;
+    }
+    Ok(DataType::Matrix(result))
+}
+
+// Eigenvalues sorted by real part. They are returned as a vector if they are all real,
+// otherwise as a list of complex numbers.
+fn eigenvalues(m: DMatrix<f64>) -> Result<DataType, String> {
+    if m.is_empty() {
+        return Ok(DataType::List(vec![]));
+    }
+
+    let mut values: Vec<Complex64> = m.complex_eigenvalues().iter().cloned().collect();
+    values.sort_by(|a, b| a.re.total_cmp(&b.re).then(a.im.total_cmp(&b.im)));
+
+    let scale = values.iter().map(|c| c.norm()).fold(1.0, f64::max);
+    if values.iter().all(|c| c.im.abs() <= 1e-12 * scale) {
+        Ok(DataType::Vector(DVector::from_iterator(values.len(), values.iter().map(|c| c.re))))
+    } else {
+        Ok(DataType::List(values.into_iter().map(DataType::from_complex).collect()))
+    }
+}
+
+// Solves A x = b. Square systems are solved exactly, other systems in the least squares sense.
+fn solve(a: DMatrix<f64>, b: &DataType) -> Result<DataType, String> {
+    let b = to_matrix(b)?;
+    if a.nrows() != b.nrows() {
+        return Err(format!("Dimension mismatch: A has {} rows but b has {}", a.nrows(), b.nrows()));
+    }
+
+    let solution = if a.is_square() {
+        a.lu().solve(&b).ok_or("Matrix is singular, the system has no unique solution")?
+    } else {
+        a.svd(true, true).solve(&b, 1e-12)?
+    };
+    Ok(from_matrix(solution))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[f64]]) -> DataType {
+        DataType::Matrix(DMatrix::from_fn(rows.len(), rows[0].len(), |i, j| rows[i][j]))
+    }
+
+    fn vector(values: &[f64]) -> DataType {
+        DataType::Vector(DVector::from_column_slice(values))
+    }
+
+    fn assert_close(value: DataType, expected: DataType) {
+        match (value, expected) {
+            (DataType::Number(a), DataType::Number(b)) => assert!((a.to_f64() - b.to_f64()).abs() < 1e-9, "{} != {}", a, b),
+            (DataType::Vector(a), DataType::Vector(b)) => assert!((a - b).amax() < 1e-9),
+            (DataType::Matrix(a), DataType::Matrix(b)) => assert!((a - b).amax() < 1e-9),
+            (value, expected) => panic!("Expected {}, got {}", expected, value),
+        }
+    }
+
+    #[test]
+    fn determinant() {
+        assert_close(call("det", &[matrix(&[&[1.0, 2.0], &[3.0, 4.0]])]).unwrap(), float(-2.0));
+        assert!(call("det", &[matrix(&[&[1.0, 2.0, 3.0]])]).is_err());
+    }
+
+    #[test]
+    fn inverse() {
+        let inverse = call("inv", &[matrix(&[&[4.0, 7.0], &[2.0, 6.0]])]).unwrap();
+        assert_close(inverse, matrix(&[&[0.6, -0.7], &[-0.2, 0.4]]));
+        assert_eq!(call("inv", &[matrix(&[&[1.0, 2.0], &[2.0, 4.0]])]), Err("Matrix is singular and has no inverse".to_string()));
+    }
+
+    #[test]
+    fn solves_linear_systems() {
+        let a = matrix(&[&[2.0, 1.0], &[1.0, 3.0]]);
+        assert_close(call("solve", &[a.clone(), vector(&[3.0, 5.0])]).unwrap(), vector(&[0.8, 1.4]));
+        assert!(call("solve", &[a, vector(&[1.0, 2.0, 3.0])]).unwrap_err().starts_with("Dimension mismatch"));
+    }
+
+    #[test]
+    fn shapes_must_match() {
+        let error = binary_op(vector(&[1.0, 2.0]), &Operator::Add, vector(&[1.0, 2.0, 3.0])).unwrap_err();
+        assert_eq!(error, "Dimension mismatch: 2x1 and 3x1");
+        let error = binary_op(matrix(&[&[1.0, 2.0]]), &Operator::Mul, matrix(&[&[1.0, 2.0]])).unwrap_err();
+        assert_eq!(error, "Cannot multiply a 1x2 matrix by a 1x2 matrix");
+    }
+
+    #[test]
+    fn identity_matrices_have_a_size_limit() {
+        assert_close(call("identity", &[DataType::Number(Number::Int(2))]).unwrap(), matrix(&[&[1.0, 0.0], &[0.0, 1.0]]));
+        assert!(call("identity", &[DataType::Number(Number::Int(100_000_000))]).is_err());
+    }
+}
@@ -1,21 +1,42 @@
-use crate::vm::{VM, symbol::DataType};
-use crate::ast::parser::{ASTNode, Operator, AssignmentKind};
-use crate::solve;
+use num_complex::Complex64;
+
+use crate::vm::{VM, settings, symbol::{DataType, Scope}, number::Number};
+use crate::ast::parser::{ASTNode, Operator, AssignmentKind, StringPart};
+use crate::units::Unit;
+
+mod builtins;
+mod calculus;
+mod format;
+mod linalg;
+mod ode;
+mod plot;
+mod quantity;
+mod symbolic;
+mod systems;
+mod table;
+mod uncertainty;
+
+// How many of the numbered results 'ans1', 'ans2', ... the REPL keeps, older ones are removed
+const ANSWERS_KEPT: usize = 100;
 
 pub struct Interpreter {
     vm: VM,
     flags: InterpreterFlags,
+    answers: usize, // The number of results stored by 'evaluate', the last one is 'ans<answers>'
+    output: Option<Vec<String>>, // The lines of 'out', when they're kept instead of printed
 }
 
 struct InterpreterFlags {
     pub break_flag: bool,
     pub continue_flag: bool,
+    pub strict: bool, // Conditions must be bools, 'if 5 { }' is a type error
 }
 impl InterpreterFlags {
     pub fn new() -> InterpreterFlags {
         InterpreterFlags {
             break_flag: false,
             continue_flag: false,
+            strict: false,
         }
     }
 }
@@ -25,12 +46,77 @@ impl Interpreter {
         Interpreter {
             vm: VM::new(),
             flags: InterpreterFlags::new(),
+            answers: 0,
+            output: None,
+        }
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.flags.strict = strict;
+    }
+
+    /// Keeps the lines written by 'out' instead of printing them, see `take_output`
+    pub fn capture_output(&mut self) {
+        self.output = Some(vec![]);
+    }
+
+    /// The lines written since the last call, if the output is captured
+    pub fn take_output(&mut self) -> Vec<String> {
+        self.output.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // Prints a line of output, or keeps it if the output is captured
+    fn write_line(&mut self, line: String) {
+        match &mut self.output {
+            Some(output) => output.push(line),
+            None => println!("{}", line),
+        }
+    }
+
+    /// The names of the built-in functions and constants
+    pub fn builtin_names() -> Vec<&'static str> {
+        [builtins::FUNCTIONS, linalg::FUNCTIONS, symbolic::FUNCTIONS, calculus::FUNCTIONS, plot::FUNCTIONS, table::FUNCTIONS, builtins::CONSTANTS].concat()
+    }
+
+    /// The declared variables and functions with their values and whether they are mutable
+    pub fn symbols(&self) -> Vec<(String, DataType, bool)> {
+        self.vm.list_symbols()
+    }
+
+    // Evaluates a value used as a condition. In strict mode only bools are allowed.
+    fn condition(&self, value: DataType) -> Result<bool, String> {
+        match value {
+            DataType::Bool(b) => Ok(b),
+            other if self.flags.strict => Err(format!("Expected a bool in condition, got {} {}", other.type_name(), other)),
+            other => Ok(self.vm.truthy_check(other)),
         }
     }
 
     fn compute_expr(&mut self, expr: ASTNode) -> Result<DataType, String> {
         match expr {
-            ASTNode::Number(value) => Ok(DataType::Number(value)),
+            ASTNode::Number(value) => Ok(DataType::Number(Number::from_bigint(value))),
+            ASTNode::Float(value) => Ok(DataType::Number(Number::Float(value))),
+            ASTNode::Imaginary(value) => Ok(DataType::Complex(Complex64::new(0.0, value))),
+            ASTNode::Bool(value) => Ok(DataType::Bool(value)),
+            ASTNode::Quantity { value, unit } => {
+                // A declared variable takes precedence over a unit with the same name: after 'var m = 3', '2 m' is 2*m
+                if let Some(variable) = self.unit_variable(&unit) {
+                    let value = self.compute_expr(*value)?;
+                    return self.apply_operator(Operator::Mul, vec![value, variable]);
+                }
+                let value = self.compute_expr(*value)?.to_f64().unwrap();
+                let unit = Unit::parse(&unit).ok_or(format!("Unknown unit {}", unit))?;
+                Ok(quantity::literal(value, unit))
+            },
+            ASTNode::Convert { expr, unit } => {
+                let value = self.compute_expr(*expr)?;
+                quantity::convert(value, &unit)
+            },
+            ASTNode::Interval { lower, upper } => {
+                let lower = self.compute_expr(*lower)?;
+                let upper = self.compute_expr(*upper)?;
+                uncertainty::interval(lower, upper)
+            },
             ASTNode::Identifier(name) => {
                 let result = self.vm.get_variable(&name);
                 match result {
@@ -38,136 +124,189 @@ impl Interpreter {
                         Some(value) => Ok(value),
                         None => Err(format!("Variable {:?} not found", name)),
                     },
-                    Err(e) => Err(e),       
+                    Err(e) => builtins::constant(&name).ok_or(e),
                 }
             },
+            ASTNode::List(items) => {
+                let mut values = vec![];
+                for item in items {
+                    values.push(self.compute_expr(item)?);
+                }
+                linalg::from_list(values)
+            },
+            ASTNode::String(parts) => {
+                let mut string = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => string.push_str(&text),
+                        StringPart::Interpolation { expr, format } => {
+                            let value = self.compute_expr(*expr)?;
+                            string.push_str(&format::format_value(&value, format.as_deref())?);
+                        },
+                    }
+                }
+                Ok(DataType::String(string))
+            },
             ASTNode::BinaryOp { left, op, right } => {
-                let left_result = self.compute_expr(*left);
-                let right_result = self.compute_expr(*right);
+                let left = self.compute_expr(*left)?;
+                let right = self.compute_expr(*right)?;
+                binary_op(left, op, right)
+            },
+            ASTNode::UnaryOp { op, expr } => {
+                let expr = self.compute_expr(*expr)?;
+                self.unary_op(op, expr)
+            },
+            ASTNode::Lambda { params, body } => Ok(DataType::Function(params, body, self.vm.capture_scope())),
+            ASTNode::NamedArgument { name, .. } => Err(format!("Named arguments like '{}: ...' can only be used to call a math system", name)),
+            ASTNode::FunctionCall { name, args } => {
+                if let Ok(Some(DataType::System(vars, equations))) = self.vm.get_variable(&name) {
+                    return self.call_system(&name, &vars, &equations, &args);
+                }
 
-                let left = match left_result {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };
-                let right = match right_result {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };
-                
-                // Only add numbers
-                if !left.is_number() || !right.is_number() {
-                    return Err(format!("Expected numbers, got {:?} and {:?}", left, right));
+                // Symbolic, calculus, plot and table built-ins get their arguments unevaluated
+                if self.vm.get_function(&name).is_err() {
+                    if let Some(result) = self.call_symbolic(&name, &args) {
+                        return result;
+                    }
+                    if let Some(result) = self.call_calculus(&name, &args) {
+                        return result;
+                    }
+                    if let Some(result) = self.call_plot(&name, &args) {
+                        return result;
+                    }
+                    if let Some(result) = self.call_table(&name, &args) {
+                        return result;
+                    }
                 }
 
-                let result = match op {
-                    Operator::Add => left + right,
-                    Operator::Sub => left - right,
-                    Operator::Mul => left * right,
-                    Operator::Div => left / right,
-                    Operator::Mod => left % right,
+                let mut arg_values = vec![];
+                for arg in args {
+                    arg_values.push(self.compute_expr(arg)?);
+                }
+                self.call(&name, arg_values)
+            },
+            _ => panic!("Expression {:?} not implemented yet", expr),
+        }
+    }
+
+    fn unary_op(&self, op: Operator, value: DataType) -> Result<DataType, String> {
+        match op {
+            // Like '&' and '|', '~' is bitwise on integers and logical on everything else
+            Operator::Not => match value {
+                DataType::Number(n) if n.is_integer() => Ok(DataType::Number(!n)),
+                value => Ok(DataType::Bool(!self.condition(value)?)),
+            },
+            Operator::Neg if value.is_numeric() || value.is_linear_algebra() || value.is_uncertain() || matches!(value, DataType::Quantity(..)) => Ok(-value),
+            Operator::Neg => Err(format!("Cannot negate {}", value)),
+            Operator::Factorial => value.factorial(),
+            _ => panic!("Unexpected operator"),
+        }
+    }
 
-                    Operator::And => left & right,
-                    Operator::Or => left | right,
+    /// Applies an operator to values that are already computed: two for a binary operator, one for
+    /// a negation, '!' or '~'. Used by the RPN mode of the REPL.
+    pub fn apply_operator(&self, op: Operator, mut operands: Vec<DataType>) -> Result<DataType, String> {
+        match (operands.len(), &op) {
+            (2, _) => {
+                let right = operands.pop().unwrap();
+                binary_op(operands.pop().unwrap(), op, right)
+            },
+            (1, Operator::Neg | Operator::Factorial | Operator::Not) => self.unary_op(op, operands.pop().unwrap()),
+            (n, op) => Err(format!("The operator {} can't be applied to {} values", op, n)),
+        }
+    }
 
-                    Operator::Eq => if left == right { DataType::Number(1) } else { DataType::Number(0) },
-                    Operator::Ne => if left != right { DataType::Number(1) } else { DataType::Number(0) },
-                    Operator::Lt => if left < right { DataType::Number(1) } else { DataType::Number(0) },
-                    Operator::Gt => if left > right { DataType::Number(1) } else { DataType::Number(0) },
-                    Operator::Le => if left <= right { DataType::Number(1) } else { DataType::Number(0) },
-                    Operator::Ge => if left >= right { DataType::Number(1) } else { DataType::Number(0) },
+    /// The variable named like a unit, if it holds a value that a number can be multiplied with.
+    /// Functions, math systems and other values don't hide the unit: after 'math ohm { ... }', '5 ohm' is still 5 ohms.
+    pub(super) fn unit_variable(&self, unit: &str) -> Option<DataType> {
+        match self.vm.get_variable(unit) {
+            Ok(Some(value @ (DataType::Number(_) | DataType::Quantity(..) | DataType::Uncertain(_) | DataType::Interval(_)))) => Some(value),
+            _ => None,
+        }
+    }
 
-                    _ => panic!("Unexpected operator"),
-                };
+    /// The value of an expression, without storing it in 'ans' like `evaluate`
+    pub fn value_of(&mut self, expr: ASTNode) -> Result<DataType, String> {
+        self.compute_expr(expr)
+    }
 
-                Ok(result)
-            },
-            ASTNode::UnaryOp { op, expr } => {
-                let expr_result = self.compute_expr(*expr);
-                let expr = match expr_result {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };  
+    /// The number of arguments of a user function or a built-in that takes values, None if there is no such function
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        match self.vm.get_variable(name) {
+            Ok(Some(DataType::Function(params, ..))) => Some(params.len()),
+            // A variable hides the built-in with its name
+            Ok(_) => None,
+            Err(_) => builtins::arity(name),
+        }
+    }
 
-                match op {
-                    Operator::Not => if self.vm.truthy_check(expr) { Ok(DataType::Number(0)) } else { Ok(DataType::Number(1)) },
-                    Operator::Neg => Ok(-expr),
-                    _ => panic!("Unexpected operator"),
-                }
-            },
-            ASTNode::FunctionCall { name, args } => {
-                let mut arg_indices = vec![];
-                for (i, arg) in args.iter().enumerate() {
-                    let arg_value = self.compute_expr(arg.clone());
-                    match arg_value {
-                        Ok(value) => {
-                            match self.vm.get_or_add_to_memory(value) {
-                                Ok(_) => arg_indices.push(i),
-                                Err(e) => return Err(e),
-                            }
-                        }
-                        Err(e) => return Err(e),
-                    }
-                }
-                
-                let function = self.vm.get_function(&name);
-                let (params, body, scope) = match function {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };
+    /// Calls a user function, or else a built-in, with computed arguments
+    pub fn call(&mut self, name: &str, arg_values: Vec<DataType>) -> Result<DataType, String> {
+        let (params, body, scope) = match self.vm.get_function(name) {
+            Ok(function) => function,
+            // f' is the derivative of the user function f
+            Err(_) if name.ends_with('\'') => return self.call_derivative(name, arg_values),
+            // Fall back to the built-in functions if there's no user-defined function with this name
+            Err(e) => return builtins::call(name, &arg_values).unwrap_or(Err(e)),
+        };
+        if params.len() != arg_values.len() {
+            return Err(format!("{}() takes {} arguments, got {}", name, params.len(), arg_values.len()));
+        }
 
-                let old_scopes = self.vm.scopes.clone();
-                let old_pc = self.vm.pc;
-                self.vm.scopes = vec![scope];
-                self.vm.pc = 0;
+        self.call_function(params, body, scope, arg_values)
+    }
 
-                for (i, param) in params.iter().enumerate() {
-                    let value = self.vm.get_register_value(i);
-                    match value {
-                        Ok(value) => {
-                            match self.vm.declare_variable(param.clone(), value, false) {
-                                Ok(_) => {},
-                                Err(e) => return Err(e),
-                            }
-                        },
-                        Err(e) => return Err(e),
-                    }
-                }
+    fn call_function(&mut self, params: Vec<String>, body: Vec<ASTNode>, scope: Scope, arg_values: Vec<DataType>) -> Result<DataType, String> {
+        let old_scopes = self.vm.scopes.clone();
+        let old_pc = self.vm.pc;
+        // The parameters live in their own scope, so they can shadow variables the function captured
+        self.vm.scopes = vec![scope, Scope::new(None)];
+        self.vm.pc = 0;
 
-                // interpret the function body
-                let result = self.interpret(body.clone());
-                self.vm.scopes = old_scopes;
-                self.vm.pc = old_pc;
+        let mut result = Ok(None);
+        for (param, value) in params.iter().zip(arg_values) {
+            if let Err(e) = self.vm.declare_variable(param.clone(), value, false) {
+                result = Err(e);
+                break;
+            }
+        }
 
-                match result {
-                    Ok(Some(value)) => Ok(value[0].clone()),
-                    Ok(None) => Ok(DataType::Null()),
-                    Err(e) => Err(e),
+        // interpret the function body
+        if result.is_ok() {
+            result = self.interpret(body);
+        }
+        self.vm.scopes = old_scopes;
+        self.vm.pc = old_pc;
+
+        match result {
+            Ok(Some(value)) => Ok(value[0].clone()),
+            Ok(None) => Ok(DataType::Null()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Calls a function value, e.g. a lambda passed as an argument
+    fn call_value(&mut self, function: &DataType, arg_values: Vec<DataType>) -> Result<DataType, String> {
+        match function {
+            DataType::Function(params, body, scope) => {
+                if params.len() != arg_values.len() {
+                    return Err(format!("The function takes {} arguments, got {}", params.len(), arg_values.len()));
                 }
+                self.call_function(params.clone(), body.clone(), scope.clone(), arg_values)
             },
-            _ => panic!("Expression {:?} not implemented yet", expr),
+            other => Err(format!("Expected a function, got {} {}", other.type_name(), other)),
         }
     }
 
     fn match_node(&mut self, node: ASTNode) -> Result<Option<Vec<DataType>>, String> {
         match node {
             ASTNode::VariableDeclaration { mutable, name, value } => {
-                let value = self.compute_expr(*value);
-                match value {
-                    Ok(value) => {
-                        match self.vm.declare_variable(name, value, mutable) {
-                            Ok(_) => return Ok(None),
-                            Err(e) => return Err(e),
-                        }
-                    },
-                    Err(e) => return Err(e),
-                }
+                let value = self.compute_expr(*value)?;
+                self.vm.declare_variable(name, value, mutable)?;
+                Ok(None)
             },
             ASTNode::Assignment { name, kind, value } => {
-                let value_result = self.compute_expr(*value);
-                let value = match value_result {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };
+                let value = self.compute_expr(*value)?;
                 let current_value = self.vm.get_variable(&name);
                 let current_value = match current_value {
                     Ok(value) => match value {
@@ -178,11 +317,11 @@ impl Interpreter {
                 };
                 let modified_value = match kind {
                     AssignmentKind::Assign => value,
-                    AssignmentKind::Add => current_value + value,
-                    AssignmentKind::Sub => current_value - value,
-                    AssignmentKind::Mul => current_value * value,
-                    AssignmentKind::Div => current_value / value,
-                    AssignmentKind::Mod => current_value % value,
+                    AssignmentKind::Add => binary_op(current_value, Operator::Add, value)?,
+                    AssignmentKind::Sub => binary_op(current_value, Operator::Sub, value)?,
+                    AssignmentKind::Mul => binary_op(current_value, Operator::Mul, value)?,
+                    AssignmentKind::Div => binary_op(current_value, Operator::Div, value)?,
+                    AssignmentKind::Mod => binary_op(current_value, Operator::Mod, value)?,
                 };
                 let address = self.vm.get_or_add_to_memory(modified_value);
                 match address {
@@ -212,17 +351,17 @@ impl Interpreter {
             ASTNode::IfStatement { condition, body, else_body, else_ifs } => {
                 let condition_value = self.compute_expr(*condition);
                 let is_truthy = match condition_value {
-                    Ok(value) => self.vm.truthy_check(value),
+                    Ok(value) => self.condition(value)?,
                     Err(e) => return Err(e),
                 };
 
                 if is_truthy {
-                    return self.interpret(body);
+                    self.interpret(body)
                 } else {
                     for (condition, body) in else_ifs {
                         let condition_value = self.compute_expr(*condition);
                         let is_truthy = match condition_value {
-                            Ok(value) => self.vm.truthy_check(value),
+                            Ok(value) => self.condition(value)?,
                             Err(e) => return Err(e),
                         };
 
@@ -231,18 +370,14 @@ impl Interpreter {
                         }
                     }
 
-                    return self.interpret(else_body);
+                    self.interpret(else_body)
                 }
             },
             ASTNode::WhileStatement { condition, body } => {
                 let mut output = None;
                 loop {
-                    let condition_value_result = self.compute_expr(*condition.clone());
-                    let condition_value = match condition_value_result {
-                        Ok(value) => value,
-                        Err(e) => return Err(e),
-                    };
-                    if !self.vm.truthy_check(condition_value) {
+                    let condition_value = self.compute_expr(*condition.clone())?;
+                    if !self.condition(condition_value)? {
                         break;
                     }
                     let result = self.interpret(body.clone());
@@ -260,11 +395,7 @@ impl Interpreter {
                     }
                 };
 
-                if let Some(value) = output {
-                    return Ok(Some(value));
-                } else {
-                    return Ok(None);
-                }
+                Ok(output)
             },
             ASTNode::Break {  } => {
                 self.flags.break_flag = true;
@@ -275,66 +406,28 @@ impl Interpreter {
                 Ok(None)
             },
 
-            ASTNode::Output { expr } => {
-                let expr_value = self.compute_expr(*expr);
+            ASTNode::Output { expr, format } => {
+                let value = self.compute_expr(*expr)?;
+                let line = format::format_value(&value, format.as_deref())?;
+                self.write_line(line);
+                Ok(None)
+            },
 
-                match expr_value {
-                    Ok(value) => {
-                        println!("{}", value);
-                        Ok(None)
-                    },
-                    Err(e) => Err(e),
-                }
+            ASTNode::Setting { name, value } => {
+                settings::set(&name, &value)?;
+                Ok(None)
+            },
+
+            ASTNode::Expression(expr) => {
+                self.compute_expr(*expr)?;
+                Ok(None)
+            },
+
+            ASTNode::MathBody { name, unknowns, body } => {
+                self.math_block(&name, unknowns.as_deref(), &body).map_err(|e| format!("In math block '{}': {}", name, e))?;
+                Ok(None)
             },
 
-            ASTNode::MathBody { name, body } => {
-                panic!("Unsupported feature - Math expressions not implemented yet");
-                // What we need to do is:
-                // 1. There are multiple math expressions in the Body. They all define some sort of relationship between variables.
-                // 2. Some variables are declared and have a value. Do not touch them.
-                // 3. The other variables that are used in the expressions are not declared.
-                // 4. We need to solve for the undeclared variables and declare them with the correct value.
-
-                // Steps:
-                // 1. Get all the variables that are used in the expressions.
-                // 2. Get the variables that are declared and have a value.
-                // 3. Solve for the undeclared variables.
-                // 4. Declare the undeclared variables with the correct value.
-                
-                println!("Solving math expression: {:?}", name);
-                let mut vars = vec![];
-                for node in &body {
-                    let node_vars = solve::find_vars(&node);
-                    // vars.extend(node_vars);
-                    // only append the ones that are not already in the vars vector
-                    for var in node_vars {
-                        if !vars.contains(&var) {
-                            vars.push(var);
-                        }
-                    }
-                }
-                println!("Variables: {:?}", vars);
-                let known_values = solve::get_known_values(&vars, &self.vm);
-
-                // now, we need to formulate the equations
-                // for now, as a test, just use the first equation only
-                // let equation = &body[0];
-                // let (mut coefficients, mut constant) = solve::formulate_equation(equation);
-                // println!("Equation: {:?} = {:?}", coefficients, constant);
-                // // now, we need to solve the equation
-                // let solution = solve::solve_equation(&mut coefficients, &mut constant);
-                // println!("Solution: {:?}", solution);
-
-                // the solution applies to the variables in the rearranged equation
-                // so, we need to map the solution to the original variables
-                // for example for x + 1 = 2 * x, the solution is x = 1,
-                let (coefficients,  constants) = solve::formulate_system(body, &known_values);
-                println!("System: {:?} = {:?}", coefficients, constants);
-                let solution = solve::solve_system(coefficients, constants);
-                println!("Solution: {:?}", solution);
-                return Ok(None);
-            }
-            
             _ => {
                 panic!("Invalid node: {:?}", node);
             }
@@ -362,4 +455,229 @@ impl Interpreter {
         
         Ok(None)
     }
-}
\ No newline at end of file
+
+    /// Runs top level statements like `interpret`, for the REPL. The values of expressions are returned
+    /// with their names: each one is stored as 'ans' and as 'ans1', 'ans2', ... in the order they were computed.
+    /// Only the last `ANSWERS_KEPT` of the numbered results stay defined.
+    pub fn evaluate(&mut self, ast: Vec<ASTNode>) -> Result<Vec<(String, DataType)>, String> {
+        let mut results = vec![];
+        for node in ast {
+            let ASTNode::Expression(expr) = node else {
+                if self.interpret(vec![node])?.is_some() {
+                    break; // A 'return' at the top level stops the input
+                }
+                continue;
+            };
+            let value = self.compute_expr(*expr)?;
+            // Values that are only there for their effect, like the result of a function without return, aren't stored
+            if matches!(value, DataType::Null()) {
+                continue;
+            }
+            self.answers += 1;
+            let name = format!("ans{}", self.answers);
+            self.vm.replace_global(name.clone(), value.clone(), false)?;
+            self.vm.replace_global("ans".to_string(), value.clone(), false)?;
+            // Only the recent results are kept, so a long session doesn't fill the memory
+            if self.answers > ANSWERS_KEPT {
+                self.vm.remove_global(&format!("ans{}", self.answers - ANSWERS_KEPT));
+            }
+            results.push((name, value));
+        }
+        Ok(results)
+    }
+}
+
+fn binary_op(left: DataType, op: Operator, right: DataType) -> Result<DataType, String> {
+    if matches!(op, Operator::PlusMinus) {
+        return uncertainty::plus_minus(left, right);
+    }
+    if matches!(left, DataType::Bool(_)) || matches!(right, DataType::Bool(_)) {
+        return bool_op(left, op, right);
+    }
+    if matches!(left, DataType::String(_)) || matches!(right, DataType::String(_)) {
+        return string_op(left, op, right);
+    }
+    if matches!(left, DataType::Expr(_)) || matches!(right, DataType::Expr(_)) {
+        return match op {
+            Operator::Eq | Operator::Ne => compare(&left, op, &right),
+            _ => Err(format!("Unsupported operation {:?} on a symbolic expression, use eval() to evaluate it first", op)),
+        };
+    }
+
+    if matches!(left, DataType::Quantity(..)) || matches!(right, DataType::Quantity(..)) {
+        return quantity::binary_op(left, &op, right);
+    }
+
+    if matches!(left, DataType::Vector(_) | DataType::Matrix(_) | DataType::List(_))
+        || matches!(right, DataType::Vector(_) | DataType::Matrix(_) | DataType::List(_)) {
+        return linalg::binary_op(left, &op, right);
+    }
+    
+    if left.is_uncertain() || right.is_uncertain() {
+        uncertainty::check_operation(&left, &op, &right)?;
+    } else if !left.is_numeric() || !right.is_numeric() {
+        // Only add numbers
+        return Err(format!("Expected numbers, got {} and {}", left.type_name(), right.type_name()));
+    }
+
+    match op {
+        Operator::And | Operator::Or if !is_integer(&left) || !is_integer(&right) => {
+            return Err(format!("Expected integers, got {} and {}", left, right));
+        },
+        Operator::Mod if !left.is_number() || !right.is_number() => {
+            return Err(format!("Expected real numbers, got {} and {}", left, right));
+        },
+        Operator::Div | Operator::Mod if right == DataType::Number(0.into()) => {
+            return Err("Division by zero".to_string());
+        },
+        _ => {},
+    }
+
+    let result = match op {
+        Operator::Add => left + right,
+        Operator::Sub => left - right,
+        Operator::Mul => left * right,
+        Operator::Div => left / right,
+        Operator::Mod => left % right,
+        Operator::Pow => left.pow(right)?,
+
+        Operator::And => left & right,
+        Operator::Or => left | right,
+
+        Operator::Eq | Operator::Ne | Operator::Lt | Operator::Gt | Operator::Le | Operator::Ge => compare(&left, op, &right)?,
+
+        _ => panic!("Unexpected operator"),
+    };
+
+    Ok(result)
+}
+
+// '&' and '|' are logical operators on bools. Bools are not numbers, so 'true + 1' is an error.
+fn bool_op(left: DataType, op: Operator, right: DataType) -> Result<DataType, String> {
+    match (op, left, right) {
+        (Operator::And, DataType::Bool(a), DataType::Bool(b)) => Ok(DataType::Bool(a && b)),
+        (Operator::Or, DataType::Bool(a), DataType::Bool(b)) => Ok(DataType::Bool(a || b)),
+        (op @ (Operator::Eq | Operator::Ne), a, b) => compare(&a, op, &b),
+        (op, a, b) => Err(format!("Unsupported operation {:?} between {} and {}", op, a.type_name(), b.type_name())),
+    }
+}
+
+// Strings can be concatenated with '+' and compared with each other
+fn string_op(left: DataType, op: Operator, right: DataType) -> Result<DataType, String> {
+    match (op, left, right) {
+        (Operator::Add, DataType::String(a), DataType::String(b)) => Ok(DataType::String(a + &b)),
+        (Operator::Add, a, b) => {
+            Err(format!("Cannot add {} and {}, use interpolation instead, e.g. \"{{x}}\"", a.type_name(), b.type_name()))
+        },
+        (op @ (Operator::Eq | Operator::Ne | Operator::Lt | Operator::Gt | Operator::Le | Operator::Ge), a, b) => compare(&a, op, &b),
+        (op, a, b) => Err(format!("Unsupported operation {:?} between {} and {}", op, a.type_name(), b.type_name())),
+    }
+}
+
+fn compare(left: &DataType, op: Operator, right: &DataType) -> Result<DataType, String> {
+    let result = match op {
+        Operator::Eq => left == right,
+        Operator::Ne => left != right,
+        _ => {
+            // Complex numbers have no ordering
+            let ordering = match left.partial_cmp(right) {
+                Some(ordering) => ordering,
+                None => return Err(format!("Cannot compare {} and {}", left, right)),
+            };
+            match op {
+                Operator::Lt => ordering.is_lt(),
+                Operator::Gt => ordering.is_gt(),
+                Operator::Le => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }
+        },
+    };
+    Ok(DataType::Bool(result))
+}
+
+fn is_integer(value: &DataType) -> bool {
+    matches!(value, DataType::Number(n) if n.is_integer())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(interpreter: &mut Interpreter, code: &str) -> Result<Vec<(String, DataType)>, String> {
+        interpreter.evaluate(crate::ast::parse(code)?)
+    }
+
+    #[test]
+    fn only_recent_answers_are_kept() {
+        let mut interpreter = Interpreter::new();
+        for i in 0..ANSWERS_KEPT + 50 {
+            evaluate(&mut interpreter, &format!("{} + 1i", i)).unwrap();
+        }
+        let last = ANSWERS_KEPT + 50;
+        assert!(interpreter.vm.get_variable(&format!("ans{}", last)).is_ok());
+        assert!(interpreter.vm.get_variable(&format!("ans{}", last - ANSWERS_KEPT + 1)).is_ok());
+        assert!(interpreter.vm.get_variable(&format!("ans{}", last - ANSWERS_KEPT)).is_err());
+    }
+
+    #[test]
+    fn interpolations_can_contain_strings() {
+        let mut interpreter = Interpreter::new();
+        let mut output = |code: &str| evaluate(&mut interpreter, code).map(|results| results[0].1.to_string());
+        assert_eq!(output(r#""{len("abc")}""#), Ok("3".to_string()));
+        assert_eq!(output(r#""{"a:b" + "c"}, {len("x{{y}}"):.1}""#), Ok("a:bc, 4.0".to_string()));
+        assert_eq!(output(r#""{len("\"")} quote""#), Ok("1 quote".to_string()));
+    }
+
+    #[test]
+    fn variables_take_precedence_over_units() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(evaluate(&mut interpreter, "2 m").unwrap()[0].1.to_string(), "2 m");
+        evaluate(&mut interpreter, "var m = 3").unwrap();
+        assert_eq!(evaluate(&mut interpreter, "2 m").unwrap()[0].1, DataType::Number(Number::Int(6)));
+        assert_eq!(evaluate(&mut interpreter, "2 m/s").unwrap()[0].1.to_string(), "2 m/s");
+        assert_eq!(evaluate(&mut interpreter, "2 km").unwrap()[0].1.to_string(), "2 km");
+    }
+
+    #[test]
+    fn functions_dont_take_precedence_over_units() {
+        let mut interpreter = Interpreter::new();
+        evaluate(&mut interpreter, "fun s(x) { return x }").unwrap();
+        assert_eq!(evaluate(&mut interpreter, "3 s").unwrap()[0].1.to_string(), "3 s");
+    }
+
+    #[test]
+    fn arithmetic_on_functions_is_an_error() {
+        let mut interpreter = Interpreter::new();
+        evaluate(&mut interpreter, "fun f(x) { return x }").unwrap();
+        assert_eq!(evaluate(&mut interpreter, "f + 1"), Err("Expected numbers, got function and number".to_string()));
+        for code in ["f == f", "f^2", "f!", "f < 1"] {
+            assert!(evaluate(&mut interpreter, code).is_err(), "{} should be an error", code);
+        }
+    }
+
+    #[test]
+    fn not_is_bitwise_on_integers() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(evaluate(&mut interpreter, "~5").unwrap()[0].1, DataType::Number(Number::Int(-6)));
+        assert_eq!(evaluate(&mut interpreter, "~true").unwrap()[0].1, DataType::Bool(false));
+        assert_eq!(evaluate(&mut interpreter, "~\"\"").unwrap()[0].1, DataType::Bool(true));
+    }
+
+    #[test]
+    fn strict_conditions_must_be_bools() {
+        let mut interpreter = Interpreter::new();
+        assert!(evaluate(&mut interpreter, "if 5 { out 1 }").is_ok());
+        interpreter.set_strict(true);
+        assert_eq!(evaluate(&mut interpreter, "if 5 { out 1 }"), Err("Expected a bool in condition, got number 5".to_string()));
+        assert!(evaluate(&mut interpreter, "while 0 { }").is_err());
+        assert!(evaluate(&mut interpreter, "if 5 > 1 { out 1 }").is_ok());
+    }
+
+    #[test]
+    fn long_sessions_dont_run_out_of_memory() {
+        let mut interpreter = Interpreter::new();
+        for i in 0..1100 {
+            evaluate(&mut interpreter, &format!("{}.5", i)).unwrap();
+        }
+        assert_eq!(evaluate(&mut interpreter, "ans").unwrap()[0].1, DataType::Number(Number::Float(1099.5)));
+    }
+}
@@ -0,0 +1,172 @@
+use nalgebra::DVector;
+
+// Runge-Kutta solvers for initial value problems y' = f(t, y). The state is a vector, a single
+// equation is a vector with one component. Both solvers return every step as a (t, y) sample.
+
+pub type Derivative<'a> = dyn FnMut(f64, &DVector<f64>) -> Result<DVector<f64>, String> + 'a;
+pub type Samples = Vec<(f64, DVector<f64>)>;
+
+// Guards against solutions that would take forever, e.g. because the step is tiny
+const MAX_STEPS: usize = 1_000_000;
+
+// Error tolerances of the adaptive solver
+const RELATIVE_TOLERANCE: f64 = 1e-8;
+const ABSOLUTE_TOLERANCE: f64 = 1e-10;
+
+// Dormand-Prince 5(4) coefficients. The last stage is evaluated at the new point, so it can be
+// reused as the first stage of the next step.
+const C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+const A: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+    [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+    [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+// The difference between the 5th and the embedded 4th order weights, for the error estimate
+const E: [f64; 7] = [
+    35.0 / 384.0 - 5179.0 / 57600.0,
+    0.0,
+    500.0 / 1113.0 - 7571.0 / 16695.0,
+    125.0 / 192.0 - 393.0 / 640.0,
+    -2187.0 / 6784.0 + 92097.0 / 339200.0,
+    11.0 / 84.0 - 187.0 / 2100.0,
+    -1.0 / 40.0,
+];
+
+/// Classic 4th order Runge-Kutta with a fixed step. The last step is shortened to end exactly at `t1`.
+pub fn rk4(f: &mut Derivative, y0: DVector<f64>, t0: f64, t1: f64, step: f64) -> Result<Samples, String> {
+    if step.is_nan() || step <= 0.0 {
+        return Err(format!("The step must be positive, got {}", step));
+    }
+    let steps = ((t1 - t0).abs() / step).ceil();
+    if steps > MAX_STEPS as f64 {
+        return Err(format!("The step {} is too small, it would take {} steps", step, steps));
+    }
+
+    let h = step * (t1 - t0).signum();
+    let mut samples = vec![(t0, y0)];
+    for i in 1..=steps as usize {
+        let (t, y) = samples.last().unwrap();
+        let next_t = if i == steps as usize { t1 } else { t0 + i as f64 * h };
+        let h = next_t - t;
+        let k1 = f(*t, y)?;
+        let k2 = f(t + h / 2.0, &(y + &k1 * (h / 2.0)))?;
+        let k3 = f(t + h / 2.0, &(y + &k2 * (h / 2.0)))?;
+        let k4 = f(t + h, &(y + &k3 * h))?;
+        let next_y = y + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * (h / 6.0);
+        samples.push((next_t, next_y));
+    }
+    Ok(samples)
+}
+
+/// Adaptive Runge-Kutta 4(5) using the Dormand-Prince pair. The step size is chosen so the estimated
+/// error of each step stays below the tolerance.
+pub fn dormand_prince(f: &mut Derivative, y0: DVector<f64>, t0: f64, t1: f64) -> Result<Samples, String> {
+    let direction = (t1 - t0).signum();
+    let mut h = (t1 - t0) / 100.0;
+    let mut k_first = f(t0, &y0)?;
+    let mut samples = vec![(t0, y0)];
+    if t0 == t1 {
+        return Ok(samples);
+    }
+
+    for _ in 0..MAX_STEPS {
+        let (t, y) = samples.last().unwrap().clone();
+        if (t1 - t) * direction <= 0.0 {
+            return Ok(samples);
+        }
+        // Don't step past the end
+        if (t + h - t1) * direction > 0.0 {
+            h = t1 - t;
+        }
+
+        let mut k = vec![k_first.clone()];
+        for stage in 1..7 {
+            let mut y_stage = y.clone();
+            for (j, k_j) in k.iter().enumerate() {
+                y_stage += k_j * (h * A[stage][j]);
+            }
+            k.push(f(t + C[stage] * h, &y_stage)?);
+        }
+        let mut next_y = y.clone();
+        let mut error = DVector::zeros(y.len());
+        for (i, k_i) in k.iter().enumerate() {
+            if i < 6 {
+                next_y += k_i * (h * A[6][i]);
+            }
+            error += k_i * (h * E[i]);
+        }
+
+        // The largest error relative to the tolerance, accepting the step if it's at most 1
+        let error_norm = error.iter().zip(y.iter().zip(next_y.iter()))
+            .map(|(e, (a, b))| e.abs() / (ABSOLUTE_TOLERANCE + RELATIVE_TOLERANCE * a.abs().max(b.abs())))
+            .fold(0.0, f64::max);
+        if !error_norm.is_finite() {
+            return Err(format!("The solution is not finite at t = {}", t + h));
+        }
+        if error_norm <= 1.0 {
+            let next_t = if (t1 - (t + h)) * direction <= 0.0 { t1 } else { t + h };
+            k_first = k.pop().unwrap();
+            samples.push((next_t, next_y));
+        }
+
+        let factor = if error_norm == 0.0 { 5.0 } else { (0.9 * error_norm.powf(-0.2)).clamp(0.2, 5.0) };
+        h *= factor;
+        if h.abs() <= 1e-12 * t.abs().max(1.0) {
+            return Err(format!("The step size became too small at t = {}, the equation may be stiff or singular", t));
+        }
+    }
+    Err(format!("The solver didn't reach t = {} after {} steps", t1, MAX_STEPS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // y' = y with y(0) = 1, solved by e^t
+    fn exponential(_: f64, y: &DVector<f64>) -> Result<DVector<f64>, String> {
+        Ok(y.clone())
+    }
+
+    fn assert_exponential(samples: &Samples, tolerance: f64) {
+        for (t, y) in samples {
+            assert!((y[0] - t.exp()).abs() <= tolerance * t.exp(), "y({}) = {}, expected {}", t, y[0], t.exp());
+        }
+    }
+
+    #[test]
+    fn rk4_solves_exponential_growth() {
+        let samples = rk4(&mut exponential, DVector::from_element(1, 1.0), 0.0, 2.0, 0.01).unwrap();
+        assert_eq!(samples.len(), 201);
+        assert_eq!(samples.last().unwrap().0, 2.0);
+        assert_exponential(&samples, 1e-8);
+    }
+
+    #[test]
+    fn rk4_solves_backwards() {
+        let samples = rk4(&mut exponential, DVector::from_element(1, 1.0), 0.0, -1.0, 0.3).unwrap();
+        assert_eq!(samples.last().unwrap().0, -1.0);
+        assert_exponential(&samples, 1e-4);
+        assert!(rk4(&mut exponential, DVector::from_element(1, 1.0), 0.0, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn dormand_prince_solves_exponential_growth() {
+        let samples = dormand_prince(&mut exponential, DVector::from_element(1, 1.0), 0.0, 5.0).unwrap();
+        assert_eq!(samples.last().unwrap().0, 5.0);
+        assert_exponential(&samples, 1e-7);
+    }
+
+    #[test]
+    fn dormand_prince_solves_systems() {
+        // y'' = -y as a system, with y(0) = 0 and y'(0) = 1, solved by sin(t)
+        let mut oscillator = |_: f64, y: &DVector<f64>| Ok(DVector::from_vec(vec![y[1], -y[0]]));
+        let samples = dormand_prince(&mut oscillator, DVector::from_vec(vec![0.0, 1.0]), 0.0, 10.0).unwrap();
+        for (t, y) in &samples {
+            assert!((y[0] - t.sin()).abs() < 1e-6 && (y[1] - t.cos()).abs() < 1e-6, "y({}) = {}", t, y);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::{self, IsTerminal};
+
+use super::Interpreter;
+use crate::ast::parser::ASTNode;
+use crate::plot::{braille, svg, Plot, Series};
+use crate::vm::symbol::DataType;
+
+// plot(f, g, ..., xmin, xmax) draws functions in the terminal. The functions are given like for
+// 'integrate': user functions, lambdas, built-ins like 'sin', or expressions followed by their
+// variable, as in 'plot(x^2, x^3, x, -1, 1)'. Two more numbers fix the y range, and a file name at
+// the end writes the plot to an SVG file instead.
+
+pub const FUNCTIONS: &[&str] = &["plot"];
+
+// The number of points each function is evaluated at
+const SAMPLES: usize = 400;
+
+// The size of the plot in characters. Captured output is shown in narrower panes, like the TUI's.
+const COLUMNS: usize = 60;
+const CAPTURED_COLUMNS: usize = 40;
+const ROWS: usize = 15;
+
+impl Interpreter {
+    /// Calls a plot built-in. Returns `None` if `name` isn't one of them.
+    pub(super) fn call_plot(&mut self, name: &str, args: &[ASTNode]) -> Option<Result<DataType, String>> {
+        match name {
+            "plot" => Some(self.plot(args)),
+            _ => None,
+        }
+    }
+
+    fn plot(&mut self, args: &[ASTNode]) -> Result<DataType, String> {
+        let mut args = args.to_vec();
+        let file = match args.last() {
+            Some(ASTNode::String(_)) => match self.compute_expr(args.pop().unwrap())? {
+                DataType::String(file) => Some(file),
+                other => return Err(format!("Expected a file name, got {}", other)),
+            },
+            _ => None,
+        };
+
+        // The functions come before the first bound: 2 bounds for x, or 4 with the y range
+        let bounds = match args.len() {
+            n if n >= 5 && args[n - 4..].iter().all(|arg| self.is_bound(arg)) => 4,
+            n if n >= 3 => 2,
+            _ => return Err("plot() takes one or more functions and the x range, e.g. plot(sin, 0, 2*pi)".to_string()),
+        };
+        let bounds_at = args.len() - bounds;
+        let mut values = vec![];
+        for arg in &args[bounds_at..] {
+            values.push(self.real_arg(arg)?);
+        }
+        let x_range = (values[0], values[1]);
+        let y_range = if bounds == 4 { Some((values[2], values[3])) } else { None };
+        for (axis, range) in [("x", Some(x_range)), ("y", y_range)] {
+            match range {
+                Some((low, high)) if low >= high || !low.is_finite() || !high.is_finite() => {
+                    return Err(format!("The {} range of plot() must be finite and increasing, got {} to {}", axis, low, high));
+                },
+                _ => {},
+            }
+        }
+
+        let functions = self.function_args(&args[..bounds_at])?;
+        let mut series = vec![];
+        for (name, function) in functions {
+            let mut points = vec![];
+            let mut first_error = None;
+            for i in 0..SAMPLES {
+                let x = x_range.0 + (x_range.1 - x_range.0) * i as f64 / (SAMPLES - 1) as f64;
+                // Points where the function fails, like sqrt(x) below 0, are left out
+                match self.call_real(&function, x) {
+                    Ok(y) if y.is_finite() => points.push((x, Some(y))),
+                    Ok(_) => points.push((x, None)),
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                        points.push((x, None));
+                    },
+                }
+            }
+            if let Some(e) = first_error.filter(|_| points.iter().all(|(_, y)| y.is_none())) {
+                return Err(format!("Can't plot {}: {}", name, e));
+            }
+            series.push(Series { name, points });
+        }
+
+        let plot = Plot::new(series, x_range, y_range);
+        match file {
+            Some(file) => {
+                svg::write(&plot, &file)?;
+                self.write_line(format!("Saved the plot to {}", file));
+            },
+            None => {
+                let captured = self.output.is_some();
+                let columns = if captured { CAPTURED_COLUMNS } else { COLUMNS };
+                let color = !captured && io::stdout().is_terminal();
+                for line in braille::render(&plot, columns, ROWS, color).lines() {
+                    self.write_line(line.to_string());
+                }
+            },
+        }
+        Ok(DataType::Null())
+    }
+
+    // An argument that can only be a bound, not a function
+    fn is_bound(&mut self, arg: &ASTNode) -> bool {
+        !self.is_variable(arg) && matches!(self.compute_expr(arg.clone()), Ok(DataType::Number(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plot(code: &str) -> Result<DataType, String> {
+        let mut interpreter = Interpreter::new();
+        interpreter.value_of(crate::ast::parse_expression(code)?)
+    }
+
+    #[test]
+    fn ranges_must_be_increasing() {
+        assert_eq!(plot("plot(sin, 1, 0)"), Err("The x range of plot() must be finite and increasing, got 1 to 0".to_string()));
+        assert_eq!(plot("plot(sin, 0, 1, 2, -2)"), Err("The y range of plot() must be finite and increasing, got 2 to -2".to_string()));
+        assert_eq!(plot("plot(sin, 0, 1, 1, 1)"), Err("The y range of plot() must be finite and increasing, got 1 to 1".to_string()));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::ast::parser::Operator;
+use crate::units::{self, Unit};
+use crate::vm::number::Number;
+use crate::vm::settings::{self, Angle};
+use crate::vm::symbol::DataType;
+
+// Arithmetic on quantities with units. Adding, subtracting and comparing requires both sides to have
+// the same dimensions, multiplying and dividing combines them. A result without dimensions, like
+// 3 m / 2 km, is a plain number again.
+
+/// A quantity, or a plain number if the unit has no dimensions
+pub fn quantity(value: f64, unit: Unit) -> DataType {
+    if unit.is_dimensionless() {
+        DataType::Number(Number::Float(value))
+    } else {
+        DataType::Quantity(value, unit)
+    }
+}
+
+/// The quantity written as `value unit`, e.g. 5 km. The only units without dimensions are the
+/// angles, which give plain numbers: radians, or degrees after 'set angle deg'.
+pub fn literal(value: f64, unit: Unit) -> DataType {
+    if unit.is_dimensionless() {
+        return DataType::Number(Number::Float(value * unit.scale / angle_scale()));
+    }
+    quantity(value * unit.scale, unit)
+}
+
+// The angle that a plain number stands for, in radians
+fn angle_scale() -> f64 {
+    match settings::get().angle {
+        Angle::Radians => 1.0,
+        Angle::Degrees => std::f64::consts::PI / 180.0,
+    }
+}
+
+// The value in SI units and the unit of a quantity or a real number
+fn split(value: &DataType) -> Result<(f64, Unit), String> {
+    match value {
+        DataType::Quantity(value, unit) => Ok((*value, unit.clone())),
+        DataType::Number(n) => Ok((n.to_f64(), Unit::si(units::DIMENSIONLESS))),
+        other => Err(format!("Units can only be used with real numbers, got {} {}", other.type_name(), other)),
+    }
+}
+
+fn check_same_dimensions(action: &str, left: &DataType, a: &Unit, right: &DataType, b: &Unit) -> Result<(), String> {
+    if a.dims != b.dims {
+        return Err(format!(
+            "Cannot {} {} and {}, their dimensions don't match ({} vs {})",
+            action, left, right, units::describe(&a.dims), units::describe(&b.dims),
+        ));
+    }
+    Ok(())
+}
+
+/// Applies a binary operator where at least one side is a quantity.
+pub fn binary_op(left: DataType, op: &Operator, right: DataType) -> Result<DataType, String> {
+    let (a, a_unit) = split(&left)?;
+    let (b, b_unit) = split(&right)?;
+    // Sums keep the unit of the side that has one, 'x + 2 km' is shown in the unit of x
+    let sum_unit = if matches!(left, DataType::Quantity(..)) { a_unit.clone() } else { b_unit.clone() };
+
+    match op {
+        Operator::Add | Operator::Sub => {
+            check_same_dimensions(if matches!(op, Operator::Add) { "add" } else { "subtract" }, &left, &a_unit, &right, &b_unit)?;
+            let value = if matches!(op, Operator::Add) { a + b } else { a - b };
+            Ok(DataType::Quantity(value, sum_unit))
+        },
+        Operator::Mod => {
+            check_same_dimensions("take the remainder of", &left, &a_unit, &right, &b_unit)?;
+            if b == 0.0 {
+                return Err("Division by zero".to_string());
+            }
+            Ok(DataType::Quantity(a % b, a_unit))
+        },
+        Operator::Mul | Operator::Div => {
+            if matches!(op, Operator::Div) && b == 0.0 {
+                return Err("Division by zero".to_string());
+            }
+            let (value, dims) = match op {
+                Operator::Mul => (a * b, units::add_dims(&a_unit.dims, &b_unit.dims)),
+                _ => (a / b, units::sub_dims(&a_unit.dims, &b_unit.dims)),
+            };
+            // Scaling by a number keeps the unit, 2 * 5 km is 10 km
+            let unit = match (&left, &right) {
+                (DataType::Quantity(..), DataType::Number(_)) => a_unit,
+                (DataType::Number(_), DataType::Quantity(..)) if matches!(op, Operator::Mul) => b_unit,
+                _ => Unit::si(dims),
+            };
+            Ok(quantity(value, unit))
+        },
+        Operator::Pow => pow(&left, a, &a_unit, &right, &b_unit),
+        Operator::Eq | Operator::Ne | Operator::Lt | Operator::Gt | Operator::Le | Operator::Ge => {
+            check_same_dimensions("compare", &left, &a_unit, &right, &b_unit)?;
+            let ordering = a.partial_cmp(&b).ok_or(format!("Cannot compare {} and {}", left, right))?;
+            Ok(DataType::Bool(match op {
+                Operator::Eq => ordering.is_eq(),
+                Operator::Ne => ordering.is_ne(),
+                Operator::Lt => ordering.is_lt(),
+                Operator::Gt => ordering.is_gt(),
+                Operator::Le => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }))
+        },
+        _ => Err(format!("Unsupported operation {:?} between {} and {}", op, left, right)),
+    }
+}
+
+// A quantity to a power. The exponent must be a number, and fractional exponents must give whole
+// powers of the units, sqrt(4 m^2) is 2 m but sqrt(2 m) has no unit.
+fn pow(left: &DataType, value: f64, unit: &Unit, right: &DataType, exponent_unit: &Unit) -> Result<DataType, String> {
+    if !exponent_unit.is_dimensionless() {
+        return Err(format!("Exponents can't have a unit, got {}", right));
+    }
+    let exponent = match right {
+        DataType::Number(n) => n.to_f64(),
+        _ => return Err(format!("The exponent of a quantity must be a dimensionless number, got {}", right)),
+    };
+    if exponent.fract() == 0.0 {
+        return Ok(quantity(value.powf(exponent), unit.powi(exponent as i32)));
+    }
+
+    let dims = unit.dims.map(|d| d as f64 * exponent);
+    if dims.iter().any(|d| d.fract() != 0.0) {
+        return Err(format!("Cannot raise {} to the power {}, the unit would have a fractional power", left, exponent));
+    }
+    Ok(quantity(value.powf(exponent), Unit::si(dims.map(|d| d as i32))))
+}
+
+/// Converts a value to another unit with the same dimensions. Angles are plain numbers like the
+/// literals `5 rad` and `90 deg`, so converting a number to 'rad' or 'deg' leaves it as it is.
+pub fn convert(value: DataType, unit: &str) -> Result<DataType, String> {
+    let unit = Unit::parse(unit).ok_or(format!("Unknown unit {}", unit))?;
+    let (amount, from) = match &value {
+        DataType::Number(_) if !unit.is_dimensionless() => {
+            return Err(format!("Cannot convert {} to {}, it has no unit", value, unit.name));
+        },
+        DataType::Number(_) => return Ok(value),
+        other => split(other)?,
+    };
+    if from.dims != unit.dims {
+        return Err(format!(
+            "Cannot convert {} to {}, their dimensions don't match ({} vs {})",
+            value, unit.name, units::describe(&from.dims), units::describe(&unit.dims),
+        ));
+    }
+    Ok(DataType::Quantity(amount, unit))
+}
+
+/// Built-in functions called with a quantity. Only the ones that make sense with units are supported.
+pub fn call(name: &str, args: &[DataType]) -> Result<DataType, String> {
+    match (name, args) {
+        ("abs", [value]) => {
+            let (amount, unit) = split(value)?;
+            Ok(DataType::Quantity(amount.abs(), unit))
+        },
+        ("sqrt", [value]) => {
+            let (amount, unit) = split(value)?;
+            pow(value, amount, &unit, &DataType::Number(Number::Float(0.5)), &Unit::si(units::DIMENSIONLESS))
+        },
+        (_, [value]) => Err(format!("{}() expects a number without unit, got {}", name, value)),
+        _ => Err(format!("{}() can't be called with quantities", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> DataType {
+        DataType::Number(Number::Float(n))
+    }
+
+    #[test]
+    fn converts_between_units_with_the_same_dimensions() {
+        let km = literal(5.0, Unit::parse("km").unwrap());
+        let m = convert(km, "m").unwrap();
+        assert_eq!(m.to_string(), "5000 m");
+        assert!(convert(number(5.0), "m").is_err());
+        assert!(convert(m, "s").is_err());
+    }
+
+    #[test]
+    fn converting_to_an_angle_unit_gives_a_number() {
+        assert_eq!(convert(number(5.0), "rad").unwrap(), number(5.0));
+        assert_eq!(literal(5.0, Unit::parse("rad").unwrap()), number(5.0));
+    }
+
+    #[test]
+    fn exponents_must_be_numbers() {
+        let length = literal(2.0, Unit::parse("m").unwrap());
+        let exponent = literal(1.0, Unit::parse("m").unwrap());
+        assert!(binary_op(length.clone(), &Operator::Pow, exponent).is_err());
+        assert_eq!(binary_op(length.clone(), &Operator::Pow, number(2.0)).unwrap().to_string(), "4 m^2");
+        assert_eq!(binary_op(length, &Operator::Pow, number(1.0)).unwrap().to_string(), "2 m");
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use super::Interpreter;
+use crate::ast::parser::{ASTNode, Operator};
+use crate::symbolic;
+use crate::vm::symbol::DataType;
+
+// Symbolic built-ins. Except for `eval`, these are special forms: their first argument is not evaluated
+// but manipulated as an expression.
+
+pub const FUNCTIONS: &[&str] = &["diff", "eval", "simplify", "expand", "factor"];
+
+// How deeply user functions are inlined into an expression before giving up, e.g. for recursive functions
+const MAX_INLINE_DEPTH: usize = 64;
+
+impl Interpreter {
+    /// Calls a symbolic built-in. Returns `None` if `name` isn't one of them.
+    pub(super) fn call_symbolic(&mut self, name: &str, args: &[ASTNode]) -> Option<Result<DataType, String>> {
+        if !FUNCTIONS.contains(&name) {
+            return None;
+        }
+
+        let result = match (name, args) {
+            ("diff", _) => self.diff(args),
+            ("simplify", [expr]) => self.resolve_symbolic(expr, &[], 0).and_then(symbolic::simplify),
+            ("expand", [expr]) => self.resolve_symbolic(expr, &[], 0).and_then(|expr| symbolic::expand(&expr)),
+            ("factor", [expr]) => self.resolve_symbolic(expr, &[], 0).and_then(|expr| symbolic::factor(&expr)),
+            ("eval", [arg]) => {
+                return Some(match self.compute_expr(arg.clone()) {
+                    Ok(DataType::Expr(expr)) => self.compute_expr(expr),
+                    other => other,
+                });
+            },
+            _ => Err(format!("{}() takes 1 argument, got {}", name, args.len())),
+        };
+
+        // Results that are just a number are returned as a number
+        Some(result.map(|expr| match symbolic::literal(&expr) {
+            Some(n) => DataType::Number(n),
+            None => DataType::Expr(expr),
+        }))
+    }
+
+    // diff(expr, x) or diff(expr, x, n) for the nth derivative
+    fn diff(&mut self, args: &[ASTNode]) -> Result<ASTNode, String> {
+        let (expr, var, order) = match args {
+            [expr, ASTNode::Identifier(var)] => (expr, var, 1),
+            [expr, ASTNode::Identifier(var), order] => {
+                let order = match self.compute_expr(order.clone())? {
+                    DataType::Number(n) if n.is_integer() => n.to_u32(),
+                    _ => None,
+                };
+                match order {
+                    Some(order) => (expr, var, order),
+                    None => return Err("The order of a derivative must be a non-negative integer".to_string()),
+                }
+            },
+            [_, other] | [_, other, _] => return Err(format!("Expected a variable to differentiate by, got {}", other)),
+            _ => return Err(format!("diff() takes 2 or 3 arguments, got {}", args.len())),
+        };
+
+        let mut result = self.resolve_symbolic(expr, std::slice::from_ref(var), 0)?;
+        for _ in 0..order {
+            result = symbolic::diff(&result, var)?;
+        }
+        Ok(result)
+    }
+
+    /// Calls the derivative of a user function, e.g. f'(2) or f''(2).
+    pub(super) fn call_derivative(&mut self, name: &str, arg_values: Vec<DataType>) -> Result<DataType, String> {
+        let base = name.trim_end_matches('\'');
+        let order = name.len() - base.len();
+        let (params, body, scope) = self.vm.get_function(base)?;
+
+        let derivative = self.differentiate_function(base, &params, &body, order, 0)?;
+        self.call_function(params, vec![ASTNode::Return { expr: Box::new(derivative) }], scope, arg_values)
+    }
+
+    fn differentiate_function(&mut self, name: &str, params: &[String], body: &[ASTNode], order: usize, depth: usize) -> Result<ASTNode, String> {
+        let param = match params {
+            [param] => param,
+            _ => return Err(format!("{}' needs a function of one variable, use diff() for partial derivatives", name)),
+        };
+
+        let mut result = self.resolve_symbolic(function_expr(name, body)?, params, depth + 1)?;
+        for _ in 0..order {
+            result = symbolic::diff(&result, param)?;
+        }
+        Ok(result)
+    }
+
+    // Prepares an expression for symbolic manipulation. Variables that hold symbolic expressions are
+    // replaced by those expressions and calls to user functions are inlined. Other variables are kept
+    // as they are, so they're looked up when the expression is evaluated. `bound` are the names that
+    // must not be replaced, e.g. the variable that is differentiated by.
+    pub(super) fn resolve_symbolic(&mut self, node: &ASTNode, bound: &[String], depth: usize) -> Result<ASTNode, String> {
+        if depth > MAX_INLINE_DEPTH {
+            return Err("Expression is nested too deeply, is a function recursive?".to_string());
+        }
+
+        match node {
+            ASTNode::Identifier(name) if !bound.contains(name) => match self.vm.get_variable(name) {
+                Ok(Some(DataType::Expr(expr))) => self.resolve_symbolic(&expr, bound, depth + 1),
+                _ => Ok(node.clone()),
+            },
+            ASTNode::BinaryOp { left, op, right } => Ok(symbolic::binary(
+                self.resolve_symbolic(left, bound, depth)?,
+                op.clone(),
+                self.resolve_symbolic(right, bound, depth)?,
+            )),
+            ASTNode::UnaryOp { op, expr } => Ok(ASTNode::UnaryOp {
+                op: op.clone(),
+                expr: Box::new(self.resolve_symbolic(expr, bound, depth)?),
+            }),
+            // As in evaluation, '2 m' is 2*m if there is a variable m
+            ASTNode::Quantity { value, unit } if bound.contains(unit) || self.unit_variable(unit).is_some() => {
+                let product = symbolic::binary(*value.clone(), Operator::Mul, ASTNode::Identifier(unit.clone()));
+                self.resolve_symbolic(&product, bound, depth)
+            },
+            // A nested diff() is differentiated first, e.g. diff(diff(f(x), x) * x, x)
+            ASTNode::FunctionCall { name, args } if name == "diff" && self.vm.get_function(name).is_err() => {
+                self.diff(args)
+            },
+            ASTNode::FunctionCall { name, args } => {
+                let mut resolved_args = vec![];
+                for arg in args {
+                    resolved_args.push(self.resolve_symbolic(arg, bound, depth)?);
+                }
+
+                let base = name.trim_end_matches('\'');
+                let (params, body, _) = match self.vm.get_function(base) {
+                    Ok(function) => function,
+                    // Built-in functions are kept as calls
+                    Err(_) => return Ok(symbolic::call(name, resolved_args)),
+                };
+                if params.len() != resolved_args.len() {
+                    return Err(format!("{}() takes {} arguments, got {}", name, params.len(), resolved_args.len()));
+                }
+
+                let expr = if base == name {
+                    self.resolve_symbolic(function_expr(name, &body)?, &params, depth + 1)?
+                } else {
+                    self.differentiate_function(base, &params, &body, name.len() - base.len(), depth)?
+                };
+                let bindings: HashMap<String, ASTNode> = params.into_iter().zip(resolved_args).collect();
+                Ok(symbolic::substitute(&expr, &bindings))
+            },
+            other => Ok(other.clone()),
+        }
+    }
+}
+
+// The expression a function returns. Only functions of the form 'fun f(x) { return <expr> }' can be
+// used symbolically.
+fn function_expr<'a>(name: &str, body: &'a [ASTNode]) -> Result<&'a ASTNode, String> {
+    match body {
+        [ASTNode::Return { expr }] => Ok(expr),
+        _ => Err(format!("{}() can't be used symbolically, its body must be a single return statement", name)),
+    }
+}
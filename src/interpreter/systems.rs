@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use super::{builtins, Interpreter};
+use crate::ast::parser::ASTNode;
+use crate::solve;
+use crate::symbolic;
+use crate::vm::symbol::DataType;
+
+// Math blocks. A block is solved where it's declared, and also stored under its name as a system
+// that can be called with named arguments to solve it for other values: 'ohm(v: 12, r: 4)'.
+
+impl Interpreter {
+    /// Solves the equations of a `math` block and declares the unknowns. Variables that already have
+    /// a number value are treated as known, variables holding an expression are substituted,
+    /// and integrals are evaluated first. With a `solve x, y` clause, exactly the listed variables
+    /// are unknowns, and existing mutable variables among them are assigned instead.
+    /// A block with fewer equations than unknowns, like 'math ohm { v = i*r }', is only declared.
+    pub(super) fn math_block(&mut self, name: &str, unknowns: Option<&[String]>, body: &[ASTNode]) -> Result<(), String> {
+        let bound = unknowns.unwrap_or_default();
+        let mut equations = vec![];
+        for equation in body {
+            equations.push(match equation {
+                ASTNode::MathExpression { left, right } => ASTNode::MathExpression {
+                    left: Box::new(self.resolve_math_expr(left, bound)?),
+                    right: Box::new(self.resolve_math_expr(right, bound)?),
+                },
+                ASTNode::BinaryOp { left, op, right } => symbolic::binary(
+                    self.resolve_math_expr(left, bound)?,
+                    op.clone(),
+                    self.resolve_math_expr(right, bound)?,
+                ),
+                ASTNode::MathObjective { maximize, expr } => ASTNode::MathObjective {
+                    maximize: *maximize,
+                    expr: Box::new(self.resolve_math_expr(expr, bound)?),
+                },
+                other => other.clone(),
+            });
+        }
+
+        let mut vars: Vec<String> = equations.iter().flat_map(solve::find_vars).collect();
+        vars.retain(|var| !bound.contains(var));
+        let mut known_values = solve::get_known_values(&vars, &self.vm);
+        for var in &vars {
+            if let (false, Some(DataType::Number(n))) = (known_values.contains_key(var), builtins::constant(var)) {
+                if self.vm.get_variable(var).is_err() {
+                    known_values.insert(var.clone(), symbolic::from_number(n));
+                }
+            }
+        }
+
+        // The system keeps the values known now, its other variables can be given when it's called
+        let system: Vec<ASTNode> = equations.iter().map(|equation| symbolic::substitute(equation, &known_values)).collect();
+        let mut system_vars: Vec<String> = vec![];
+        for var in system.iter().flat_map(solve::find_vars) {
+            if !system_vars.contains(&var) {
+                system_vars.push(var);
+            }
+        }
+        let is_formula = unknowns.is_none()
+            && equations.iter().all(|equation| matches!(equation, ASTNode::MathExpression { .. }))
+            && system_vars.len() > equations.len();
+
+        if !is_formula {
+            let solution = solve::solve_system(&equations, unknowns, &known_values, &mut |expr| self.compute_expr(expr.clone()))?;
+            for (name, value) in solution {
+                if self.vm.get_variable(&name).is_ok() {
+                    let address = self.vm.get_or_add_to_memory(value)?;
+                    self.vm.set_variable_address(&name, address)?;
+                } else {
+                    self.vm.declare_variable(name, value, false)?;
+                }
+            }
+        }
+        self.vm.declare_variable(name.to_string(), DataType::System(system_vars, system), false)
+    }
+
+    fn resolve_math_expr(&mut self, expr: &ASTNode, bound: &[String]) -> Result<ASTNode, String> {
+        let expr = self.resolve_symbolic(expr, bound, 0)?;
+        self.evaluate_calculus(&expr)
+    }
+
+    /// Calls a math system with some of its variables given as named arguments, and solves it for the
+    /// others. Returns the value of the single unknown, or a list of the values of all unknowns.
+    pub(super) fn call_system(&mut self, name: &str, vars: &[String], equations: &[ASTNode], args: &[ASTNode]) -> Result<DataType, String> {
+        let mut known_values = HashMap::new();
+        for arg in args {
+            let ASTNode::NamedArgument { name: var, value } = arg else {
+                return Err(format!("The arguments of {} must be named, e.g. {}({}: 1)", name, name, vars.join(": 1, ")));
+            };
+            if !vars.contains(var) {
+                return Err(format!("{} has no variable {}, its variables are {}", name, var, vars.join(", ")));
+            }
+            match self.compute_expr(*value.clone())? {
+                DataType::Number(n) => known_values.insert(var.clone(), symbolic::from_number(n)),
+                DataType::Quantity(value, unit) => known_values.insert(var.clone(), unit.to_node(value)),
+                other => return Err(format!("Expected a number or a quantity for {}, got {}", var, other)),
+            };
+        }
+
+        let unknowns: Vec<String> = vars.iter().filter(|var| !known_values.contains_key(*var)).cloned().collect();
+        if unknowns.is_empty() {
+            return Err(format!("All variables of {} are given, there's nothing to solve for", name));
+        }
+        let is_equations = equations.iter().all(|equation| matches!(equation, ASTNode::MathExpression { .. }));
+        if is_equations && unknowns.len() > equations.len() {
+            return Err(format!(
+                "{} has {} equation(s), so it can only be solved for {} variable(s), but {} are missing",
+                name, equations.len(), equations.len(), unknowns.join(", "),
+            ));
+        }
+        let solution = solve::solve_system(equations, Some(&unknowns), &known_values, &mut |expr| self.compute_expr(expr.clone()))
+            .map_err(|e| format!("In {}(): {}", name, e))?;
+
+        let mut values: Vec<DataType> = solution.into_iter().map(|(_, value)| value).collect();
+        match values.len() {
+            1 => Ok(values.remove(0)),
+            _ => Ok(DataType::List(values)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(interpreter: &mut Interpreter, code: &str) -> Result<String, String> {
+        let results = interpreter.evaluate(crate::ast::parse(code)?)?;
+        Ok(results.last().map(|(_, value)| value.to_string()).unwrap_or_default())
+    }
+
+    #[test]
+    fn systems_can_be_called_with_named_arguments() {
+        let mut interpreter = Interpreter::new();
+        evaluate(&mut interpreter, "math ohm { v = i * r }").unwrap();
+        assert_eq!(evaluate(&mut interpreter, "ohm(v: 12, r: 4)"), Ok("3".to_string()));
+        assert_eq!(evaluate(&mut interpreter, "ohm(i: 2, r: 4)"), Ok("8".to_string()));
+    }
+
+    #[test]
+    fn a_system_named_like_a_unit_doesnt_hide_it() {
+        let mut interpreter = Interpreter::new();
+        evaluate(&mut interpreter, "math ohm { v = i * r }").unwrap();
+        assert_eq!(evaluate(&mut interpreter, "5 ohm"), Ok("5 ohm".to_string()));
+        assert_eq!(evaluate(&mut interpreter, "ohm + 1"), Err("Expected numbers, got system and number".to_string()));
+    }
+}
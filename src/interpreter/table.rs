@@ -0,0 +1,139 @@
+use super::Interpreter;
+use crate::ast::parser::ASTNode;
+use crate::vm::{number::Number, symbol::DataType};
+
+// table(f, g, ..., start, end, step) prints the values of functions from start to end. The functions
+// are given like for 'plot'. A "csv" argument at the end prints comma separated values for
+// spreadsheets instead, and any other string writes them to that file.
+
+pub const FUNCTIONS: &[&str] = &["table"];
+
+// Tables are for reading, a longer one is most likely a wrong step
+const MAX_ROWS: usize = 10000;
+
+impl Interpreter {
+    /// Calls a table built-in. Returns `None` if `name` isn't one of them.
+    pub(super) fn call_table(&mut self, name: &str, args: &[ASTNode]) -> Option<Result<DataType, String>> {
+        match name {
+            "table" => Some(self.table(args)),
+            _ => None,
+        }
+    }
+
+    fn table(&mut self, args: &[ASTNode]) -> Result<DataType, String> {
+        let mut args = args.to_vec();
+        let target = match args.last() {
+            Some(ASTNode::String(_)) => match self.compute_expr(args.pop().unwrap())? {
+                DataType::String(target) => Some(target),
+                other => return Err(format!("Expected \"csv\" or a file name, got {}", other)),
+            },
+            _ => None,
+        };
+        if args.len() < 4 {
+            return Err("table() takes one or more functions, the start, the end and the step, e.g. table(f, 0, 1, 0.1)".to_string());
+        }
+
+        let bounds_at = args.len() - 3;
+        let mut bounds = vec![];
+        for arg in &args[bounds_at..] {
+            match self.compute_expr(arg.clone())? {
+                DataType::Number(n) => bounds.push(n),
+                other => return Err(format!("Expected a real number, got {}", other)),
+            }
+        }
+        let (start, end, step) = (bounds[0].to_f64(), bounds[1].to_f64(), bounds[2].to_f64());
+        let steps = (end - start) / step;
+        if !steps.is_finite() || steps < 0.0 {
+            return Err(format!("table() can't get from {} to {} in steps of {}", bounds[0], bounds[1], bounds[2]));
+        }
+        // A little slack, so 0 to 1 in steps of 0.1 ends at 1 despite rounding
+        let rows = (steps + 1e-9).floor() as usize + 1;
+        if rows > MAX_ROWS {
+            return Err(format!("table() would have {} rows, at most {} are allowed", rows, MAX_ROWS));
+        }
+        // With whole numbers the function gets integers, so e.g. 'table(fun(n) { return n! }, 1, 10, 1)' works
+        let integers = bounds.iter().all(|n| matches!(n, Number::Int(_)));
+
+        let functions = self.function_args(&args[..bounds_at])?;
+        let variable = match &args[bounds_at - 1] {
+            ASTNode::Identifier(var) if self.is_variable(&args[bounds_at - 1]) => var.clone(),
+            _ => match &functions[0].1 {
+                DataType::Function(params, ..) if params.len() == 1 => params[0].clone(),
+                _ => "x".to_string(),
+            },
+        };
+
+        let mut header = vec![variable];
+        header.extend(functions.iter().map(|(name, _)| name.clone()));
+        let mut table = vec![];
+        for i in 0..rows {
+            let x = match integers {
+                true => Number::Int(bounds[0].to_f64() as i64 + i as i64 * step as i64),
+                false => Number::Float(round(start + i as f64 * step)),
+            };
+            let mut row = vec![Some(x.to_string())];
+            for (_, function) in &functions {
+                // A failing function leaves its cell empty, like sqrt below 0
+                let value = self.call_value(function, vec![DataType::Number(x.clone())]).ok();
+                row.push(value.map(|value| value.to_string().lines().collect::<Vec<_>>().join(" ")));
+            }
+            table.push(row);
+        }
+
+        match target.as_deref() {
+            None => {
+                for line in aligned(&header, &table) {
+                    self.write_line(line);
+                }
+            },
+            Some("csv") => {
+                for line in csv(&header, &table) {
+                    self.write_line(line);
+                }
+            },
+            Some(file) => {
+                let mut text = csv(&header, &table).join("\n");
+                text.push('\n');
+                std::fs::write(file, text).map_err(|e| format!("Can't write {}: {}", file, e))?;
+                self.write_line(format!("Saved the table to {}", file));
+            },
+        }
+        Ok(DataType::Null())
+    }
+}
+
+// Rounds away the error of adding up the steps, so 3 steps of 0.1 are 0.3
+fn round(x: f64) -> f64 {
+    format!("{:.12e}", x).parse().unwrap_or(x)
+}
+
+// The table with the columns right aligned and a line under the header
+fn aligned(header: &[String], table: &[Vec<Option<String>>]) -> Vec<String> {
+    let cell = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    let widths: Vec<usize> = header.iter().enumerate().map(|(column, name)| {
+        table.iter().map(|row| cell(&row[column]).chars().count()).fold(name.chars().count(), usize::max)
+    }).collect();
+    let line = |cells: Vec<String>| {
+        cells.iter().zip(&widths).map(|(text, width)| format!("{:>width$}", text, width = width)).collect::<Vec<_>>().join(" │ ")
+    };
+
+    let mut lines = vec![line(header.to_vec())];
+    lines.push(widths.iter().map(|width| "─".repeat(*width)).collect::<Vec<_>>().join("─┼─"));
+    for row in table {
+        lines.push(line(row.iter().map(cell).collect()));
+    }
+    lines
+}
+
+fn csv(header: &[String], table: &[Vec<Option<String>>]) -> Vec<String> {
+    // Fields with commas or quotes, like vectors, are quoted
+    let field = |text: &str| match text.contains([',', '"']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    };
+    let mut lines = vec![header.iter().map(|name| field(name)).collect::<Vec<_>>().join(",")];
+    for row in table {
+        lines.push(row.iter().map(|value| field(value.as_deref().unwrap_or_default())).collect::<Vec<_>>().join(","));
+    }
+    lines
+}
@@ -0,0 +1,174 @@
+use std::f64::consts::{FRAC_PI_2, LN_10, PI};
+
+use crate::ast::parser::Operator;
+use crate::vm::uncertain::{Interval, Uncertain};
+use crate::vm::symbol::DataType;
+
+// Numbers with an uncertainty and intervals: checks before arithmetic, and the math functions.
+// The arithmetic itself is in the `DataType` operators.
+
+/// Creates a value with an uncertainty, 'value +- error'
+pub fn plus_minus(value: DataType, error: DataType) -> Result<DataType, String> {
+    match (&value, &error) {
+        (DataType::Number(value), DataType::Number(error)) => Ok(DataType::Uncertain(Uncertain::new(value.to_f64(), error.to_f64()))),
+        _ => Err(format!("'+-' expects two real numbers, got {} and {}", value, error)),
+    }
+}
+
+/// Creates the interval '[lower .. upper]'
+pub fn interval(lower: DataType, upper: DataType) -> Result<DataType, String> {
+    let (Some(a), Some(b)) = (lower.to_f64(), upper.to_f64()) else {
+        return Err(format!("The bounds of an interval must be real numbers, got {} and {}", lower, upper));
+    };
+    if a > b {
+        return Err(format!("The lower bound of an interval can't be above the upper bound, got [{} .. {}]", lower, upper));
+    }
+    Ok(DataType::Interval(Interval::new(a, b)))
+}
+
+/// Checks an operation where at least one side is uncertain. They combine with real numbers,
+/// but an uncertain number and an interval can't be combined.
+pub fn check_operation(left: &DataType, op: &Operator, right: &DataType) -> Result<(), String> {
+    let combines = |value: &DataType| value.is_number() || value.is_uncertain();
+    let mixed = matches!((left, right), (DataType::Uncertain(_), DataType::Interval(_)) | (DataType::Interval(_), DataType::Uncertain(_)));
+    if !combines(left) || !combines(right) || mixed {
+        return Err(format!("Cannot combine {} {} and {} {}", left.type_name(), left, right.type_name(), right));
+    }
+    match (op, right) {
+        // Like dividing by the number 0, the result would be infinite
+        (Operator::Div, DataType::Uncertain(u)) if u.value == 0.0 => Err("Division by zero".to_string()),
+        (Operator::Div, DataType::Interval(i)) if i.contains(0.0) => Err("Division by zero".to_string()),
+        (Operator::Mod | Operator::And | Operator::Or, _) => {
+            Err(format!("Unsupported operation {:?} between {} and {}", op, left.type_name(), right.type_name()))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Built-in functions called with an uncertain number or an interval.
+pub fn call(name: &str, args: &[DataType]) -> Result<DataType, String> {
+    let result = match args {
+        [DataType::Uncertain(u)] => uncertain_function(name, *u).map(|u| (u.value.is_nan() || u.error.is_nan(), DataType::Uncertain(u))),
+        [DataType::Interval(i)] => interval_function(name, *i).map(|i| (i.lower.is_nan() || i.upper.is_nan(), DataType::Interval(i))),
+        _ => return Err(format!("{}() takes a single uncertain number or interval", name)),
+    };
+    match result {
+        Some((true, _)) => Err(format!("{}() is not defined for {}", name, args[0])),
+        Some((false, value)) => Ok(value),
+        None => Err(format!("{}() can't be called with {} {}", name, args[0].type_name(), args[0])),
+    }
+}
+
+// First order error propagation with the derivative of the function
+fn uncertain_function(name: &str, u: Uncertain) -> Option<Uncertain> {
+    Some(match name {
+        "abs" => u.apply(f64::abs, f64::signum),
+        "sqrt" => u.apply(f64::sqrt, |x| 0.5 / x.sqrt()),
+        "exp" => u.apply(f64::exp, f64::exp),
+        "ln" => u.apply(f64::ln, |x| 1.0 / x),
+        "log" => u.apply(f64::log10, |x| 1.0 / (x * LN_10)),
+        "sin" => u.apply(f64::sin, f64::cos),
+        "cos" => u.apply(f64::cos, |x| -x.sin()),
+        "tan" => u.apply(f64::tan, |x| 1.0 / (x.cos() * x.cos())),
+        "asin" => u.apply(f64::asin, |x| 1.0 / (1.0 - x * x).sqrt()),
+        "acos" => u.apply(f64::acos, |x| -1.0 / (1.0 - x * x).sqrt()),
+        "atan" => u.apply(f64::atan, |x| 1.0 / (1.0 + x * x)),
+        _ => return None,
+    })
+}
+
+// The range of the function over the interval. Most functions are monotonic on their domain.
+fn interval_function(name: &str, i: Interval) -> Option<Interval> {
+    let in_domain = |lower: f64, upper: f64| lower <= i.lower && i.upper <= upper;
+    Some(match name {
+        "abs" if i.contains(0.0) => Interval::new(0.0, i.lower.abs().max(i.upper.abs())),
+        "abs" => i.monotonic(f64::abs),
+        "sqrt" if in_domain(0.0, f64::INFINITY) => i.monotonic(f64::sqrt),
+        "exp" => i.monotonic(f64::exp),
+        "ln" if i.lower > 0.0 => i.monotonic(f64::ln),
+        "log" if i.lower > 0.0 => i.monotonic(f64::log10),
+        "sin" => i.sin(),
+        "cos" => (i + Interval::exact(FRAC_PI_2)).sin(),
+        // tan is monotonic between its poles, the first pole from the lower bound must be past the upper one
+        "tan" if FRAC_PI_2 + PI * ((i.lower - FRAC_PI_2) / PI).ceil() > i.upper => i.monotonic(f64::tan),
+        "asin" if in_domain(-1.0, 1.0) => i.monotonic(f64::asin),
+        "acos" if in_domain(-1.0, 1.0) => i.monotonic(f64::acos),
+        "atan" => i.monotonic(f64::atan),
+        "floor" => i.monotonic(f64::floor),
+        "ceil" => i.monotonic(f64::ceil),
+        "round" => i.monotonic(f64::round),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use super::*;
+
+    fn value_of(code: &str) -> Result<DataType, String> {
+        Interpreter::new().value_of(crate::ast::parse_expression(code)?)
+    }
+
+    fn uncertain(code: &str) -> Uncertain {
+        match value_of(code) {
+            Ok(DataType::Uncertain(u)) => u,
+            other => panic!("Expected an uncertain number from {}, got {:?}", code, other.map(|v| v.to_string())),
+        }
+    }
+
+    fn interval(code: &str) -> Interval {
+        match value_of(code) {
+            Ok(DataType::Interval(i)) => i,
+            other => panic!("Expected an interval from {}, got {:?}", code, other.map(|v| v.to_string())),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn errors_are_propagated() {
+        // Independent errors add in quadrature
+        let sum = uncertain("(1 +- 0.3) + (2 +- 0.4)");
+        assert_close(sum.value, 3.0);
+        assert_close(sum.error, 0.5);
+        // Relative errors add in quadrature for products and quotients
+        let quotient = uncertain("(6 +- 0.3) / (2 +- 0.2)");
+        assert_close(quotient.value, 3.0);
+        assert_close(quotient.error, 3.0 * (0.05f64.powi(2) + 0.1f64.powi(2)).sqrt());
+        let scaled = uncertain("2 * (1 +- 0.1)");
+        assert_close(scaled.error, 0.2);
+        let root = uncertain("sqrt(4 +- 0.4)");
+        assert_close(root.value, 2.0);
+        assert_close(root.error, 0.1);
+    }
+
+    #[test]
+    fn intervals_contain_every_result() {
+        let difference = interval("[1 .. 2] - [1 .. 2]");
+        assert_eq!((difference.lower, difference.upper), (-1.0, 1.0));
+        let product = interval("[-1 .. 2] * [3 .. 4]");
+        assert_eq!((product.lower, product.upper), (-4.0, 8.0));
+        let quotient = interval("[1 .. 2] / [4 .. 8]");
+        assert_eq!((quotient.lower, quotient.upper), (0.125, 0.5));
+        let sine = interval("sin([0 .. pi])");
+        assert_close(sine.lower, 0.0);
+        assert_close(sine.upper, 1.0);
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        for code in ["(1 +- 0.1) / (0 +- 0.1)", "(1 +- 0.1) / 0", "[1 .. 2] / [-1 .. 1]", "[1 .. 2] / [0 .. 1]", "1 / [0 .. 0]", "[1 .. 2] / 0"] {
+            assert_eq!(value_of(code).map(|v| v.to_string()), Err("Division by zero".to_string()), "{}", code);
+        }
+        assert!(value_of("[1 .. 2] / [1 .. 3]").is_ok());
+    }
+
+    #[test]
+    fn uncertain_numbers_and_intervals_dont_mix() {
+        assert!(value_of("(1 +- 0.1) + [1 .. 2]").is_err());
+        assert!(value_of("(1 +- 0.1) % 2").is_err());
+    }
+}
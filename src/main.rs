@@ -1,9 +1,35 @@
 mod vm;
 mod ast;
 mod interpreter;
+mod plot;
 mod solve;
+mod symbolic;
+mod units;
 mod repl;
 
 fn main() {
-    repl::start();
+    let mut strict = false;
+    let mut tui = false;
+    let mut rpn = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            "--tui" => tui = true,
+            "--rpn" => rpn = true,
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                eprintln!("Usage: rust_vm [--strict] [--tui] [--rpn]");
+                std::process::exit(2);
+            },
+        }
+    }
+
+    if tui {
+        if let Err(e) = repl::tui::start(strict, rpn) {
+            eprintln!("Can't run the TUI: {}", e);
+            std::process::exit(1);
+        }
+    } else {
+        repl::start(strict, rpn);
+    }
 }
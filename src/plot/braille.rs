@@ -0,0 +1,112 @@
+use crossterm::style::{Color, Stylize};
+
+use super::{label, Plot};
+
+// Each braille character is a cell of 2x4 dots, so a plot of 60x15 characters has 120x60 dots.
+// The bit of each dot in the character, by column and row.
+const DOT_BITS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+const COLORS: [Color; 6] = [Color::Cyan, Color::Yellow, Color::Magenta, Color::Green, Color::Red, Color::Blue];
+
+struct Canvas {
+    columns: usize,
+    rows: usize,
+    cells: Vec<u8>,
+    owners: Vec<Option<usize>>, // The series that drew last in each cell, None for the axes
+}
+
+impl Canvas {
+    fn set(&mut self, x: i64, y: i64, owner: Option<usize>) {
+        if x < 0 || y < 0 || x >= self.columns as i64 * 2 || y >= self.rows as i64 * 4 {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        let cell = (y / 4) * self.columns + x / 2;
+        self.cells[cell] |= DOT_BITS[x % 2][y % 4];
+        if owner.is_some() || self.owners[cell].is_none() {
+            self.owners[cell] = owner;
+        }
+    }
+
+    // A vertical run of dots, from one sample to the next
+    fn line(&mut self, x: i64, from: i64, to: i64, owner: Option<usize>) {
+        for y in from.min(to)..=from.max(to) {
+            self.set(x, y, owner);
+        }
+    }
+}
+
+/// Draws the plot in `columns` x `rows` characters, with the y values on the left, the x range
+/// below and a legend. With `color`, each function gets its own color.
+pub fn render(plot: &Plot, columns: usize, rows: usize, color: bool) -> String {
+    let mut canvas = Canvas { columns, rows, cells: vec![0; columns * rows], owners: vec![None; columns * rows] };
+    let (x_min, x_max) = plot.x_range;
+    let (y_min, y_max) = plot.y_range;
+    let (width, height) = ((columns * 2) as f64, (rows * 4) as f64);
+    // Dot coordinates, y grows downwards
+    let to_x = |x: f64| ((x - x_min) / (x_max - x_min) * (width - 1.0)).round() as i64;
+    let to_y = |y: f64| ((y_max - y) / (y_max - y_min) * (height - 1.0)).round() as i64;
+
+    if x_min < 0.0 && x_max > 0.0 {
+        canvas.line(to_x(0.0), 0, height as i64 - 1, None);
+    }
+    if y_min < 0.0 && y_max > 0.0 {
+        for x in 0..width as i64 {
+            canvas.set(x, to_y(0.0), None);
+        }
+    }
+
+    for (index, series) in plot.series.iter().enumerate() {
+        let mut previous: Option<i64> = None;
+        for (x, y) in &series.points {
+            let Some(y) = y else {
+                previous = None;
+                continue;
+            };
+            // Values far outside the plot are clamped, so steep parts still reach the edge
+            let (x, y) = (to_x(*x), to_y(*y).clamp(-1, height as i64));
+            match previous {
+                // Jumps over more than the height are poles, like tan at pi/2, and aren't connected
+                Some(from) if (y - from).abs() <= height as i64 => canvas.line(x, from, y, Some(index)),
+                _ => canvas.set(x, y, Some(index)),
+            }
+            previous = Some(y);
+        }
+    }
+
+    let top = label(y_max, y_max - y_min);
+    let middle = label((y_min + y_max) / 2.0, y_max - y_min);
+    let bottom = label(y_min, y_max - y_min);
+    let margin = top.len().max(middle.len()).max(bottom.len());
+    let mut lines = vec![];
+    for row in 0..rows {
+        let (text, tick) = match row {
+            0 => (top.as_str(), '┤'),
+            _ if row == rows / 2 => (middle.as_str(), '┤'),
+            _ if row == rows - 1 => (bottom.as_str(), '┤'),
+            _ => ("", '│'),
+        };
+        let mut line = format!("{:>width$} {}", text, tick, width = margin);
+        for column in 0..columns {
+            let cell = row * columns + column;
+            let c = char::from_u32(0x2800 + canvas.cells[cell] as u32).unwrap();
+            match canvas.owners[cell] {
+                Some(owner) if color => line.push_str(&c.with(COLORS[owner % COLORS.len()]).to_string()),
+                None if color && canvas.cells[cell] != 0 => line.push_str(&c.dark_grey().to_string()),
+                _ => line.push(c),
+            }
+        }
+        lines.push(line);
+    }
+    lines.push(format!("{} └{}", " ".repeat(margin), "─".repeat(columns)));
+    let (left, right) = (label(x_min, x_max - x_min), label(x_max, x_max - x_min));
+    let gap = (columns + 1).saturating_sub(left.len() + right.len()).max(1);
+    lines.push(format!("{} {}{}{}", " ".repeat(margin), left, " ".repeat(gap), right));
+
+    let legend: Vec<String> = plot.series.iter().enumerate().map(|(index, series)| {
+        let marker = if color { "━━".with(COLORS[index % COLORS.len()]).to_string() } else { format!("[{}]", index + 1) };
+        format!("{} {}", marker, series.name)
+    }).collect();
+    lines.push(format!("{} {}", " ".repeat(margin), legend.join("   ")));
+    lines.join("\n")
+}
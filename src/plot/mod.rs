@@ -0,0 +1,51 @@
+// Plots of functions of one variable, drawn in the terminal with braille characters or written to
+// SVG files. The functions are sampled by the interpreter, this module only draws the points.
+
+pub mod braille;
+pub mod svg;
+
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, Option<f64>)>, // None where the function has no real value, which leaves a gap
+}
+
+pub struct Plot {
+    pub series: Vec<Series>,
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+}
+
+impl Plot {
+    /// A plot of the series. Without a y range, it's chosen so every point is visible.
+    pub fn new(series: Vec<Series>, x_range: (f64, f64), y_range: Option<(f64, f64)>) -> Plot {
+        let y_range = y_range.unwrap_or_else(|| {
+            let values = series.iter().flat_map(|s| s.points.iter().filter_map(|(_, y)| *y));
+            let (low, high) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), y| (low.min(y), high.max(y)));
+            match (low, high) {
+                (low, high) if low > high => (-1.0, 1.0),
+                // A constant function is drawn in the middle
+                (low, high) if low == high => (low - 1.0, high + 1.0),
+                range => range,
+            }
+        });
+        Plot { series, x_range, y_range }
+    }
+}
+
+/// A short label for a value on an axis of length `span`, e.g. '0.5', '-12' or '1.5e6'
+pub fn label(value: f64, span: f64) -> String {
+    let magnitude = value.abs();
+    // Values that are 0 at the scale of the axis, like sin(pi) or the sample of x^2 closest to 0
+    if magnitude < span * 1e-3 {
+        return "0".to_string();
+    }
+    if !(1e-3..1e5).contains(&magnitude) {
+        return format!("{:.2e}", value);
+    }
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
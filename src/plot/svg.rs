@@ -0,0 +1,84 @@
+use std::fmt::Write;
+
+use super::{label, Plot};
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 400.0;
+const MARGIN: f64 = 50.0;
+const COLORS: [&str; 6] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b"];
+
+// Round values for the ticks of an axis, about five of them
+fn ticks(low: f64, high: f64) -> Vec<f64> {
+    let raw = (high - low) / 5.0;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * magnitude).find(|step| *step >= raw).unwrap_or(10.0 * magnitude);
+    let first = (low / step).ceil() as i64;
+    let last = (high / step).floor() as i64;
+    (first..=last).map(|i| i as f64 * step).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// The plot as an SVG image, with a grid, labeled axes and a legend
+pub fn to_svg(plot: &Plot) -> String {
+    let (x_min, x_max) = plot.x_range;
+    let (y_min, y_max) = plot.y_range;
+    let to_x = |x: f64| MARGIN + (x - x_min) / (x_max - x_min) * (WIDTH - 2.0 * MARGIN);
+    let to_y = |y: f64| HEIGHT - MARGIN - (y - y_min) / (y_max - y_min) * (HEIGHT - 2.0 * MARGIN);
+
+    // Writing to a String can't fail
+    let mut svg = String::new();
+    writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#, WIDTH, HEIGHT).unwrap();
+    writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+
+    for x in ticks(x_min, x_max) {
+        let px = to_x(x);
+        writeln!(svg, r##"<line x1="{px:.1}" y1="{:.1}" x2="{px:.1}" y2="{:.1}" stroke="#ddd"/>"##, MARGIN, HEIGHT - MARGIN).unwrap();
+        writeln!(svg, r#"<text x="{px:.1}" y="{:.1}" text-anchor="middle">{}</text>"#, HEIGHT - MARGIN + 16.0, label(x, x_max - x_min)).unwrap();
+    }
+    for y in ticks(y_min, y_max) {
+        let py = to_y(y);
+        writeln!(svg, r##"<line x1="{:.1}" y1="{py:.1}" x2="{:.1}" y2="{py:.1}" stroke="#ddd"/>"##, MARGIN, WIDTH - MARGIN).unwrap();
+        writeln!(svg, r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#, MARGIN - 6.0, py + 4.0, label(y, y_max - y_min)).unwrap();
+    }
+    // The axes through the origin, if it's in the plot
+    if x_min < 0.0 && x_max > 0.0 {
+        writeln!(svg, r#"<line x1="{0:.1}" y1="{1:.1}" x2="{0:.1}" y2="{2:.1}" stroke="black"/>"#, to_x(0.0), MARGIN, HEIGHT - MARGIN).unwrap();
+    }
+    if y_min < 0.0 && y_max > 0.0 {
+        writeln!(svg, r#"<line x1="{1:.1}" y1="{0:.1}" x2="{2:.1}" y2="{0:.1}" stroke="black"/>"#, to_y(0.0), MARGIN, WIDTH - MARGIN).unwrap();
+    }
+    writeln!(svg, r#"<rect x="{0}" y="{0}" width="{1}" height="{2}" fill="none" stroke="black"/>"#, MARGIN, WIDTH - 2.0 * MARGIN, HEIGHT - 2.0 * MARGIN).unwrap();
+
+    writeln!(svg, r#"<clipPath id="area"><rect x="{0}" y="{0}" width="{1}" height="{2}"/></clipPath>"#, MARGIN, WIDTH - 2.0 * MARGIN, HEIGHT - 2.0 * MARGIN).unwrap();
+    for (index, series) in plot.series.iter().enumerate() {
+        let color = COLORS[index % COLORS.len()];
+        // A gap in the values starts a new line
+        let mut path = String::new();
+        let mut drawing = false;
+        for (x, y) in &series.points {
+            match y {
+                Some(y) => {
+                    // Values far outside the plot are clamped, so they don't overflow the coordinates
+                    let py = to_y(*y).clamp(-HEIGHT, 2.0 * HEIGHT);
+                    write!(path, "{}{:.1},{:.1} ", if drawing { "L" } else { "M" }, to_x(*x), py).unwrap();
+                    drawing = true;
+                },
+                None => drawing = false,
+            }
+        }
+        writeln!(svg, r#"<path d="{}" fill="none" stroke="{}" stroke-width="2" clip-path="url(#area)"/>"#, path.trim_end(), color).unwrap();
+        let legend_y = MARGIN + 16.0 + 18.0 * index as f64;
+        writeln!(svg, r#"<line x1="{0:.1}" y1="{1:.1}" x2="{3:.1}" y2="{1:.1}" stroke="{2}" stroke-width="2"/>"#, WIDTH - MARGIN - 120.0, legend_y, color, WIDTH - MARGIN - 100.0).unwrap();
+        writeln!(svg, r#"<text x="{:.1}" y="{:.1}">{}</text>"#, WIDTH - MARGIN - 94.0, legend_y + 4.0, escape(&series.name)).unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes the plot to an SVG file
+pub fn write(plot: &Plot, path: &str) -> Result<(), String> {
+    std::fs::write(path, to_svg(plot)).map_err(|e| format!("Can't write {}: {}", path, e))
+}
@@ -0,0 +1,116 @@
+use std::time::Instant;
+
+use super::Session;
+use crate::vm::{settings, symbol::DataType};
+
+// Meta-commands of the REPL. They start with ':' and act on the session instead of being run as code.
+
+pub const NAMES: &[&str] = &["vars", "reset", "load", "save", "ast", "time", "set", "rpn", "help"];
+
+const HELP: &str = "\
+:vars           list the variables and functions with their values
+:reset          start over with an empty session
+:load <file>    run a script in this session
+:save <file>    write the code of this session to a file
+:ast <code>     show the syntax tree of an expression or of statements
+:time <code>    run code and show how long it took
+:set            show the settings
+:set <name> <value>
+                change a setting, like 'set' in code: angle rad/deg, digits 1-17/auto,
+                notation normal/sci/eng, base dec/hex/bin/oct
+:rpn            switch between code and RPN input like '3 4 + 2 *', with the stack
+                words swap, drop, dup and clear, neg and ! on one value, and functions
+                that take their arguments from the stack
+:help           show this help
+exit            quit";
+
+/// Runs a meta-command, `command` is the line without the leading ':'.
+pub fn run(session: &mut Session, command: &str) {
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+
+    match (name, argument) {
+        ("vars", "") => vars(session),
+        ("reset", "") => {
+            let rpn = session.stack.is_some();
+            *session = Session::new(session.strict, session.messages.is_some());
+            // The mode stays, with an empty stack
+            session.stack = rpn.then(Vec::new);
+            settings::reset();
+            session.print("Session reset".to_string());
+        },
+        ("load", file) if !file.is_empty() => match std::fs::read_to_string(file) {
+            Ok(code) => {
+                session.run(&code);
+            },
+            Err(e) => session.error(format!("Can't read {}: {}", file, e)),
+        },
+        ("save", file) if !file.is_empty() => {
+            let mut code = session.history.join("\n");
+            code.push('\n');
+            match std::fs::write(file, code) {
+                Ok(()) => session.print(format!("Saved {} entries to {}", session.history.len(), file)),
+                Err(e) => session.error(format!("Can't write {}: {}", file, e)),
+            }
+        },
+        ("ast", code) if !code.is_empty() => ast(session, code),
+        ("time", code) if !code.is_empty() => {
+            let start = Instant::now();
+            session.run(code);
+            session.print(format!("Took {:?}", start.elapsed()));
+        },
+        ("set", "") => {
+            for setting in settings::describe() {
+                session.print(setting);
+            }
+        },
+        ("set", setting) => {
+            let (name, value) = setting.split_once(char::is_whitespace).unwrap_or((setting, ""));
+            match settings::set(name, value.trim()) {
+                Ok(()) => session.print(format!("{} {}", name, value.trim())),
+                Err(e) => session.error(e),
+            }
+        },
+        ("rpn", "") => match session.stack.take() {
+            Some(_) => session.print("Code input, :rpn switches back to RPN".to_string()),
+            None => {
+                session.stack = Some(vec![]);
+                session.print("RPN input, :rpn switches back to code".to_string());
+            },
+        },
+        ("help", "") => session.print(HELP.to_string()),
+        ("load" | "save", _) => session.error(format!(":{} needs a file name", name)),
+        ("ast" | "time", _) => session.error(format!(":{} needs some code", name)),
+        ("vars" | "reset" | "rpn" | "help", _) => session.error(format!(":{} takes no arguments", name)),
+        _ => session.error(format!("Unknown command :{}, type :help for a list of commands", name)),
+    }
+}
+
+fn vars(session: &mut Session) {
+    let symbols = session.interpreter.symbols();
+    if symbols.is_empty() {
+        session.print("No variables".to_string());
+        return;
+    }
+    for (name, value, mutable) in symbols {
+        let name = format!("{} {}", if mutable { "mut" } else { "var" }, name);
+        match value {
+            DataType::Function(params, _, _) => session.print(format!("{} = fun({})", name, params.join(", "))),
+            value => session.print_value(&name, &value),
+        }
+    }
+}
+
+// Statements are shown as they are, anything else is parsed as an expression
+fn ast(session: &mut Session, code: &str) {
+    let result = match crate::ast::parse(code) {
+        Ok(nodes) => Ok(nodes.iter().map(|node| format!("{:#?}", node)).collect::<Vec<_>>().join("\n")),
+        Err(e) => crate::ast::parse_expression(code).map(|node| format!("{:#?}", node)).map_err(|_| e),
+    };
+    match result {
+        Ok(tree) => session.print(tree),
+        Err(e) => session.error(format!("Parse error: {}", e)),
+    }
+}
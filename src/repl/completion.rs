@@ -0,0 +1,51 @@
+use super::{commands, rpn};
+use super::editor::Completion;
+use super::Session;
+use crate::interpreter::Interpreter;
+use crate::vm::{settings, symbol::DataType};
+
+// Tab completion of names: the session's variables and functions, keywords and built-ins,
+// meta-commands after a ':' at the start of the line, and the names of settings after 'set'.
+
+/// The candidates for `word`, the name being typed after `before`. A name the session
+/// declares comes first, so it hides a built-in with the same name.
+pub fn complete(session: &Session, before: &str, word: &str) -> Vec<Completion> {
+    if before == ":" {
+        return commands::NAMES.iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| Completion { text: name.to_string(), hint: None })
+            .collect();
+    }
+    if matches!(before.trim_end(), "set" | ":set") {
+        return settings::NAMES.iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| Completion { text: name.to_string(), hint: Some("setting".to_string()) })
+            .collect();
+    }
+
+    let mut candidates = vec![];
+    for (name, value, _) in session.interpreter.symbols() {
+        let hint = match value {
+            DataType::Function(params, _, _) => format!("{}({})", name, params.join(", ")),
+            value => value.type_name().to_string(),
+        };
+        candidates.push(Completion { text: name, hint: Some(hint) });
+    }
+    for keyword in crate::ast::KEYWORDS {
+        candidates.push(Completion { text: keyword.to_string(), hint: Some("keyword".to_string()) });
+    }
+    if session.stack.is_some() {
+        for word in rpn::WORDS {
+            candidates.push(Completion { text: word.to_string(), hint: Some("rpn".to_string()) });
+        }
+    }
+    for name in Interpreter::builtin_names() {
+        candidates.push(Completion { text: name.to_string(), hint: Some("built-in".to_string()) });
+    }
+
+    candidates.retain(|candidate| candidate.text.starts_with(word));
+    // The sort is stable, so the first candidate with a name is the one that's kept
+    candidates.sort_by(|a, b| a.text.cmp(&b.text));
+    candidates.dedup_by(|a, b| a.text == b.text);
+    candidates
+}
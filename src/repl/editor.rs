@@ -0,0 +1,267 @@
+use std::io::{self, IsTerminal, Write};
+
+use crossterm::{
+    cursor::MoveToColumn,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType},
+};
+
+// A line editor for the REPL: moving the cursor, history with the arrow keys, completion with Tab
+// and syntax highlighting.
+// When the input isn't a terminal, e.g. a script piped into the REPL, lines are read as they are.
+
+const INDENT: usize = 4;
+
+pub enum Line {
+    Text(String),
+    Interrupted, // Ctrl-C, the input so far is dropped
+    Eof, // Ctrl-D on an empty line, or the end of the piped input
+}
+
+pub struct Completion {
+    pub text: String, // Replaces the word before the cursor
+    pub hint: Option<String>, // Shown after the line, e.g. the parameters of a function
+}
+
+// Repeated Tabs cycle through the candidates for the word that starts at `start`
+struct Cycle {
+    start: usize,
+    candidates: Vec<Completion>,
+    index: usize,
+}
+
+pub struct Editor {
+    history: Vec<String>,
+    interactive: bool,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '\''
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            history: vec![],
+            interactive: io::stdin().is_terminal() && io::stdout().is_terminal(),
+        }
+    }
+
+    /// Reads a line after showing the prompt, starting with `indent` levels of indentation.
+    /// `complete` gets the text before the word at the cursor and the word, and returns the candidates for it.
+    pub fn read_line(&mut self, prompt: &str, indent: usize, complete: &dyn Fn(&str, &str) -> Vec<Completion>) -> Line {
+        print!("{}", prompt);
+        io::stdout().flush().unwrap();
+        if !self.interactive {
+            let mut line = String::new();
+            return match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => Line::Eof,
+                Ok(_) => Line::Text(line.trim_end_matches(['\n', '\r']).to_string()),
+            };
+        }
+
+        if terminal::enable_raw_mode().is_err() {
+            self.interactive = false;
+            return self.read_line("", indent, complete);
+        }
+        let line = self.edit(prompt, indent, complete);
+        terminal::disable_raw_mode().unwrap();
+        line.unwrap_or(Line::Eof)
+    }
+
+    fn edit(&mut self, prompt: &str, indent: usize, complete: &dyn Fn(&str, &str) -> Vec<Completion>) -> io::Result<Line> {
+        let mut out = io::stdout();
+        let mut line = LineBuffer::new(indent, self.history.len());
+        render(&mut out, prompt, &line.chars, Some(line.cursor), None)?;
+
+        loop {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            let hint = match line.handle_key(key, &self.history, complete) {
+                Action::Edited(hint) => hint,
+                Action::Submit => {
+                    // Without the cursor, so no bracket stays marked
+                    render(&mut out, prompt, &line.chars, None, None)?;
+                    queue!(out, Print("\r\n"))?;
+                    out.flush()?;
+                    let text = line.text();
+                    if !text.trim().is_empty() && self.history.last() != Some(&text) {
+                        self.history.push(text.clone());
+                    }
+                    return Ok(Line::Text(text));
+                },
+                Action::Interrupt => {
+                    queue!(out, Print("^C\r\n"))?;
+                    out.flush()?;
+                    return Ok(Line::Interrupted);
+                },
+                Action::Eof => {
+                    queue!(out, Print("\r\n"))?;
+                    out.flush()?;
+                    return Ok(Line::Eof);
+                },
+                Action::Ignored => continue,
+            };
+            render(&mut out, prompt, &line.chars, Some(line.cursor), hint.as_deref())?;
+        }
+    }
+}
+
+/// What a key did to a `LineBuffer`
+pub enum Action {
+    Edited(Option<String>), // The text or the cursor may have changed, with a hint to show
+    Submit, // Enter
+    Interrupt, // Ctrl-C
+    Eof, // Ctrl-D on an empty line
+    Ignored, // Key releases and keys the buffer doesn't handle
+}
+
+/// The text being edited, also used by the TUI for its input line
+pub struct LineBuffer {
+    pub chars: Vec<char>,
+    pub cursor: usize,
+    history_index: usize,
+    draft: Vec<char>, // The new line, while browsing the history
+    cycle: Option<Cycle>,
+}
+
+impl LineBuffer {
+    pub fn new(indent: usize, history_len: usize) -> LineBuffer {
+        let chars = vec![' '; indent * INDENT];
+        LineBuffer { cursor: chars.len(), chars, history_index: history_len, draft: vec![], cycle: None }
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.chars.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    /// Applies a key, browsing `history` with the arrow keys and completing with `complete`
+    pub fn handle_key(&mut self, key: KeyEvent, history: &[String], complete: &dyn Fn(&str, &str) -> Vec<Completion>) -> Action {
+        if key.kind == KeyEventKind::Release {
+            return Action::Ignored;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if !matches!(key.code, KeyCode::Tab | KeyCode::BackTab) {
+            self.cycle = None;
+        }
+
+        let (chars, cursor) = (&mut self.chars, &mut self.cursor);
+        match key.code {
+            KeyCode::Enter => return Action::Submit,
+            KeyCode::Char('c') if ctrl => return Action::Interrupt,
+            KeyCode::Char('d') if ctrl && chars.is_empty() => return Action::Eof,
+            KeyCode::Char('d') if ctrl && *cursor < chars.len() => {
+                chars.remove(*cursor);
+            },
+            KeyCode::Char('a') if ctrl => *cursor = 0,
+            KeyCode::Char('e') if ctrl => *cursor = chars.len(),
+            KeyCode::Char(c) if !ctrl => {
+                // A closing bracket at the start of a line goes back one indent
+                if matches!(c, ')' | ']' | '}') && *cursor >= INDENT && chars[..*cursor].iter().all(|c| *c == ' ') {
+                    chars.drain(*cursor - INDENT..*cursor);
+                    *cursor -= INDENT;
+                }
+                chars.insert(*cursor, c);
+                *cursor += 1;
+            },
+            KeyCode::Backspace if *cursor > 0 => {
+                *cursor -= 1;
+                chars.remove(*cursor);
+            },
+            KeyCode::Delete if *cursor < chars.len() => {
+                chars.remove(*cursor);
+            },
+            KeyCode::Left if *cursor > 0 => *cursor -= 1,
+            KeyCode::Right if *cursor < chars.len() => *cursor += 1,
+            KeyCode::Home => *cursor = 0,
+            KeyCode::End => *cursor = chars.len(),
+            KeyCode::Up if self.history_index > 0 => {
+                if self.history_index == history.len() {
+                    self.draft = chars.clone();
+                }
+                self.history_index -= 1;
+                *chars = history[self.history_index].chars().collect();
+                *cursor = chars.len();
+            },
+            KeyCode::Down if self.history_index < history.len() => {
+                self.history_index += 1;
+                *chars = match history.get(self.history_index) {
+                    Some(line) => line.chars().collect(),
+                    None => self.draft.clone(),
+                };
+                *cursor = chars.len();
+            },
+            KeyCode::Tab | KeyCode::BackTab => {
+                let forward = key.code == KeyCode::Tab;
+                return Action::Edited(tab(chars, cursor, &mut self.cycle, forward, complete));
+            },
+            _ => return Action::Ignored,
+        }
+        Action::Edited(None)
+    }
+}
+
+// Completes the word before the cursor, or moves to the next candidate if Tab was pressed before.
+// Returns the hint to show.
+fn tab(
+    buffer: &mut Vec<char>,
+    cursor: &mut usize,
+    cycle: &mut Option<Cycle>,
+    forward: bool,
+    complete: &dyn Fn(&str, &str) -> Vec<Completion>,
+) -> Option<String> {
+    match cycle {
+        Some(cycle) => {
+            let count = cycle.candidates.len();
+            cycle.index = if forward { (cycle.index + 1) % count } else { (cycle.index + count - 1) % count };
+        },
+        None => {
+            let start = buffer[..*cursor].iter().rposition(|c| !is_word_char(*c)).map_or(0, |i| i + 1);
+            let before: String = buffer[..start].iter().collect();
+            let word: String = buffer[start..*cursor].iter().collect();
+            if word.is_empty() {
+                return None;
+            }
+            let candidates = complete(&before, &word);
+            if candidates.is_empty() {
+                return None;
+            }
+            *cycle = Some(Cycle { start, candidates, index: 0 });
+        },
+    }
+
+    let current = cycle.as_ref().unwrap();
+    let candidate = &current.candidates[current.index];
+    buffer.splice(current.start..*cursor, candidate.text.chars());
+    *cursor = current.start + candidate.text.chars().count();
+
+    let position = match current.candidates.len() {
+        1 => None,
+        count => Some(format!("({}/{})", current.index + 1, count)),
+    };
+    match (&candidate.hint, position) {
+        (Some(hint), Some(position)) => Some(format!("{} {}", hint, position)),
+        (hint, position) => hint.clone().or(position),
+    }
+}
+
+fn render(out: &mut io::Stdout, prompt: &str, buffer: &[char], cursor: Option<usize>, hint: Option<&str>) -> io::Result<()> {
+    let line = super::highlight::highlight(buffer, cursor);
+    queue!(out, MoveToColumn(0), Clear(ClearType::UntilNewLine), Print(prompt), Print(line))?;
+    if let Some(hint) = hint {
+        queue!(out, SetAttribute(Attribute::Dim), Print(format!("  {}", hint)), SetAttribute(Attribute::Reset))?;
+    }
+    let cursor = cursor.unwrap_or(buffer.len());
+    queue!(out, MoveToColumn((prompt.chars().count() + cursor) as u16))?;
+    out.flush()
+}
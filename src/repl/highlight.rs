@@ -0,0 +1,110 @@
+use crossterm::style::{Attribute, Color, ContentStyle};
+
+use crate::ast::Token;
+
+// Syntax highlighting of the line being typed. The colors come from the tokens of the lexer, so what's
+// shown is what will be parsed. Only the current line is highlighted, a bracket that matches one on an
+// earlier line of the input isn't marked.
+
+fn color(token: &Token) -> Option<Color> {
+    match token {
+        Token::Keyword(_) => Some(Color::Magenta),
+        Token::Identifier(_) => None,
+        Token::Number(_) | Token::Float(_) | Token::Imaginary(_) => Some(Color::Yellow),
+        Token::String(_) => Some(Color::Green),
+        Token::Operator(_) | Token::Assigner(_) => Some(Color::Cyan),
+        Token::Symbol(_) => None,
+        Token::Unit(_) => Some(Color::DarkYellow),
+    }
+}
+
+// The position of the bracket that matches the one at `index`, searching forwards from an opening
+// bracket and backwards from a closing one
+fn matching_bracket(brackets: &[(usize, char)], index: usize) -> Option<usize> {
+    let bracket = brackets[index].1;
+    let other = match bracket {
+        '(' => ')', ')' => '(',
+        '[' => ']', ']' => '[',
+        '{' => '}', _ => '{',
+    };
+    let candidates: Box<dyn Iterator<Item = &(usize, char)>> = match bracket {
+        '(' | '[' | '{' => Box::new(brackets[index..].iter()),
+        _ => Box::new(brackets[..=index].iter().rev()),
+    };
+    let mut depth = 0;
+    for (position, c) in candidates {
+        if *c == bracket {
+            depth += 1;
+        } else if *c == other {
+            depth -= 1;
+        }
+        if depth == 0 {
+            return Some(*position);
+        }
+    }
+    None
+}
+
+/// The line with terminal colors. The bracket next to the cursor and its match are highlighted,
+/// and what the lexer can't read is underlined in red.
+pub fn highlight(line: &[char], cursor: Option<usize>) -> String {
+    let text: String = line.iter().collect();
+    let (tokens, error) = crate::ast::scan(&text);
+    let mut styles = vec![ContentStyle::new(); line.len()];
+    let mut covered = vec![false; line.len()];
+
+    for (token, span) in &tokens {
+        for i in span.clone() {
+            styles[i].foreground_color = color(token);
+            covered[i] = true;
+        }
+    }
+
+    // Comments are skipped by the lexer, they're the text after a '#' that isn't part of a token
+    let lexed = error.as_ref().map_or(line.len(), |error| error.span.start);
+    let mut in_comment = false;
+    for i in 0..lexed {
+        in_comment = !covered[i] && (in_comment || line[i] == '#');
+        if in_comment {
+            styles[i].foreground_color = Some(Color::DarkGrey);
+        }
+    }
+
+    let brackets: Vec<(usize, char)> = tokens.iter()
+        .filter_map(|(token, span)| match token {
+            Token::Symbol(c @ ('(' | ')' | '[' | ']' | '{' | '}')) => Some((span.start, *c)),
+            _ => None,
+        })
+        .collect();
+    // The bracket just before the cursor, as it's the one that was just typed, or the one under it
+    let at_cursor = cursor.and_then(|cursor| {
+        brackets.iter().position(|(position, _)| *position + 1 == cursor)
+            .or_else(|| brackets.iter().position(|(position, _)| *position == cursor))
+    });
+    if let Some(index) = at_cursor {
+        if let Some(other) = matching_bracket(&brackets, index) {
+            styles[brackets[index].0].attributes.set(Attribute::Reverse);
+            styles[other].attributes.set(Attribute::Reverse);
+        }
+    }
+
+    if let Some(error) = error {
+        for style in &mut styles[error.span.start..error.span.end.min(line.len())] {
+            style.foreground_color = Some(Color::Red);
+            style.underline_color = Some(Color::Red);
+            style.attributes.set(Attribute::Underlined);
+        }
+    }
+
+    // Runs of characters with the same style are printed together
+    let mut output = String::new();
+    let mut start = 0;
+    for i in 1..=line.len() {
+        if i == line.len() || styles[i] != styles[start] {
+            let segment: String = line[start..i].iter().collect();
+            output.push_str(&styles[start].apply(segment).to_string());
+            start = i;
+        }
+    }
+    output
+}
@@ -1,20 +1,115 @@
+use crate::ast::{Completeness, Token};
 use crate::interpreter::Interpreter;
+use crate::vm::symbol::DataType;
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
-use ctrlc;
-
-fn count_nesting(input: &str) -> i32 {
-    let mut count = 0;
-    for c in input.chars() {
-        match c {
-            '(' | '{' | '[' => count += 1,
-            ')' | '}' | ']' => count -= 1,
-            _ => {}
+
+mod commands;
+mod completion;
+mod editor;
+mod highlight;
+mod rpn;
+pub mod tui;
+
+use editor::{Editor, Line};
+
+// The state of a REPL session. Lines starting with ':' are meta-commands, see `commands`.
+pub struct Session {
+    interpreter: Interpreter,
+    strict: bool,
+    history: Vec<String>, // The code that ran without errors, for ':save'
+    messages: Option<Vec<Message>>, // What the session shows, when it's collected instead of printed
+    stack: Option<Vec<DataType>>, // The stack of the RPN mode, None when input is code
+}
+
+/// A line of output of a session
+pub enum Message {
+    Text(String),
+    Error(String),
+}
+
+impl Session {
+    /// A session that prints to the terminal, or that collects its output, see `take_messages`
+    fn new(strict: bool, collect: bool) -> Session {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_strict(strict);
+        if collect {
+            interpreter.capture_output();
+        }
+        Session { interpreter, strict, history: vec![], messages: collect.then(Vec::new), stack: None }
+    }
+
+    /// Runs a line typed by the user: a meta-command, RPN input in RPN mode, or else code
+    fn submit(&mut self, input: &str) {
+        match input.strip_prefix(':') {
+            Some(command) => commands::run(self, command),
+            None if self.stack.is_some() && !rpn::is_code(input) => rpn::run(self, input),
+            None => {
+                self.run(input);
+            },
+        }
+    }
+
+    fn print(&mut self, text: String) {
+        match &mut self.messages {
+            Some(messages) => messages.push(Message::Text(text)),
+            None => println!("{}", text),
+        }
+    }
+
+    fn error(&mut self, text: String) {
+        match &mut self.messages {
+            Some(messages) => messages.push(Message::Error(text)),
+            None => eprintln!("{}", text),
+        }
+    }
+
+    // Values that span multiple lines, like matrices, start on their own line
+    fn print_value(&mut self, name: &str, value: &DataType) {
+        let value = value.to_string();
+        if value.contains('\n') {
+            self.print(format!("{} =\n{}", name, value));
+        } else {
+            self.print(format!("{} = {}", name, value));
+        }
+    }
+
+    /// The output since the last call, if it's collected
+    fn take_messages(&mut self) -> Vec<Message> {
+        self.messages.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Parses and runs code, printing the values of expressions and errors. Returns whether it ran without errors.
+    fn run(&mut self, input: &str) -> bool {
+        let result = crate::ast::parse(input)
+            .map_err(|e| format!("Parse error: {}", e))
+            .and_then(|ast| self.interpreter.evaluate(ast).map_err(|e| format!("Runtime error: {}", e)));
+        // What 'out' wrote comes before the results
+        for line in self.interpreter.take_output() {
+            self.print(line);
+        }
+        match result {
+            Ok(results) => {
+                for (name, value) in results {
+                    self.print_value(&name, &value);
+                }
+                self.history.push(input.to_string());
+                true
+            },
+            Err(e) => {
+                self.error(e);
+                false
+            },
         }
     }
-    count
 }
 
-pub fn start() {
+// An 'else' or 'elseif' on the line after an 'if' belongs to it
+fn continues_if(line: &str) -> bool {
+    matches!(crate::ast::scan(line).0.first(), Some((Token::Keyword(keyword), _)) if keyword == "else" || keyword == "elseif")
+}
+
+/// Runs the REPL until 'exit' or Ctrl-D. With `rpn` it starts in RPN mode, see `rpn`.
+pub fn start(strict: bool, rpn: bool) {
     let ctrlc_flag = Arc::new(AtomicBool::new(false));
     let ctrlc_flag_clone = Arc::clone(&ctrlc_flag);
     
@@ -23,34 +118,64 @@ pub fn start() {
         std::io::Write::write(&mut std::io::stdout(), b"\n> ").unwrap();
     }).expect("Error setting Ctrl-C handler");
     
-    let mut interpreter = Interpreter::new();
-    loop {
-        print!("> ");
-        std::io::Write::flush(&mut std::io::stdout()).unwrap();
-
+    let mut session = Session::new(strict, false);
+    if rpn {
+        session.stack = Some(vec![]);
+    }
+    let mut editor = Editor::new();
+    let mut next_line = None; // A line read while waiting for an 'else', it starts the next input
+    let mut eof = false;
+    while !eof {
         let mut input = String::new();
-        // std::io::stdin().read_line(&mut input).unwrap();
-        loop {
-            let mut temp = String::new();
-            std::io::stdin().read_line(&mut temp).unwrap();
-            input.push_str(&temp.trim());
-
-            if temp == "exit" {
-                break;
+        let mut prompt = if session.stack.is_some() { "rpn> " } else { "> " }.to_string();
+        let mut indent = 0;
+        let mut may_continue = false;
+        let interrupted = loop {
+            let text = match next_line.take() {
+                Some(text) => text,
+                None => match editor.read_line(&prompt, indent, &|before, word| completion::complete(&session, before, word)) {
+                    Line::Text(text) => text,
+                    Line::Interrupted => break true,
+                    Line::Eof => {
+                        eof = true;
+                        break false;
+                    },
+                },
+            };
+            if may_continue && !continues_if(&text) {
+                // Anything but a blank line is the start of the next input
+                if !text.trim().is_empty() {
+                    next_line = Some(text);
+                }
+                break false;
             }
-
-            let nesting_count = count_nesting(input.as_str());
-            if nesting_count <= 0 {
-                break;
+            if input.is_empty() {
+                input = text.trim().to_string();
             } else {
-                input.push_str("\n");
-                print!("{} ", ">".repeat(nesting_count as usize + 1));
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
+                input.push('\n');
+                input.push_str(text.trim_end());
             }
-        }
-        
+            // Meta-commands and RPN input are a single line
+            if input.starts_with(':') || (session.stack.is_some() && !rpn::is_code(&input)) {
+                break false;
+            }
+
+            may_continue = false;
+            match crate::ast::completeness(&input) {
+                Completeness::Complete => break false,
+                Completeness::Incomplete { indent: depth } => indent = depth,
+                // Only an 'if' typed over several lines waits for an 'else', a one line 'if' runs right away
+                Completeness::MayContinue if input.contains('\n') => {
+                    may_continue = true;
+                    indent = 0;
+                },
+                Completeness::MayContinue => break false,
+            }
+            prompt = format!("{} ", ">".repeat(indent.max(1) + 1));
+        };
+
         // continue if ctrlc_flag
-        if ctrlc_flag.load(Ordering::SeqCst) {
+        if interrupted || ctrlc_flag.load(Ordering::SeqCst) {
             ctrlc_flag.store(false, Ordering::SeqCst);
             continue;
         }
@@ -58,20 +183,10 @@ pub fn start() {
         if input == "exit" {
             break;
         }
-
-        let ast = crate::ast::parse(&input);
-        match ast {
-            Ok(ast) => {
-                match interpreter.interpret(ast) {
-                    Ok(_) => {},
-                    Err(e) => {
-                        eprintln!("Runtime error: {}", e);
-                    }
-                }
-            },
-            Err(e) => {
-                eprintln!("Parse error: {}", e);
-            }
+        if input.is_empty() {
+            continue;
         }
+
+        session.submit(&input);
     }
 }
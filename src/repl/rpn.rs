@@ -0,0 +1,170 @@
+use super::Session;
+use crate::ast::{parser::Operator, Token};
+use crate::vm::symbol::DataType;
+
+// Reverse Polish input, like on HP calculators: '3 4 + 2 *' pushes 3 and 4, replaces them by their
+// sum and multiplies that by 2. Words are separated by spaces. Operators and functions take their
+// arguments from the stack and push their result, any other word is evaluated as an expression,
+// like '2.5', '-1', 'pi' or 'x', and pushed. The stack is shown after every line. Statements
+// like declarations run as code.
+
+// The words of RPN input besides operators, functions and expressions
+pub const WORDS: &[&str] = &["swap", "drop", "dup", "clear", "neg"];
+
+/// Whether the input is a statement like 'fun f(x) { ... }' or 'var k = 2', which runs as code
+/// also in RPN mode, so functions and variables can be declared without switching modes.
+pub fn is_code(input: &str) -> bool {
+    let (tokens, _) = crate::ast::scan(input);
+    match (tokens.first(), tokens.get(1)) {
+        (Some((Token::Keyword(keyword), _)), _) => keyword != "true" && keyword != "false",
+        (Some((Token::Identifier(_), _)), Some((Token::Assigner(_), _))) => true,
+        _ => false,
+    }
+}
+
+/// Runs a line of RPN input on the session's stack. If a word fails, the stack is left as it was
+/// before the line.
+pub fn run(session: &mut Session, line: &str) {
+    let mut stack = session.stack.clone().unwrap_or_default();
+    for word in line.split_whitespace() {
+        if let Err(e) = step(session, &mut stack, word) {
+            session.error(format!("Runtime error: {}: {}", word, e));
+            return;
+        }
+    }
+    session.stack = Some(stack);
+    show(session);
+}
+
+fn step(session: &mut Session, stack: &mut Vec<DataType>, word: &str) -> Result<(), String> {
+    let interpreter = &mut session.interpreter;
+    let operator = match word {
+        "+" => Some(Operator::Add),
+        "-" => Some(Operator::Sub),
+        "*" => Some(Operator::Mul),
+        "/" => Some(Operator::Div),
+        "%" => Some(Operator::Mod),
+        "^" => Some(Operator::Pow),
+        _ => None,
+    };
+    if let Some(op) = operator {
+        let operands = pop(stack, 2)?;
+        stack.push(interpreter.apply_operator(op, operands)?);
+        return Ok(());
+    }
+
+    match word {
+        "!" => {
+            let operands = pop(stack, 1)?;
+            stack.push(interpreter.apply_operator(Operator::Factorial, operands)?);
+        },
+        // '-' always subtracts, 'neg' changes the sign
+        "neg" => {
+            let operands = pop(stack, 1)?;
+            stack.push(interpreter.apply_operator(Operator::Neg, operands)?);
+        },
+        "swap" => {
+            let mut operands = pop(stack, 2)?;
+            operands.reverse();
+            stack.extend(operands);
+        },
+        "drop" => {
+            pop(stack, 1)?;
+        },
+        "dup" => {
+            let top = pop(stack, 1)?.remove(0);
+            stack.push(top.clone());
+            stack.push(top);
+        },
+        "clear" => stack.clear(),
+        name => match interpreter.arity(name) {
+            // User functions and built-ins get their arguments from the stack, the last one from the top
+            Some(arity) => {
+                let args = pop(stack, arity)?;
+                match interpreter.call(name, args)? {
+                    DataType::Null() => {},
+                    value => stack.push(value),
+                }
+            },
+            None => match interpreter.value_of(crate::ast::parse_expression(word)?)? {
+                DataType::Null() => return Err("Has no value to push".to_string()),
+                value => stack.push(value),
+            },
+        },
+    }
+    Ok(())
+}
+
+// The top `count` values, the topmost last
+fn pop(stack: &mut Vec<DataType>, count: usize) -> Result<Vec<DataType>, String> {
+    if stack.len() < count {
+        let values = if count == 1 { "value" } else { "values" };
+        return Err(format!("Needs {} {} on the stack, it has {}", count, values, stack.len()));
+    }
+    Ok(stack.split_off(stack.len() - count))
+}
+
+/// Shows the stack with the top as level 1 at the bottom, next to the input
+pub fn show(session: &mut Session) {
+    let stack = session.stack.clone().unwrap_or_default();
+    if stack.is_empty() {
+        session.print("Empty stack".to_string());
+    }
+    for (index, value) in stack.iter().enumerate() {
+        let level = stack.len() - index;
+        // Values that span multiple lines, like matrices, start on their own line
+        let value = value.to_string();
+        match value.contains('\n') {
+            true => session.print(format!("{}:\n{}", level, value)),
+            false => session.print(format!("{}: {}", level, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpn_session() -> Session {
+        let mut session = Session::new(false, true);
+        session.stack = Some(vec![]);
+        session
+    }
+
+    fn stack(session: &Session) -> Vec<String> {
+        session.stack.iter().flatten().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn operators_take_their_operands_from_the_stack() {
+        let mut session = rpn_session();
+        run(&mut session, "3 4 + 2 *");
+        assert_eq!(stack(&session), ["14"]);
+        run(&mut session, "10 swap - dup");
+        assert_eq!(stack(&session), ["-4", "-4"]);
+    }
+
+    #[test]
+    fn functions_take_their_arguments_from_the_stack() {
+        let mut session = rpn_session();
+        session.submit("fun f(a, b) { return a - b }");
+        run(&mut session, "10 3 f 16 sqrt");
+        assert_eq!(stack(&session), ["7", "4"]);
+    }
+
+    #[test]
+    fn a_failing_line_leaves_the_stack_unchanged() {
+        let mut session = rpn_session();
+        run(&mut session, "1 2");
+        run(&mut session, "+ + +");
+        assert_eq!(stack(&session), ["1", "2"]);
+    }
+
+    #[test]
+    fn statements_run_as_code() {
+        assert!(is_code("var k = 2"));
+        assert!(is_code("k = 3"));
+        assert!(!is_code("3 4 +"));
+        assert!(!is_code("true"));
+    }
+}
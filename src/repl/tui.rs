@@ -0,0 +1,295 @@
+use std::io::{self, IsTerminal, Write};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use super::editor::{Action, LineBuffer};
+use super::{completion, highlight, Message, Session};
+use crate::vm::symbol::DataType;
+
+// A full screen calculator: results scroll by above the input line, the variables and functions of
+// the session are listed on the right, and the function keys type common built-ins. It runs a
+// `Session` like the line REPL, with the output collected instead of printed.
+
+// Function key, label, text typed at the cursor
+const SHORTCUTS: &[(u8, &str, &str)] = &[
+    (1, "sqrt", "sqrt("), (2, "sin", "sin("), (3, "cos", "cos("), (4, "tan", "tan("),
+    (5, "ln", "ln("), (6, "diff", "diff("), (7, "pi", "pi"), (8, "ans", "ans"),
+];
+const QUIT_KEY: u8 = 10;
+
+// The variables pane is only shown on terminals at least this wide
+const PANE_MIN_WIDTH: usize = 60;
+
+enum Entry {
+    Input(String),
+    Text(String),
+    Error(String),
+}
+
+// Switches to the alternate screen, and back when it's dropped, also if the TUI panics
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Tui {
+    session: Session,
+    line: LineBuffer,
+    history: Vec<String>, // The submitted lines, for the arrow keys
+    scrollback: Vec<Entry>,
+    scroll: usize, // How many lines the scrollback is scrolled up
+    hint: Option<String>,
+}
+
+/// Runs the full screen calculator until F10, Ctrl-D or 'exit'. With `rpn` it starts in RPN mode.
+pub fn start(strict: bool, rpn: bool) -> io::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("--tui needs a terminal"));
+    }
+    let _screen = Screen::enter()?;
+    let mut tui = Tui {
+        session: Session::new(strict, true),
+        line: LineBuffer::new(0, 0),
+        history: vec![],
+        scrollback: vec![Entry::Text("Type code and press Enter, :help lists the commands".to_string())],
+        scroll: 0,
+        hint: None,
+    };
+    if rpn {
+        tui.session.stack = Some(vec![]);
+    }
+
+    loop {
+        tui.draw()?;
+        match event::read()? {
+            Event::Key(key) => {
+                let (_, height) = terminal::size()?;
+                let page = (height as usize).saturating_sub(4).max(1);
+                match key.code {
+                    KeyCode::F(QUIT_KEY) => return Ok(()),
+                    KeyCode::F(n) => {
+                        if let Some((_, _, text)) = SHORTCUTS.iter().find(|(key, ..)| *key == n) {
+                            tui.line.insert(text);
+                        }
+                    },
+                    KeyCode::PageUp => tui.scroll += page,
+                    KeyCode::PageDown => tui.scroll = tui.scroll.saturating_sub(page),
+                    KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        tui.scrollback.clear();
+                        tui.scroll = 0;
+                    },
+                    _ => {
+                        let session = &tui.session;
+                        let complete = |before: &str, word: &str| completion::complete(session, before, word);
+                        match tui.line.handle_key(key, &tui.history, &complete) {
+                            Action::Edited(hint) => tui.hint = hint,
+                            Action::Submit => {
+                                if !tui.submit() {
+                                    return Ok(());
+                                }
+                            },
+                            Action::Interrupt => tui.line = LineBuffer::new(0, tui.history.len()),
+                            Action::Eof => return Ok(()),
+                            Action::Ignored => {},
+                        }
+                    },
+                }
+            },
+            // Everything is drawn again, in the new size
+            Event::Resize(..) => {},
+            _ => {},
+        }
+    }
+}
+
+impl Tui {
+    // Runs the input line. Returns false to quit.
+    fn submit(&mut self) -> bool {
+        let input = self.line.text().trim().to_string();
+        if input == "exit" {
+            return false;
+        }
+        if !input.is_empty() && self.history.last() != Some(&input) {
+            self.history.push(input.clone());
+        }
+        self.line = LineBuffer::new(0, self.history.len());
+        self.hint = None;
+        self.scroll = 0;
+        if input.is_empty() {
+            return true;
+        }
+
+        self.scrollback.push(Entry::Input(input.clone()));
+        self.session.submit(&input);
+        for message in self.session.take_messages() {
+            self.scrollback.push(match message {
+                Message::Text(text) => Entry::Text(text),
+                Message::Error(text) => Entry::Error(text),
+            });
+        }
+        true
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let mut out = io::stdout();
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        if height < 4 {
+            return Ok(());
+        }
+        let pane_width = if width >= PANE_MIN_WIDTH { (width / 3).min(40) } else { 0 };
+        let results_width = if pane_width > 0 { width - pane_width - 1 } else { width };
+        let rows = height - 3;
+
+        queue!(out, Hide, MoveTo(0, 0), SetAttribute(Attribute::Reverse))?;
+        queue!(out, Print(fit(" rust_vm   PgUp/PgDn scroll   Ctrl-L clear   F10 quit", width)), SetAttribute(Attribute::Reset))?;
+
+        let lines = self.scrollback_lines(results_width);
+        self.scroll = self.scroll.min(lines.len().saturating_sub(rows));
+        let end = lines.len() - self.scroll;
+        let visible = &lines[end.saturating_sub(rows)..end];
+        let pane = if pane_width > 0 { self.pane_lines(pane_width) } else { vec![] };
+
+        for row in 0..rows {
+            queue!(out, MoveTo(0, (row + 1) as u16))?;
+            match visible.get(row) {
+                Some((color, text)) => {
+                    if let Some(color) = color {
+                        queue!(out, SetForegroundColor(*color))?;
+                    }
+                    queue!(out, Print(fit(text, results_width)), ResetColor)?;
+                },
+                None => queue!(out, Print(" ".repeat(results_width)))?,
+            }
+            if pane_width > 0 {
+                queue!(out, Print('│'))?;
+                match pane.get(row) {
+                    Some((true, text)) => queue!(out, SetAttribute(Attribute::Bold), Print(fit(text, pane_width)), SetAttribute(Attribute::Reset))?,
+                    Some((false, text)) => queue!(out, Print(fit(text, pane_width)))?,
+                    None => queue!(out, Print(" ".repeat(pane_width)))?,
+                }
+            }
+        }
+
+        // The input line scrolls sideways to keep the cursor visible
+        let available = width.saturating_sub(3).max(1);
+        let offset = self.line.cursor.saturating_sub(available);
+        let shown = &self.line.chars[offset..self.line.chars.len().min(offset + available)];
+        queue!(out, MoveTo(0, (height - 2) as u16), Clear(ClearType::CurrentLine), Print("> "))?;
+        queue!(out, Print(highlight::highlight(shown, Some(self.line.cursor - offset))))?;
+        if let Some(hint) = &self.hint {
+            let room = width.saturating_sub(shown.len() + 4);
+            if room > 0 {
+                queue!(out, SetAttribute(Attribute::Dim), Print(fit(&format!("  {}", hint), room).trim_end()), SetAttribute(Attribute::Reset))?;
+            }
+        }
+
+        queue!(out, MoveTo(0, (height - 1) as u16), Clear(ClearType::CurrentLine))?;
+        let keys = SHORTCUTS.iter().map(|(key, label, _)| (*key, *label)).chain([(QUIT_KEY, "quit")]);
+        let mut used = 0;
+        for (key, label) in keys {
+            let (key, label) = (format!("F{}", key), format!("{} ", label));
+            used += key.len() + label.len();
+            if used > width {
+                break;
+            }
+            queue!(out, SetAttribute(Attribute::Reverse), Print(key), SetAttribute(Attribute::Reset), Print(label))?;
+        }
+
+        queue!(out, MoveTo((2 + self.line.cursor - offset) as u16, (height - 2) as u16), Show)?;
+        out.flush()
+    }
+
+    // The scrollback wrapped to the width, with the color of each line
+    fn scrollback_lines(&self, width: usize) -> Vec<(Option<Color>, String)> {
+        let mut lines = vec![];
+        for entry in &self.scrollback {
+            let (color, text) = match entry {
+                Entry::Input(text) => (Some(Color::DarkGrey), format!("> {}", text)),
+                Entry::Text(text) => (None, text.clone()),
+                Entry::Error(text) => (Some(Color::Red), text.clone()),
+            };
+            for line in text.lines() {
+                let chars: Vec<char> = line.chars().collect();
+                if chars.is_empty() {
+                    lines.push((color, String::new()));
+                }
+                for chunk in chars.chunks(width.max(1)) {
+                    lines.push((color, chunk.iter().collect()));
+                }
+            }
+        }
+        lines
+    }
+
+    // The variables and functions of the session, one per line. Titles are marked with true.
+    fn pane_lines(&self, width: usize) -> Vec<(bool, String)> {
+        let symbols = self.session.interpreter.symbols();
+        let (functions, variables): (Vec<_>, Vec<_>) = symbols.into_iter().partition(|(_, value, _)| matches!(value, DataType::Function(..)));
+        let mut lines = vec![];
+        // In RPN mode the stack comes first, with the top at the bottom like in the scrollback
+        if let Some(stack) = &self.session.stack {
+            lines.push((true, " Stack".to_string()));
+            for (index, value) in stack.iter().enumerate() {
+                let value = value.to_string();
+                let mut first = value.lines().next().unwrap_or_default().to_string();
+                if value.contains('\n') {
+                    first.push_str(" …");
+                }
+                lines.push((false, format!(" {}: {}", stack.len() - index, first)));
+            }
+            lines.push((false, String::new()));
+        }
+        lines.push((true, " Variables".to_string()));
+        for (name, value, mutable) in variables {
+            let value = value.to_string();
+            let mut first = value.lines().next().unwrap_or_default().to_string();
+            if value.contains('\n') {
+                first.push_str(" …");
+            }
+            lines.push((false, format!(" {}{} = {}", if mutable { "mut " } else { "" }, name, first)));
+        }
+        if !functions.is_empty() {
+            lines.push((false, String::new()));
+            lines.push((true, " Functions".to_string()));
+            for (name, value, _) in functions {
+                if let DataType::Function(params, ..) = value {
+                    lines.push((false, format!(" {}({})", name, params.join(", "))));
+                }
+            }
+        }
+        lines.into_iter().map(|(title, text)| (title, fit(&text, width))).collect()
+    }
+}
+
+// Cuts or pads the text to exactly `width` characters, marking cut text with '…'
+fn fit(text: &str, width: usize) -> String {
+    let count = text.chars().count();
+    if count > width {
+        let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+        if width > 0 {
+            cut.push('…');
+        }
+        cut
+    } else {
+        format!("{}{}", text, " ".repeat(width - count))
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::ast::parser::ASTNode;
+use crate::symbolic::poly::{Monomial, Poly};
+use crate::units::{self, Dimensions, Unit, DIMENSIONLESS};
+use crate::vm::symbol::DataType;
+
+// Dimensional analysis of math blocks. Quantities like '12 V' are solved with their value in SI units,
+// and the unit of each unknown is worked out from the equations: all terms of an equation must have
+// the same dimensions, so 'v = i*r' with v in V and r in ohm gives i in A.
+
+// The dimensions of the part of a term that is known, and the unknowns in it whose dimensions aren't
+// known yet, with their exponents. Atoms other than unknowns and quantities, like sin(x), have no unit.
+fn term_dimensions(
+    poly: &Poly,
+    monomial: &Monomial,
+    dims: &HashMap<String, Dimensions>,
+    unknowns: &[String],
+) -> (Dimensions, Vec<(String, i32)>) {
+    let mut known = DIMENSIONLESS;
+    let mut open = vec![];
+    for (atom, exponent) in monomial {
+        let exponent = *exponent as i32;
+        let atom_dims = match &poly.atoms[atom] {
+            ASTNode::Identifier(name) if unknowns.contains(name) => match dims.get(name) {
+                Some(atom_dims) => *atom_dims,
+                None => {
+                    open.push((name.clone(), exponent));
+                    continue;
+                },
+            },
+            ASTNode::Quantity { unit, .. } => Unit::parse(unit).map_or(DIMENSIONLESS, |unit| unit.dims),
+            _ => DIMENSIONLESS,
+        };
+        known = units::add_dims(&known, &atom_dims.map(|d| d * exponent));
+    }
+    (known, open)
+}
+
+/// The dimensions of the unknowns. Unknowns that no quantity constrains are plain numbers,
+/// they're missing from the result.
+pub fn infer(equations: &[(&ASTNode, Poly)], unknowns: &[String]) -> Result<HashMap<String, Dimensions>, String> {
+    let mut dims = HashMap::new();
+    loop {
+        let mut progress = false;
+        for (equation, poly) in equations {
+            let terms: Vec<(Dimensions, Vec<(String, i32)>)> = poly.terms.keys()
+                .map(|monomial| term_dimensions(poly, monomial, &dims, unknowns))
+                .collect();
+            // The first term without open unknowns decides the dimensions of the equation
+            let Some(target) = terms.iter().find(|(_, open)| open.is_empty()).map(|(known, _)| *known) else {
+                continue;
+            };
+
+            for (known, open) in &terms {
+                match &open[..] {
+                    [] if *known != target => return Err(format!(
+                        "The terms of {} don't have the same dimensions ({} vs {})",
+                        equation, units::describe(&target), units::describe(known),
+                    )),
+                    [(name, exponent)] => {
+                        let missing = units::sub_dims(&target, known);
+                        if missing.iter().any(|d| d % exponent != 0) {
+                            return Err(format!("{} would need a fractional power of a unit in {}", name, equation));
+                        }
+                        dims.insert(name.clone(), missing.map(|d| d / exponent));
+                        progress = true;
+                    },
+                    _ => {},
+                }
+            }
+        }
+        if !progress {
+            return Ok(dims);
+        }
+    }
+}
+
+/// Attaches the unit of the dimensions to a solution, which is in SI units
+pub fn with_unit(value: DataType, dims: Option<&Dimensions>) -> DataType {
+    let Some(dims) = dims.filter(|dims| **dims != DIMENSIONLESS) else {
+        return value;
+    };
+    match value {
+        DataType::Number(n) => DataType::Quantity(n.to_f64(), Unit::si(*dims)),
+        DataType::List(items) => DataType::List(items.into_iter().map(|item| with_unit(item, Some(dims))).collect()),
+        other => other,
+    }
+}
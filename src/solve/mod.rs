@@ -1,12 +1,15 @@
 use crate::{ast::parser::{ASTNode, Operator}, vm::VM};
+use simplex::{Constraint, Outcome, Relation};
+use crate::symbolic::{self, poly::{Coeff, Monomial, Poly}, roots::{roots, Root}};
 use crate::vm::symbol::DataType;
-use core::panic;
 use std::collections::HashMap;
-use nalgebra::{DMatrix, DVector};
+
+mod dimensions;
+mod simplex;
 
 pub fn find_vars(ast: &ASTNode) -> Vec<String> {
     let mut vars = Vec::new();
-    
+
     match ast {
         ASTNode::Identifier(name) => {
             vars.push(name.clone());
@@ -23,128 +26,348 @@ pub fn find_vars(ast: &ASTNode) -> Vec<String> {
     vars
 }
 
-pub fn get_known_values(vars: &Vec<String>, vm: &VM) -> HashMap<String, f64> {
+/// The values of the variables that are already declared, as number or quantity literals.
+pub fn get_known_values(vars: &Vec<String>, vm: &VM) -> HashMap<String, ASTNode> {
     let mut known_values = HashMap::new();
     for var in vars {
-        if let Ok(Some(value)) = vm.get_variable(var) {
-            // known_values.insert(var.clone(), value);
-            // only numbers
-            match value {
-                DataType::Number(num) => {
-                    known_values.insert(var.clone(), num as f64);
-                },
-                _ => {}
-            }
-        }
+        match vm.get_variable(var) {
+            Ok(Some(DataType::Number(num))) => known_values.insert(var.clone(), symbolic::from_number(num)),
+            Ok(Some(DataType::Quantity(value, unit))) => known_values.insert(var.clone(), unit.to_node(value)),
+            _ => None,
+        };
     }
     known_values
+}
 
+// Equations and constraints as a single expression, left - right, that is compared to zero.
+// For an objective it's the expression to optimize.
+fn to_poly(node: &ASTNode, known_values: &HashMap<String, ASTNode>) -> Result<Poly, String> {
+    let expr = match node {
+        ASTNode::MathExpression { left, right } | ASTNode::BinaryOp { left, right, .. } => {
+            symbolic::binary(*left.clone(), Operator::Sub, *right.clone())
+        },
+        ASTNode::MathObjective { expr, .. } => *expr.clone(),
+        other => return Err(format!("Expected an equation, found {}", other)),
+    };
+    Poly::from_ast(&symbolic::substitute(&expr, known_values))
 }
 
-fn extract_coefficients_ordered(
-    node: &ASTNode,
-    sign: f64,
-    coefficients: &mut Vec<f64>,
-    variables: &Vec<String>,
-    constant: &mut f64,
-    known_values: &HashMap<String, f64> // Add known values map as a parameter
-) {
-    match node {
-        ASTNode::BinaryOp { left, right, op } => {
-            match *op {
-                Operator::Add => {
-                    extract_coefficients_ordered(left, sign, coefficients, variables, constant, known_values);
-                    extract_coefficients_ordered(right, sign, coefficients, variables, constant, known_values);
-                }
-                Operator::Sub => {
-                    extract_coefficients_ordered(left, sign, coefficients, variables, constant, known_values);
-                    extract_coefficients_ordered(right, -sign, coefficients, variables, constant, known_values);
-                }
-                Operator::Mul => {
-                    if let ASTNode::Number(val) = **right {
-                        extract_coefficients_ordered(left, sign * (val as f64), coefficients, variables, constant, known_values);
-                    } else if let ASTNode::Number(val) = **left {
-                        extract_coefficients_ordered(right, sign * (val as f64), coefficients, variables, constant, known_values);
-                    } else {
-                        panic!("Unsupported multiplication operation");
-                    }
-                }
-                _ => panic!("Unsupported operator"),
-            }
+// The exponents of the unknowns in a term, by index of the unknown
+type Powers = Vec<(usize, i64)>;
+
+// Splits a term into the powers of the unknowns and the value of the rest.
+// Atoms that don't contain an unknown, like sin(2), are evaluated with `evaluate`.
+// Returns `None` if an unknown appears inside another atom, e.g. in sin(x).
+fn split_term(
+    poly: &Poly,
+    monomial: &Monomial,
+    c: &Coeff,
+    unknowns: &[String],
+    evaluate: &mut dyn FnMut(&ASTNode) -> Result<DataType, String>,
+) -> Result<Option<(Powers, Coeff)>, String> {
+    let mut rest = Monomial::new();
+    let mut powers = vec![];
+    for (atom, exponent) in monomial {
+        if let Some(index) = unknowns.iter().position(|name| name == atom) {
+            powers.push((index, *exponent));
+        } else if unknowns.iter().any(|name| symbolic::contains_var(&poly.atoms[atom], name)) {
+            return Ok(None);
+        } else {
+            rest.insert(atom.clone(), *exponent);
         }
-        ASTNode::UnaryOp { op, expr } => {
-            match *op {
-                Operator::Neg => {
-                    extract_coefficients_ordered(expr, -sign, coefficients, variables, constant, known_values);
-                }
-                _ => panic!("Unsupported unary operator"),
+    }
+
+    // Dividing by something that is zero, like in x/0, is an error as in ordinary evaluation
+    for (atom, exponent) in &rest {
+        if *exponent < 0 {
+            match evaluate(&poly.atoms[atom])? {
+                DataType::Number(n) if n.is_zero() => return Err("Division by zero".to_string()),
+                DataType::Quantity(0.0, _) => return Err("Division by zero".to_string()),
+                _ => {},
             }
         }
-        ASTNode::Identifier(name) => {
-            if let Some(value) = known_values.get(name) {
-                // Identifier has a known value, treat it as a constant
-                *constant += sign * value;
-                println!("Found known value: {} = {}", name, value);
-            } else if let Some(index) = variables.iter().position(|v| v == name) {
-                // Update coefficients with the current sign
-                coefficients[index] += sign;
-            } else {
-                panic!("Variable not found in variable list");
-            }
+    }
+
+    let value = match poly.monomial_to_ast(&rest) {
+        Some(expr) => match evaluate(&expr)? {
+            DataType::Number(n) => c.clone() * Coeff::from_number(&n),
+            // Quantities are solved in SI units, their units are handled by `dimensions`
+            DataType::Quantity(value, _) => c.clone() * Coeff::Float(value),
+            other => return Err(format!("{} is {}, expected a real number", expr, other)),
+        },
+        None => c.clone(),
+    };
+    Ok(Some((powers, value)))
+}
+
+// The coefficients of a linear equation in the unknowns, followed by the constant term
+fn linear_coefficients(
+    poly: &Poly,
+    unknowns: &[String],
+    evaluate: &mut dyn FnMut(&ASTNode) -> Result<DataType, String>,
+) -> Result<Option<(Vec<Coeff>, Coeff)>, String> {
+    let mut coefficients = vec![Coeff::int(0); unknowns.len()];
+    let mut constant = Coeff::int(0);
+
+    for (monomial, c) in &poly.terms {
+        match split_term(poly, monomial, c, unknowns, evaluate)? {
+            Some((powers, value)) => match powers[..] {
+                [] => constant = constant + value,
+                [(index, 1)] => coefficients[index] = coefficients[index].clone() + value,
+                _ => return Ok(None),
+            },
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some((coefficients, constant)))
+}
+
+// The coefficients of a polynomial equation in a single unknown, starting with the constant term.
+// Negative powers are cleared by multiplying with the lowest power of the unknown, which doesn't
+// add a root at zero because the lowest power then has a non-zero coefficient.
+fn polynomial_coefficients(
+    poly: &Poly,
+    unknown: &str,
+    evaluate: &mut dyn FnMut(&ASTNode) -> Result<DataType, String>,
+) -> Result<Option<Vec<Coeff>>, String> {
+    let mut terms = vec![];
+    for (monomial, c) in &poly.terms {
+        match split_term(poly, monomial, c, &[unknown.to_string()], evaluate)? {
+            Some((powers, value)) => terms.push((powers.first().map_or(0, |(_, e)| *e), value)),
+            None => return Ok(None),
         }
-        ASTNode::Number(val) => {
-            *constant += sign * (*val as f64);
+    }
+
+    let lowest = terms.iter().map(|(e, _)| *e).min().unwrap_or(0).min(0);
+    let mut coefficients = vec![];
+    for (exponent, value) in terms {
+        let index = (exponent - lowest) as usize;
+        if coefficients.len() <= index {
+            coefficients.resize(index + 1, Coeff::int(0));
         }
-        _ => panic!("Unsupported ASTNode"),
+        coefficients[index] = coefficients[index].clone() + value;
     }
+    Ok(Some(coefficients))
 }
 
-pub fn formulate_system(equations: Vec<ASTNode>, known_values: &HashMap<String, f64>) -> (DMatrix<f64>, DVector<f64>) {
-    let mut variables = Vec::new();
-    let mut coefficients_matrix = Vec::new();
-    let mut constants_vector = Vec::new();
+// Solves a single polynomial equation. Multiple solutions are returned as a list.
+fn solve_polynomial(equation: &ASTNode, coefficients: &[Coeff], unknown: &str) -> Result<DataType, String> {
+    if coefficients.iter().all(Coeff::is_zero) {
+        return Err(format!("The equation {} holds for every {}", equation, unknown));
+    }
 
-    for equation in &equations {
-        let vars_in_eq = find_vars(equation);
-        for var in vars_in_eq {
-            if !variables.contains(&var) {
-                variables.push(var);
+    let mut solutions: Vec<DataType> = roots(coefficients).into_iter().map(|root| match root {
+        Root::Exact(c) => DataType::Number(c.to_number()),
+        Root::Approx(c) => DataType::from_complex(c),
+    }).collect();
+    match solutions.len() {
+        0 => Err(format!("The equation {} has no solution", equation)),
+        1 => Ok(solutions.remove(0)),
+        _ => Ok(DataType::List(solutions)),
+    }
+}
+
+/// Solves a system of equations for the variables that aren't known yet.
+/// The equations are brought into canonical form first, so they can be written in any shape,
+/// e.g. `2*(x + 1) = x`. Systems of linear equations are solved exactly as long as they only contain
+/// rationals. A single polynomial equation in one unknown is solved for all its roots.
+/// With inequality constraints or an objective, the system is solved as a linear program.
+/// The unknowns are the variables that aren't known, unless they are given explicitly.
+/// With quantities, the solutions get the units that follow from the equations.
+pub fn solve_system(
+    body: &[ASTNode],
+    explicit_unknowns: Option<&[String]>,
+    known_values: &HashMap<String, ASTNode>,
+    evaluate: &mut dyn FnMut(&ASTNode) -> Result<DataType, String>,
+) -> Result<Vec<(String, DataType)>, String> {
+    let mut unknowns: Vec<String> = vec![];
+    for node in body {
+        for var in find_vars(node) {
+            if !known_values.contains_key(&var) && !unknowns.contains(&var) {
+                unknowns.push(var);
             }
         }
     }
 
+    if let Some(explicit) = explicit_unknowns {
+        if let Some(var) = unknowns.iter().find(|var| !explicit.contains(var)) {
+            return Err(format!("{} has no value, give it one or add it to the variables to solve for", var));
+        }
+        if let Some(var) = explicit.iter().find(|var| !unknowns.contains(var)) {
+            return Err(format!("Can't solve for {}, it doesn't appear in the equations", var));
+        }
+        unknowns = explicit.to_vec();
+    }
+
+    let mut polys = vec![];
+    for node in body {
+        polys.push((node, to_poly(node, known_values)?));
+    }
+    let dims = dimensions::infer(&polys, &unknowns)?;
+    let solution = solve_numbers(body, &unknowns, known_values, evaluate)?;
+    Ok(solution.into_iter().map(|(name, value)| {
+        let value = dimensions::with_unit(value, dims.get(&name));
+        (name, value)
+    }).collect())
+}
+
+// Solves the system for the unknowns, with quantities as numbers in SI units
+fn solve_numbers(
+    body: &[ASTNode],
+    unknowns: &[String],
+    known_values: &HashMap<String, ASTNode>,
+    evaluate: &mut dyn FnMut(&ASTNode) -> Result<DataType, String>,
+) -> Result<Vec<(String, DataType)>, String> {
+    if body.iter().any(|node| !matches!(node, ASTNode::MathExpression { .. })) {
+        return solve_linear_program(body, unknowns, known_values, evaluate);
+    }
+
+    let equations = body;
+    let mut rows = vec![];
     for equation in equations {
-        let mut coefficients = vec![0.0; variables.len()];
-        let mut constant = 0.0;
-        let (left, right) = match equation {
-            ASTNode::MathExpression { left, right } => (left, right),
-            _ => panic!("Expected MathExpression, found {:?}", equation),
-        };
+        let poly = to_poly(equation, known_values)?;
+        if let ([unknown], [_]) = (unknowns, equations) {
+            return match polynomial_coefficients(&poly, unknown, evaluate)? {
+                Some(coefficients) => Ok(vec![(unknown.clone(), solve_polynomial(equation, &coefficients, unknown)?)]),
+                None => Err(format!("The equation {} is not a polynomial in {}", equation, unknown)),
+            };
+        }
 
-        extract_coefficients_ordered(&left, 1.0, &mut coefficients, &variables, &mut constant, known_values);
-        extract_coefficients_ordered(&right, -1.0, &mut coefficients, &variables, &mut constant, known_values);
+        match linear_coefficients(&poly, unknowns, evaluate)? {
+            // An equation without unknowns only has to hold
+            Some((coefficients, constant)) if coefficients.iter().all(Coeff::is_zero) => {
+                if !constant.is_zero() {
+                    return Err(format!("The equation {} doesn't hold", equation));
+                }
+            },
+            Some((coefficients, constant)) => rows.push((coefficients, -constant)),
+            None => return Err(format!("The equation {} is not linear", equation)),
+        }
+    }
 
-        coefficients_matrix.push(coefficients);
-        constants_vector.push(constant);
+    if rows.len() < unknowns.len() {
+        return Err(format!(
+            "There are more unknowns than equations: {} equation(s) for {} unknowns ({}). \
+            Give some of them a value, or list the variables to solve for with 'solve'",
+            rows.len(), unknowns.len(), unknowns.join(", "),
+        ));
+    }
+    if rows.len() > unknowns.len() {
+        return Err(format!(
+            "There are more equations than unknowns: {} equations for {} unknown(s) ({})",
+            rows.len(), unknowns.len(), unknowns.join(", "),
+        ));
     }
 
-    let matrix = DMatrix::from_vec(coefficients_matrix.len(), variables.len(), coefficients_matrix.concat());
-    let constants = DVector::from_vec(constants_vector);
+    let solution = gaussian_elimination(rows).ok_or("The system has no unique solution")?;
+    Ok(unknowns.iter().cloned().zip(solution).map(|(name, value)| (name, DataType::Number(value.to_number()))).collect())
+}
 
-    (matrix, constants)
+// Optimizes the objective subject to the equations and constraints. Without an objective,
+// any point that satisfies the constraints is a solution.
+fn solve_linear_program(
+    body: &[ASTNode],
+    unknowns: &[String],
+    known_values: &HashMap<String, ASTNode>,
+    evaluate: &mut dyn FnMut(&ASTNode) -> Result<DataType, String>,
+) -> Result<Vec<(String, DataType)>, String> {
+    let mut constraints = vec![];
+    let mut cost = vec![Coeff::int(0); unknowns.len()];
+    let mut objectives = 0;
+    for node in body {
+        let (coefficients, constant) = match linear_coefficients(&to_poly(node, known_values)?, unknowns, evaluate)? {
+            Some(linear) => linear,
+            None => return Err(format!("{} is not linear, only linear constraints and objectives are supported", node)),
+        };
+
+        let relation = match node {
+            ASTNode::MathObjective { maximize, .. } => {
+                objectives += 1;
+                cost = if *maximize { coefficients.into_iter().map(|c| -c).collect() } else { coefficients };
+                continue;
+            },
+            ASTNode::MathExpression { .. } => Relation::Eq,
+            // Strict inequalities are treated like non-strict ones, the optimum is usually on the boundary
+            ASTNode::BinaryOp { op: Operator::Lt | Operator::Le, .. } => Relation::Le,
+            ASTNode::BinaryOp { op: Operator::Gt | Operator::Ge, .. } => Relation::Ge,
+            other => return Err(format!("Unexpected {} in math block", other)),
+        };
+        constraints.push(Constraint { coefficients, relation, rhs: -constant });
+    }
+    if objectives > 1 {
+        return Err("A math block can only have one objective".to_string());
+    }
+
+    match simplex::minimize(&constraints, &cost) {
+        Outcome::Optimal(solution) => Ok(unknowns.iter().cloned()
+            .zip(solution)
+            .map(|(name, value)| (name, DataType::Number(value.to_number())))
+            .collect()),
+        Outcome::Infeasible => Err("The constraints can't all be satisfied, the problem is infeasible".to_string()),
+        Outcome::Unbounded => Err("The objective is unbounded".to_string()),
+    }
 }
 
-pub fn solve_system(matrix: DMatrix<f64>, constants: DVector<f64>) -> Result<HashMap<String, f64>, String> {
-    // Solve the linear system
-    match matrix.lu().solve(&constants) {
-        Some(solution) => {
-            println!("Raw solution: {:?}", solution);
-            let mut result = HashMap::new();
-            for (i, value) in solution.iter().enumerate() {
-                result.insert(format!("var{}", i + 1), *value);
+// Solves A x = b, given as rows of (A row, b entry). Returns `None` if A is singular.
+fn gaussian_elimination(mut rows: Vec<(Vec<Coeff>, Coeff)>) -> Option<Vec<Coeff>> {
+    let n = rows.len();
+    for col in 0..n {
+        // The largest pivot keeps floating point errors small, exact pivots just have to be non-zero
+        let pivot = (col..n)
+            .filter(|&row| !rows[row].0[col].is_zero())
+            .max_by(|&a, &b| rows[a].0[col].to_f64().abs().total_cmp(&rows[b].0[col].to_f64().abs()))?;
+        rows.swap(col, pivot);
+
+        let inverse = rows[col].0[col].recip()?;
+        for row in col + 1..n {
+            let factor = rows[row].0[col].clone() * inverse.clone();
+            if factor.is_zero() {
+                continue;
+            }
+            for k in col..n {
+                let value = rows[row].0[k].clone() - factor.clone() * rows[col].0[k].clone();
+                rows[row].0[k] = value;
             }
-            Ok(result)
+            rows[row].1 = rows[row].1.clone() - factor * rows[col].1.clone();
         }
-        None => Err("No solution found".to_string()),
+    }
+
+    let mut solution = vec![Coeff::int(0); n];
+    for row in (0..n).rev() {
+        let mut sum = rows[row].1.clone();
+        for (a, x) in rows[row].0.iter().zip(&solution).skip(row + 1) {
+            sum = sum - a.clone() * x.clone();
+        }
+        solution[row] = sum * rows[row].0[row].recip()?;
+    }
+    Some(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::vm::number::Number;
+
+    fn solve(code: &str) -> Result<Vec<(String, DataType)>, String> {
+        let body = match crate::ast::parse(code)?.pop() {
+            Some(ASTNode::MathBody { body, .. }) => body,
+            other => panic!("Expected a math block, got {:?}", other),
+        };
+        let mut interpreter = Interpreter::new();
+        solve_system(&body, None, &HashMap::new(), &mut |expr| interpreter.value_of(expr.clone()))
+    }
+
+    #[test]
+    fn solves_linear_equations() {
+        assert_eq!(solve("math a { 2*x = 4 }").unwrap(), vec![("x".to_string(), DataType::Number(Number::Int(2)))]);
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(solve("math a { x/0 = 1 }"), Err("Division by zero".to_string()));
+        assert_eq!(solve("math a { 2*x = 4/0 }"), Err("Division by zero".to_string()));
+        assert_eq!(solve("math a { x^2/(1 - 1) = 1 }"), Err("Division by zero".to_string()));
     }
 }
@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+
+use crate::symbolic::poly::Coeff;
+
+// Linear programming with the two-phase simplex method on a dense tableau. Bland's rule picks the
+// pivots, which is slower than picking the steepest edge but can't cycle.
+
+// Float coefficients closer to zero than this are treated as zero
+const EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Relation {
+    Le,
+    Ge,
+    Eq,
+}
+
+/// A linear constraint: coefficients · x (relation) rhs
+pub struct Constraint {
+    pub coefficients: Vec<Coeff>,
+    pub relation: Relation,
+    pub rhs: Coeff,
+}
+
+pub enum Outcome {
+    Optimal(Vec<Coeff>),
+    Infeasible,
+    Unbounded,
+}
+
+fn sign(c: &Coeff) -> Ordering {
+    match c {
+        Coeff::Float(n) if n.abs() <= EPSILON => Ordering::Equal,
+        c if c.is_zero() => Ordering::Equal,
+        c if c.is_negative() => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+struct Tableau {
+    rows: Vec<Vec<Coeff>>, // The last column is the right-hand side
+    basis: Vec<usize>,
+}
+
+impl Tableau {
+    fn rhs(&self, row: usize) -> &Coeff {
+        self.rows[row].last().unwrap()
+    }
+
+    fn pivot(&mut self, row: usize, col: usize) {
+        let inverse = self.rows[row][col].recip().unwrap();
+        self.rows[row] = self.rows[row].iter().map(|c| c.clone() * inverse.clone()).collect();
+        for other in 0..self.rows.len() {
+            let factor = self.rows[other][col].clone();
+            if other == row || sign(&factor) == Ordering::Equal {
+                continue;
+            }
+            let pivot_row = self.rows[row].clone();
+            for (c, p) in self.rows[other].iter_mut().zip(pivot_row) {
+                *c = c.clone() - factor.clone() * p;
+            }
+        }
+        self.basis[row] = col;
+    }
+
+    // Runs the simplex method for the cost vector, only letting the allowed columns enter the basis.
+    // Returns false if the objective is unbounded.
+    fn optimize(&mut self, cost: &[Coeff], allowed: usize) -> bool {
+        loop {
+            // The entering column is the first one with a negative reduced cost
+            let entering = (0..allowed).find(|&col| {
+                let mut reduced = cost[col].clone();
+                for (row, &basic) in self.basis.iter().enumerate() {
+                    reduced = reduced - cost[basic].clone() * self.rows[row][col].clone();
+                }
+                sign(&reduced) == Ordering::Less
+            });
+            let Some(col) = entering else { return true };
+
+            // The leaving row is the one that limits the step the most, ties go to the lowest basic column
+            let mut leaving: Option<(usize, Coeff)> = None;
+            for row in 0..self.rows.len() {
+                if sign(&self.rows[row][col]) != Ordering::Greater {
+                    continue;
+                }
+                let ratio = self.rhs(row).clone() * self.rows[row][col].recip().unwrap();
+                let better = match &leaving {
+                    None => true,
+                    Some((best, best_ratio)) => match sign(&(ratio.clone() - best_ratio.clone())) {
+                        Ordering::Less => true,
+                        Ordering::Equal => self.basis[row] < self.basis[*best],
+                        Ordering::Greater => false,
+                    },
+                };
+                if better {
+                    leaving = Some((row, ratio));
+                }
+            }
+            match leaving {
+                Some((row, _)) => self.pivot(row, col),
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Minimizes cost · x subject to the constraints. The variables are free, they can be negative.
+pub fn minimize(constraints: &[Constraint], cost: &[Coeff]) -> Outcome {
+    let n = cost.len();
+    let m = constraints.len();
+    let slacks = constraints.iter().filter(|c| c.relation != Relation::Eq).count();
+    // Columns: x+ and x- for every variable (x = x+ - x-), the slack variables, an artificial variable
+    // for every row, and the right-hand side
+    let artificial_start = 2 * n + slacks;
+    let width = artificial_start + m + 1;
+
+    let mut rows = vec![];
+    let mut slack = 2 * n;
+    for (i, constraint) in constraints.iter().enumerate() {
+        let mut row = vec![Coeff::int(0); width];
+        for (j, c) in constraint.coefficients.iter().enumerate() {
+            row[2 * j] = c.clone();
+            row[2 * j + 1] = -c.clone();
+        }
+        match constraint.relation {
+            Relation::Le => { row[slack] = Coeff::int(1); slack += 1; },
+            Relation::Ge => { row[slack] = Coeff::int(-1); slack += 1; },
+            Relation::Eq => {},
+        }
+        row[width - 1] = constraint.rhs.clone();
+        // The artificial variables start as the basis, so the right-hand side must not be negative
+        if sign(&constraint.rhs) == Ordering::Less {
+            row = row.into_iter().map(|c| -c).collect();
+        }
+        row[artificial_start + i] = Coeff::int(1);
+        rows.push(row);
+    }
+    let mut tableau = Tableau { rows, basis: (artificial_start..artificial_start + m).collect() };
+
+    // Phase 1: minimize the sum of the artificial variables to find a feasible point
+    let mut phase_one_cost = vec![Coeff::int(0); width - 1];
+    phase_one_cost[artificial_start..].iter_mut().for_each(|c| *c = Coeff::int(1));
+    tableau.optimize(&phase_one_cost, width - 1);
+    let infeasibility = tableau.basis.iter().enumerate()
+        .filter(|(_, &basic)| basic >= artificial_start)
+        .fold(Coeff::int(0), |sum, (row, _)| sum + tableau.rhs(row).clone());
+    if sign(&infeasibility) == Ordering::Greater {
+        return Outcome::Infeasible;
+    }
+
+    // Artificial variables that are still in the basis are zero, swap them for a real column.
+    // If there is none, the constraint is redundant and dropped.
+    let mut row = 0;
+    while row < tableau.rows.len() {
+        if tableau.basis[row] >= artificial_start {
+            match (0..artificial_start).find(|&col| sign(&tableau.rows[row][col]) != Ordering::Equal) {
+                Some(col) => tableau.pivot(row, col),
+                None => {
+                    tableau.rows.remove(row);
+                    tableau.basis.remove(row);
+                    continue;
+                },
+            }
+        }
+        row += 1;
+    }
+
+    // Phase 2: minimize the actual cost, without the artificial variables
+    let mut phase_two_cost = vec![Coeff::int(0); width - 1];
+    for (j, c) in cost.iter().enumerate() {
+        phase_two_cost[2 * j] = c.clone();
+        phase_two_cost[2 * j + 1] = -c.clone();
+    }
+    if !tableau.optimize(&phase_two_cost, artificial_start) {
+        return Outcome::Unbounded;
+    }
+
+    let mut values = vec![Coeff::int(0); 2 * n];
+    for (row, &basic) in tableau.basis.iter().enumerate() {
+        if basic < 2 * n {
+            values[basic] = tableau.rhs(row).clone();
+        }
+    }
+    Outcome::Optimal((0..n).map(|j| values[2 * j].clone() - values[2 * j + 1].clone()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(coefficients: &[i64], relation: Relation, rhs: i64) -> Constraint {
+        Constraint { coefficients: coefficients.iter().map(|c| Coeff::int(*c)).collect(), relation, rhs: Coeff::int(rhs) }
+    }
+
+    fn cost(coefficients: &[i64]) -> Vec<Coeff> {
+        coefficients.iter().map(|c| Coeff::int(*c)).collect()
+    }
+
+    #[test]
+    fn finds_the_optimum() {
+        // Maximize 3x + 2y with x + y <= 4, x + 3y <= 6, x >= 0, y >= 0: x = 4, y = 0
+        let constraints = [
+            constraint(&[1, 1], Relation::Le, 4),
+            constraint(&[1, 3], Relation::Le, 6),
+            constraint(&[1, 0], Relation::Ge, 0),
+            constraint(&[0, 1], Relation::Ge, 0),
+        ];
+        match minimize(&constraints, &cost(&[-3, -2])) {
+            Outcome::Optimal(x) => assert_eq!(x.iter().map(Coeff::to_f64).collect::<Vec<_>>(), [4.0, 0.0]),
+            _ => panic!("Expected an optimal solution"),
+        }
+    }
+
+    #[test]
+    fn equality_constraints_are_met() {
+        // Minimize x + y with x + 2y = 4, x >= 0, y >= 0: x = 0, y = 2
+        let constraints = [
+            constraint(&[1, 2], Relation::Eq, 4),
+            constraint(&[1, 0], Relation::Ge, 0),
+            constraint(&[0, 1], Relation::Ge, 0),
+        ];
+        match minimize(&constraints, &cost(&[1, 1])) {
+            Outcome::Optimal(x) => assert_eq!(x.iter().map(Coeff::to_f64).collect::<Vec<_>>(), [0.0, 2.0]),
+            _ => panic!("Expected an optimal solution"),
+        }
+    }
+
+    #[test]
+    fn detects_infeasible_constraints() {
+        let constraints = [constraint(&[1], Relation::Ge, 5), constraint(&[1], Relation::Le, 2)];
+        assert!(matches!(minimize(&constraints, &cost(&[1])), Outcome::Infeasible));
+    }
+
+    #[test]
+    fn detects_unbounded_objectives() {
+        let constraints = [constraint(&[1, 0], Relation::Ge, 0), constraint(&[1, -1], Relation::Le, 1)];
+        assert!(matches!(minimize(&constraints, &cost(&[0, -1])), Outcome::Unbounded));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::ast::parser::{ASTNode, Operator};
+
+use super::{binary, call, contains_var, neg, number, simplify};
+
+/// Differentiates `expr` with respect to the variable `var` and simplifies the result.
+/// Any identifier other than `var` is treated as a constant.
+pub fn diff(expr: &ASTNode, var: &str) -> Result<ASTNode, String> {
+    simplify(derivative(expr, var)?)
+}
+
+fn derivative(expr: &ASTNode, var: &str) -> Result<ASTNode, String> {
+    if !contains_var(expr, var) {
+        return match expr {
+            ASTNode::Number(_) | ASTNode::Float(_) | ASTNode::Imaginary(_) | ASTNode::Identifier(_)
+            | ASTNode::BinaryOp { .. } | ASTNode::UnaryOp { .. } | ASTNode::FunctionCall { .. } => Ok(number(0)),
+            other => Err(format!("Cannot differentiate {}", other)),
+        };
+    }
+
+    match expr {
+        ASTNode::Identifier(_) => Ok(number(1)), // Must be `var` itself, see above
+        ASTNode::UnaryOp { op: Operator::Neg, expr } => Ok(neg(derivative(expr, var)?)),
+        ASTNode::BinaryOp { left, op, right } => {
+            let (u, v) = (left.as_ref().clone(), right.as_ref().clone());
+            match op {
+                Operator::Add | Operator::Sub => {
+                    Ok(binary(derivative(&u, var)?, op.clone(), derivative(&v, var)?))
+                },
+                // (uv)' = u'v + uv'
+                Operator::Mul => Ok(binary(
+                    binary(derivative(&u, var)?, Operator::Mul, v.clone()),
+                    Operator::Add,
+                    binary(u.clone(), Operator::Mul, derivative(&v, var)?),
+                )),
+                // (u/v)' = (u'v - uv') / v^2
+                Operator::Div => Ok(binary(
+                    binary(
+                        binary(derivative(&u, var)?, Operator::Mul, v.clone()),
+                        Operator::Sub,
+                        binary(u.clone(), Operator::Mul, derivative(&v, var)?),
+                    ),
+                    Operator::Div,
+                    binary(v, Operator::Pow, number(2)),
+                )),
+                Operator::Pow => power(u, v, var),
+                _ => Err(format!("Cannot differentiate the operator '{}'", op)),
+            }
+        },
+        ASTNode::FunctionCall { name, args } => function(name, args, var),
+        other => Err(format!("Cannot differentiate {}", other)),
+    }
+}
+
+fn power(base: ASTNode, exponent: ASTNode, var: &str) -> Result<ASTNode, String> {
+    // e^u is treated as exp(u)
+    if matches!(&base, ASTNode::Identifier(name) if name == "e") {
+        return function("exp", &[exponent], var);
+    }
+
+    if !contains_var(&exponent, var) {
+        // (u^n)' = n * u^(n-1) * u'
+        let lowered = binary(exponent.clone(), Operator::Sub, number(1));
+        let du = derivative(&base, var)?;
+        return Ok(binary(binary(exponent, Operator::Mul, binary(base, Operator::Pow, lowered)), Operator::Mul, du));
+    }
+
+    let power = binary(base.clone(), Operator::Pow, exponent.clone());
+    if !contains_var(&base, var) {
+        // (a^v)' = a^v * ln(a) * v'
+        let dv = derivative(&exponent, var)?;
+        return Ok(binary(binary(power, Operator::Mul, call("ln", vec![base])), Operator::Mul, dv));
+    }
+
+    // (u^v)' = u^v * (v' ln(u) + v u' / u)
+    let (du, dv) = (derivative(&base, var)?, derivative(&exponent, var)?);
+    Ok(binary(
+        power,
+        Operator::Mul,
+        binary(
+            binary(dv, Operator::Mul, call("ln", vec![base.clone()])),
+            Operator::Add,
+            binary(binary(exponent, Operator::Mul, du), Operator::Mul, reciprocal(base)),
+        ),
+    ))
+}
+
+// u^-1. Reciprocals introduced by differentiation are written as negative powers instead of divisions,
+// so they aren't truncated by integer division: ln(x)' at x = 2 is 0.5, not 0
+fn reciprocal(expr: ASTNode) -> ASTNode {
+    binary(expr, Operator::Pow, number(-1))
+}
+
+// The chain rule for the built-in functions: f(u)' = f'(u) * u'
+fn function(name: &str, args: &[ASTNode], var: &str) -> Result<ASTNode, String> {
+    let u = match (name, args) {
+        // log(u, b) = ln(u) / ln(b)
+        ("log", [u, base]) => {
+            let quotient = binary(call("ln", vec![u.clone()]), Operator::Mul, reciprocal(call("ln", vec![base.clone()])));
+            return derivative(&quotient, var);
+        },
+        (_, [u]) => u.clone(),
+        _ => return Err(format!("Cannot differentiate {}() with {} arguments", name, args.len())),
+    };
+
+    let outer = match name {
+        "sin" => call("cos", vec![u.clone()]),
+        "cos" => neg(call("sin", vec![u.clone()])),
+        "tan" => reciprocal(binary(call("cos", vec![u.clone()]), Operator::Pow, number(2))),
+        "exp" => call("exp", vec![u.clone()]),
+        "ln" => reciprocal(u.clone()),
+        "log" => reciprocal(binary(u.clone(), Operator::Mul, call("ln", vec![number(10)]))),
+        "sqrt" => binary(ASTNode::Float(0.5), Operator::Mul, binary(u.clone(), Operator::Pow, ASTNode::Float(-0.5))),
+        "asin" | "acos" => {
+            let root = binary(binary(number(1), Operator::Sub, binary(u.clone(), Operator::Pow, number(2))), Operator::Pow, ASTNode::Float(-0.5));
+            if name == "asin" { root } else { neg(root) }
+        },
+        "atan" => reciprocal(binary(number(1), Operator::Add, binary(u.clone(), Operator::Pow, number(2)))),
+        "abs" => binary(u.clone(), Operator::Mul, reciprocal(call("abs", vec![u.clone()]))),
+        _ => return Err(format!("Cannot differentiate {}()", name)),
+    };
+
+    Ok(binary(outer, Operator::Mul, derivative(&u, var)?))
+}
@@ -0,0 +1,244 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use crate::ast::parser::{ASTNode, Operator};
+
+use super::binary;
+use super::poly::{neg_term, Coeff, Monomial, Poly};
+
+// Factoring works on the canonical form. Common factors are pulled out of every polynomial,
+// and polynomials in a single variable with rational coefficients are split into linear factors
+// wherever they have rational roots: 2*x^3 - 2*x = 2*x*(x - 1)*(x + 1).
+
+// Rational roots are found by trying every divisor of the first and last coefficient, which is only
+// feasible while they are small
+const MAX_ROOT_CANDIDATE: u64 = 1_000_000_000_000;
+
+/// Factors an expression as far as possible.
+pub fn factor(expr: &ASTNode) -> Result<ASTNode, String> {
+    Ok(factor_poly(&Poly::from_ast(expr)?))
+}
+
+pub fn factor_poly(poly: &Poly) -> ASTNode {
+    if poly.terms.len() <= 1 {
+        return poly.to_ast();
+    }
+
+    let content = content(poly);
+    let common = common_monomial(poly);
+    let divisor = Poly { terms: [(common.clone(), content.clone())].into(), atoms: poly.atoms.clone() };
+    let rest = poly.mul(&divisor.pow(-1).unwrap()).unwrap();
+
+    let mut factors: Vec<(ASTNode, usize)> = vec![];
+    let mut scale = content;
+    let mut rest = Some(rest);
+    if let Some((key, coefficients)) = univariate(rest.as_ref().unwrap()) {
+        let atom = poly.atoms[&key].clone();
+        let (linear, remaining, leftover) = rational_roots(coefficients);
+        for (root, multiplicity) in linear {
+            factors.push((linear_factor(&atom, &root), multiplicity));
+        }
+        scale = scale * Coeff::Exact(leftover);
+        rest = (remaining.len() > 1).then(|| from_coefficients(&atom, &remaining, poly));
+    }
+
+    // Put the pieces together: scale * common * (linear factors) * rest
+    let mut product: Vec<ASTNode> = vec![];
+    if let Some(monomial) = poly.monomial_to_ast(&common) {
+        product.push(monomial);
+    }
+    for (factor, multiplicity) in factors {
+        product.push(match multiplicity {
+            1 => factor,
+            m => binary(factor, Operator::Pow, ASTNode::Number(BigInt::from(m))),
+        });
+    }
+    if let Some(rest) = rest {
+        product.push(rest.to_ast());
+    }
+
+    let negative = scale.is_negative();
+    let magnitude = if negative { -scale } else { scale };
+    if !magnitude.is_one() || product.is_empty() {
+        product.insert(0, magnitude.to_node());
+    }
+    let product = product.into_iter().reduce(|product, factor| binary(product, Operator::Mul, factor)).unwrap();
+    if negative { neg_term(product) } else { product }
+}
+
+fn gcd(a: BigInt, b: BigInt) -> BigInt {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while !b.is_zero() {
+        let r = &a % &b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+// The factor that makes all coefficients coprime integers with a positive leading coefficient.
+// Floats can't be factored, only the sign is taken out of them.
+fn content(poly: &Poly) -> Coeff {
+    let leading_negative = poly.sorted_terms()[0].1.is_negative();
+    let sign = if leading_negative { -1 } else { 1 };
+
+    let mut numerators = BigInt::zero();
+    let mut denominators = BigInt::one();
+    for c in poly.terms.values() {
+        match c {
+            Coeff::Exact(r) => {
+                numerators = gcd(numerators, r.numer().clone());
+                let d = r.denom().clone();
+                denominators = &denominators * &d / gcd(denominators.clone(), d);
+            },
+            Coeff::Float(_) => return Coeff::int(sign),
+        }
+    }
+    Coeff::Exact(BigRational::new(numerators * sign, denominators))
+}
+
+// The highest power of each atom that divides every term
+fn common_monomial(poly: &Poly) -> Monomial {
+    let mut terms = poly.terms.keys();
+    let mut common = terms.next().unwrap().clone();
+    for monomial in terms {
+        common = common.into_iter()
+            .filter_map(|(atom, e)| monomial.get(&atom).map(|&other| (atom, e.min(other))))
+            .collect();
+    }
+    common.retain(|_, e| *e > 0);
+    common
+}
+
+// If the polynomial only depends on a single atom with non-negative powers and has exact coefficients,
+// returns that atom and the coefficients, starting with the constant term.
+fn univariate(poly: &Poly) -> Option<(String, Vec<BigRational>)> {
+    let mut key: Option<&String> = None;
+    let mut coefficients: Vec<BigRational> = vec![];
+    for (monomial, c) in &poly.terms {
+        let exponent = match monomial.len() {
+            0 => 0,
+            1 => {
+                let (atom, &e) = monomial.iter().next().unwrap();
+                if key.is_some_and(|key| key != atom) || e < 0 {
+                    return None;
+                }
+                key = Some(atom);
+                e as usize
+            },
+            _ => return None,
+        };
+        let c = match c {
+            Coeff::Exact(r) => r.clone(),
+            Coeff::Float(_) => return None,
+        };
+        if coefficients.len() <= exponent {
+            coefficients.resize(exponent + 1, BigRational::zero());
+        }
+        coefficients[exponent] = c;
+    }
+    Some((key?.clone(), coefficients))
+}
+
+fn divisors(n: &BigInt) -> Option<Vec<u64>> {
+    let n = n.abs().to_u64().filter(|n| *n <= MAX_ROOT_CANDIDATE)?;
+    let mut divisors = vec![];
+    let mut i = 1;
+    while i * i <= n {
+        if n % i == 0 {
+            divisors.push(i);
+            if i * i != n {
+                divisors.push(n / i);
+            }
+        }
+        i += 1;
+    }
+    Some(divisors)
+}
+
+fn evaluate(coefficients: &[BigRational], x: &BigRational) -> BigRational {
+    coefficients.iter().rev().fold(BigRational::zero(), |acc, c| acc * x + c)
+}
+
+// Divides by (x - root) using synthetic division. The root must be a root, so there's no remainder.
+fn divide_by_root(coefficients: &[BigRational], root: &BigRational) -> Vec<BigRational> {
+    let mut quotient = vec![BigRational::zero(); coefficients.len() - 1];
+    let mut carry = BigRational::zero();
+    for i in (1..coefficients.len()).rev() {
+        carry = &coefficients[i] + carry * root;
+        quotient[i - 1] = carry.clone();
+    }
+    quotient
+}
+
+// Splits off all rational roots. Returns the roots with their multiplicity, the remaining coefficients
+// and the constant that's left over if all roots were split off.
+pub(super) fn rational_roots(mut coefficients: Vec<BigRational>) -> (Vec<(BigRational, usize)>, Vec<BigRational>, BigRational) {
+    let mut roots = vec![];
+    let mut leftover = BigRational::one();
+    let (first, last) = (coefficients[0].to_integer(), coefficients[coefficients.len() - 1].to_integer());
+    let (numerators, denominators) = match (divisors(&first), divisors(&last)) {
+        (Some(p), Some(q)) => (p, q),
+        _ => return (roots, coefficients, leftover),
+    };
+
+    let mut candidates: Vec<BigRational> = vec![];
+    for p in &numerators {
+        for q in &denominators {
+            for sign in [1, -1] {
+                let candidate = BigRational::new(BigInt::from(*p) * sign, BigInt::from(*q));
+                if !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+    }
+    candidates.sort();
+
+    for root in candidates {
+        let mut multiplicity = 0;
+        while coefficients.len() > 1 && evaluate(&coefficients, &root).is_zero() {
+            coefficients = divide_by_root(&coefficients, &root);
+            // x - p/q = (q*x - p) / q, so the quotient by (q*x - p) is the quotient by (x - p/q) divided by q
+            let denominator = BigRational::from_integer(root.denom().clone());
+            coefficients.iter_mut().for_each(|c| *c = &*c / &denominator);
+            multiplicity += 1;
+        }
+        if multiplicity > 0 {
+            roots.push((root, multiplicity));
+        }
+    }
+
+    // Whatever remains is a constant times a primitive polynomial
+    if coefficients.len() == 1 {
+        leftover *= coefficients[0].clone();
+        coefficients = vec![BigRational::one()];
+    }
+    (roots, coefficients, leftover)
+}
+
+// q*x - p for the root p/q
+fn linear_factor(atom: &ASTNode, root: &BigRational) -> ASTNode {
+    let x = Poly::atom(atom.clone());
+    let q = Coeff::Exact(BigRational::from_integer(root.denom().clone()));
+    let p = Coeff::Exact(BigRational::from_integer(root.numer().clone()));
+    x.scale(q).sub(Poly::constant(p)).to_ast()
+}
+
+fn from_coefficients(atom: &ASTNode, coefficients: &[BigRational], poly: &Poly) -> Poly {
+    let key = atom.to_string();
+    let mut result = Poly::constant(Coeff::int(0));
+    result.atoms = poly.atoms.clone();
+    for (exponent, c) in coefficients.iter().enumerate() {
+        if c.is_zero() {
+            continue;
+        }
+        let monomial = match exponent {
+            0 => Monomial::new(),
+            e => Monomial::from([(key.clone(), e as i64)]),
+        };
+        result.terms.insert(monomial, Coeff::Exact(c.clone()));
+    }
+    result
+}
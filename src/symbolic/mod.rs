@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+
+use crate::ast::parser::{ASTNode, Operator};
+use crate::vm::number::Number;
+
+mod diff;
+mod factor;
+pub mod poly;
+pub mod roots;
+mod simplify;
+
+pub use diff::diff;
+pub use factor::factor;
+pub use simplify::{expand, simplify};
+
+// Symbolic math on expression trees. Expressions are plain `ASTNode`s, so a symbolic result
+// can be printed back as source code and evaluated by the interpreter like any other expression.
+
+pub fn number(n: i64) -> ASTNode {
+    ASTNode::Number(BigInt::from(n))
+}
+
+pub fn binary(left: ASTNode, op: Operator, right: ASTNode) -> ASTNode {
+    ASTNode::BinaryOp { left: Box::new(left), op, right: Box::new(right) }
+}
+
+pub fn neg(expr: ASTNode) -> ASTNode {
+    ASTNode::UnaryOp { op: Operator::Neg, expr: Box::new(expr) }
+}
+
+pub fn call(name: &str, args: Vec<ASTNode>) -> ASTNode {
+    ASTNode::FunctionCall { name: name.to_string(), args }
+}
+
+/// Returns the value of a number literal.
+pub fn literal(node: &ASTNode) -> Option<Number> {
+    match node {
+        ASTNode::Number(n) => Some(Number::from_bigint(n.clone())),
+        ASTNode::Float(n) => Some(Number::Float(*n)),
+        _ => None,
+    }
+}
+
+pub fn from_number(n: Number) -> ASTNode {
+    match n {
+        Number::Float(n) => ASTNode::Float(n),
+        n => ASTNode::Number(n.to_bigint()),
+    }
+}
+
+/// Returns `true` if the variable `name` appears anywhere in the expression.
+pub fn contains_var(node: &ASTNode, name: &str) -> bool {
+    match node {
+        ASTNode::Identifier(ident) => ident == name,
+        other => other.children().into_iter().any(|child| contains_var(child, name)),
+    }
+}
+
+/// Replaces variables by expressions. All variables are replaced at once, so substituting
+/// `x -> y, y -> x` swaps them.
+pub fn substitute(node: &ASTNode, bindings: &HashMap<String, ASTNode>) -> ASTNode {
+    match node {
+        ASTNode::Identifier(name) => bindings.get(name).cloned().unwrap_or_else(|| node.clone()),
+        ASTNode::BinaryOp { left, op, right } => binary(
+            substitute(left, bindings),
+            op.clone(),
+            substitute(right, bindings),
+        ),
+        ASTNode::UnaryOp { op, expr } => ASTNode::UnaryOp {
+            op: op.clone(),
+            expr: Box::new(substitute(expr, bindings)),
+        },
+        ASTNode::FunctionCall { name, args } => ASTNode::FunctionCall {
+            name: name.clone(),
+            args: args.iter().map(|arg| substitute(arg, bindings)).collect(),
+        },
+        ASTNode::List(items) => ASTNode::List(items.iter().map(|item| substitute(item, bindings)).collect()),
+        ASTNode::MathExpression { left, right } => ASTNode::MathExpression {
+            left: Box::new(substitute(left, bindings)),
+            right: Box::new(substitute(right, bindings)),
+        },
+        ASTNode::MathObjective { maximize, expr } => ASTNode::MathObjective {
+            maximize: *maximize,
+            expr: Box::new(substitute(expr, bindings)),
+        },
+        other => other.clone(),
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use crate::ast::parser::{ASTNode, Operator};
+use crate::vm::number::Number;
+
+use super::{binary, neg};
+
+// The canonical form of an expression: a sum of terms, each a coefficient times a product of powers
+// of atoms. Atoms are variables and anything that isn't a polynomial, such as sin(x) or x^0.5.
+// Two expressions that are equal as polynomials have the same canonical form.
+
+// Expanding a product or power is given up when the result would have more terms than this.
+// The expression is kept as it is instead.
+const MAX_TERMS: usize = 1000;
+const MAX_EXPANDED_POWER: i64 = 64;
+// Exact powers are only evaluated while the result has at most this many bits, 2^300000000 is kept as it is
+const MAX_EXACT_BITS: u64 = 100_000;
+
+/// A coefficient. Exact while only integers and rationals are involved, a float otherwise.
+#[derive(Clone, Debug)]
+pub enum Coeff {
+    Exact(BigRational),
+    Float(f64),
+}
+
+impl Coeff {
+    pub fn int(n: i64) -> Coeff {
+        Coeff::Exact(BigRational::from_integer(BigInt::from(n)))
+    }
+
+    pub fn from_number(n: &Number) -> Coeff {
+        match n {
+            Number::Float(n) => Coeff::Float(*n),
+            n => Coeff::Exact(BigRational::from_integer(n.to_bigint())),
+        }
+    }
+
+    /// Integers stay integers, everything else becomes a float.
+    pub fn to_number(&self) -> Number {
+        match self {
+            Coeff::Exact(r) if r.is_integer() => Number::from_bigint(r.to_integer()),
+            other => Number::Float(other.to_f64()),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Coeff::Exact(r) => r.to_f64().unwrap_or(f64::NAN),
+            Coeff::Float(n) => *n,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Coeff::Exact(r) => r.is_zero(),
+            Coeff::Float(n) => *n == 0.0,
+        }
+    }
+
+    pub fn is_one(&self) -> bool {
+        match self {
+            Coeff::Exact(r) => r.is_one(),
+            Coeff::Float(n) => *n == 1.0,
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Coeff::Exact(_) => true,
+            Coeff::Float(n) => n.is_finite(),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Coeff::Exact(r) => r.is_negative(),
+            Coeff::Float(n) => *n < 0.0,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<BigInt> {
+        match self {
+            Coeff::Exact(r) if r.is_integer() => Some(r.to_integer()),
+            _ => None,
+        }
+    }
+
+    pub fn recip(&self) -> Option<Coeff> {
+        if self.is_zero() {
+            return None;
+        }
+        match self {
+            Coeff::Exact(r) => Some(Coeff::Exact(r.recip())),
+            Coeff::Float(n) => Some(Coeff::Float(1.0 / n)),
+        }
+    }
+
+    /// Returns `None` for a negative power of zero, and for an exact power that would be too large.
+    pub fn pow(&self, exponent: i64) -> Option<Coeff> {
+        let base = if exponent < 0 { self.recip()? } else { self.clone() };
+        let exponent = i32::try_from(exponent.unsigned_abs()).ok()?;
+        match base {
+            Coeff::Exact(r) => {
+                let bits = r.numer().bits().max(r.denom().bits());
+                if !r.abs().is_one() && bits.saturating_mul(exponent as u64) > MAX_EXACT_BITS {
+                    return None;
+                }
+                Some(Coeff::Exact(num_traits::pow(r, exponent as usize)))
+            },
+            Coeff::Float(n) => Some(Coeff::Float(n.powi(exponent))),
+        }
+    }
+
+    pub fn to_node(&self) -> ASTNode {
+        match self.to_number() {
+            Number::Float(n) => ASTNode::Float(n),
+            n => ASTNode::Number(n.to_bigint()),
+        }
+    }
+}
+
+impl std::ops::Add for Coeff {
+    type Output = Coeff;
+
+    fn add(self, other: Coeff) -> Coeff {
+        match (self, other) {
+            (Coeff::Exact(a), Coeff::Exact(b)) => Coeff::Exact(a + b),
+            (a, b) => Coeff::Float(a.to_f64() + b.to_f64()),
+        }
+    }
+}
+
+impl std::ops::Sub for Coeff {
+    type Output = Coeff;
+
+    fn sub(self, other: Coeff) -> Coeff {
+        self + -other
+    }
+}
+
+impl std::ops::Mul for Coeff {
+    type Output = Coeff;
+
+    fn mul(self, other: Coeff) -> Coeff {
+        match (self, other) {
+            (Coeff::Exact(a), Coeff::Exact(b)) => Coeff::Exact(a * b),
+            (a, b) => Coeff::Float(a.to_f64() * b.to_f64()),
+        }
+    }
+}
+
+impl std::ops::Neg for Coeff {
+    type Output = Coeff;
+
+    fn neg(self) -> Coeff {
+        match self {
+            Coeff::Exact(r) => Coeff::Exact(-r),
+            Coeff::Float(n) => Coeff::Float(-n),
+        }
+    }
+}
+
+impl fmt::Display for Coeff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Coeff::Exact(r) => write!(f, "{}", r),
+            Coeff::Float(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// A product of atoms raised to integer powers, keyed by the atoms' source code.
+pub type Monomial = BTreeMap<String, i64>;
+
+#[derive(Clone, Debug)]
+pub struct Poly {
+    pub terms: BTreeMap<Monomial, Coeff>,
+    pub atoms: HashMap<String, ASTNode>,
+}
+
+impl Poly {
+    pub fn constant(c: Coeff) -> Poly {
+        let mut terms = BTreeMap::new();
+        if !c.is_zero() {
+            terms.insert(Monomial::new(), c);
+        }
+        Poly { terms, atoms: HashMap::new() }
+    }
+
+    pub fn atom(node: ASTNode) -> Poly {
+        Poly::atom_pow(node, 1)
+    }
+
+    fn atom_pow(node: ASTNode, exponent: i64) -> Poly {
+        let key = node.to_string();
+        let mut poly = Poly::constant(Coeff::int(1));
+        poly.terms = BTreeMap::from([(Monomial::from([(key.clone(), exponent)]), Coeff::int(1))]);
+        poly.atoms.insert(key, node);
+        poly
+    }
+
+    /// Returns the value of a constant polynomial.
+    pub fn as_constant(&self) -> Option<Coeff> {
+        match self.terms.len() {
+            0 => Some(Coeff::int(0)),
+            1 => self.terms.get(&Monomial::new()).cloned(),
+            _ => None,
+        }
+    }
+
+    fn add_term(&mut self, monomial: Monomial, c: Coeff) {
+        let sum = match self.terms.remove(&monomial) {
+            Some(existing) => existing + c,
+            None => c,
+        };
+        if !sum.is_zero() {
+            self.terms.insert(monomial, sum);
+        }
+    }
+
+    pub fn add(mut self, other: Poly) -> Poly {
+        self.atoms.extend(other.atoms);
+        for (monomial, c) in other.terms {
+            self.add_term(monomial, c);
+        }
+        self
+    }
+
+    pub fn sub(self, other: Poly) -> Poly {
+        self.add(other.scale(Coeff::int(-1)))
+    }
+
+    pub fn scale(mut self, factor: Coeff) -> Poly {
+        if factor.is_zero() {
+            self.terms.clear();
+        }
+        for c in self.terms.values_mut() {
+            *c = c.clone() * factor.clone();
+        }
+        self
+    }
+
+    /// Multiplies two polynomials, or returns `None` if the result has too many terms.
+    pub fn mul(&self, other: &Poly) -> Option<Poly> {
+        if self.terms.len() * other.terms.len() > MAX_TERMS {
+            return None;
+        }
+
+        let mut result = Poly::constant(Coeff::int(0));
+        result.atoms = self.atoms.clone();
+        result.atoms.extend(other.atoms.clone());
+        for (a, c) in &self.terms {
+            for (b, d) in &other.terms {
+                let mut monomial = a.clone();
+                for (atom, exponent) in b {
+                    let sum = monomial.get(atom).unwrap_or(&0) + exponent;
+                    if sum == 0 {
+                        monomial.remove(atom);
+                    } else {
+                        monomial.insert(atom.clone(), sum);
+                    }
+                }
+                result.add_term(monomial, c.clone() * d.clone());
+            }
+        }
+        Some(result)
+    }
+
+    /// Raises the polynomial to an integer power. Negative powers are only possible for a single term,
+    /// `None` is returned for anything else or if the result has too many terms.
+    pub fn pow(&self, exponent: i64) -> Option<Poly> {
+        if exponent < 0 {
+            let (monomial, c) = match self.terms.len() {
+                1 => self.terms.iter().next().unwrap(),
+                _ => return None,
+            };
+            let inverse: Monomial = monomial.iter().map(|(atom, e)| (atom.clone(), e * exponent)).collect();
+            let mut result = Poly::constant(Coeff::int(0));
+            result.atoms = self.atoms.clone();
+            result.terms.insert(inverse, c.pow(exponent)?);
+            return Some(result);
+        }
+        if exponent > MAX_EXPANDED_POWER && self.terms.len() > 1 {
+            return None;
+        }
+        if self.terms.len() == 1 {
+            let (monomial, c) = self.terms.iter().next().unwrap();
+            let power: Monomial = monomial.iter().map(|(atom, e)| (atom.clone(), e * exponent)).collect();
+            let mut result = Poly::constant(Coeff::int(0));
+            result.atoms = self.atoms.clone();
+            result.terms.insert(power, c.pow(exponent)?);
+            return Some(result);
+        }
+
+        let mut result = Poly::constant(Coeff::int(1));
+        for _ in 0..exponent {
+            result = result.mul(self)?;
+        }
+        Some(result)
+    }
+
+    /// Builds the canonical form of an expression. Products and integer powers are multiplied out.
+    /// Dividing by zero is an error, as in ordinary evaluation, and so is a coefficient that isn't finite.
+    pub fn from_ast(node: &ASTNode) -> Result<Poly, String> {
+        let poly = match node {
+            ASTNode::Number(n) => Poly::constant(Coeff::Exact(BigRational::from_integer(n.clone()))),
+            ASTNode::Float(n) => Poly::constant(Coeff::Float(*n)),
+            ASTNode::Identifier(_) => Poly::atom(node.clone()),
+            ASTNode::UnaryOp { op: Operator::Neg, expr } => Poly::from_ast(expr)?.scale(Coeff::int(-1)),
+            ASTNode::BinaryOp { left, op, right } => {
+                let (a, b) = (Poly::from_ast(left)?, Poly::from_ast(right)?);
+                match op {
+                    Operator::Add => a.add(b),
+                    Operator::Sub => a.sub(b),
+                    Operator::Mul => a.mul(&b).unwrap_or_else(|| Poly::atom(binary(a.to_ast(), Operator::Mul, b.to_ast()))),
+                    Operator::Div if b.terms.is_empty() => return Err("Division by zero".to_string()),
+                    // Integers are divided as in ordinary evaluation, rounding towards zero: 7/2 = 3
+                    Operator::Div if a.as_integer().is_some() && b.as_integer().is_some() => {
+                        Poly::constant(Coeff::Exact(BigRational::from_integer(a.as_integer().unwrap() / b.as_integer().unwrap())))
+                    },
+                    // Otherwise symbolic division is exact division, a/b = a * b^-1
+                    Operator::Div => match b.pow(-1) {
+                        Some(inverse) => a.mul(&inverse),
+                        None => a.mul(&Poly::atom_pow(b.to_ast(), -1)),
+                    }.unwrap_or_else(|| Poly::atom(binary(a.to_ast(), Operator::Div, b.to_ast()))),
+                    Operator::Pow => match b.as_constant().and_then(|c| c.as_integer()).and_then(|n| n.to_i64()) {
+                        Some(n) if n < 0 && a.terms.is_empty() => return Err("Division by zero".to_string()),
+                        Some(n) => a.pow(n).unwrap_or_else(|| Poly::atom_pow(a.to_ast(), n)),
+                        None => Poly::atom(binary(a.to_ast(), Operator::Pow, b.to_ast())),
+                    },
+                    op => Poly::atom(binary(a.to_ast(), op.clone(), b.to_ast())),
+                }
+            },
+            ASTNode::UnaryOp { op, expr } => Poly::atom(ASTNode::UnaryOp {
+                op: op.clone(),
+                expr: Box::new(Poly::from_ast(expr)?.to_ast()),
+            }),
+            ASTNode::FunctionCall { name, args } => Poly::atom(ASTNode::FunctionCall {
+                name: name.clone(),
+                args: args.iter().map(|arg| Poly::from_ast(arg).map(|poly| poly.to_ast())).collect::<Result<_, _>>()?,
+            }),
+            other => Poly::atom(other.clone()),
+        };
+        if poly.terms.values().any(|c| !c.is_finite()) {
+            return Err("Division by zero".to_string());
+        }
+        Ok(poly)
+    }
+
+    // The value of a constant polynomial if it's an integer
+    fn as_integer(&self) -> Option<BigInt> {
+        self.as_constant()?.as_integer()
+    }
+
+    /// The terms in canonical order: highest degree first, then by the powers of the atoms in
+    /// alphabetical order, so x^2*y comes before x*y^2.
+    pub fn sorted_terms(&self) -> Vec<(&Monomial, &Coeff)> {
+        let mut terms: Vec<(&Monomial, &Coeff)> = self.terms.iter().collect();
+        terms.sort_by(|(a, _), (b, _)| {
+            let degree = |m: &Monomial| m.values().sum::<i64>();
+            degree(b).cmp(&degree(a)).then_with(|| {
+                let mut atoms: Vec<&String> = a.keys().chain(b.keys()).collect();
+                atoms.sort();
+                atoms.iter()
+                    .map(|atom| b.get(*atom).unwrap_or(&0).cmp(a.get(*atom).unwrap_or(&0)))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+        });
+        terms
+    }
+
+    pub fn monomial_to_ast(&self, monomial: &Monomial) -> Option<ASTNode> {
+        self.product_to_ast(None, monomial)
+    }
+
+    // Multiplies the atoms of a monomial onto an existing product, grouping to the left: 2*x*y
+    fn product_to_ast(&self, mut product: Option<ASTNode>, monomial: &Monomial) -> Option<ASTNode> {
+        for (key, exponent) in monomial {
+            let atom = self.atoms[key].clone();
+            let factor = match exponent {
+                1 => atom,
+                e => binary(atom, Operator::Pow, ASTNode::Number(BigInt::from(*e))),
+            };
+            product = Some(match product {
+                Some(product) => binary(product, Operator::Mul, factor),
+                None => factor,
+            });
+        }
+        product
+    }
+
+    // A single term without its sign, e.g. 3*x^2
+    fn term_to_ast(&self, monomial: &Monomial, c: &Coeff) -> ASTNode {
+        let coefficient = (!c.is_one() || monomial.is_empty()).then(|| c.to_node());
+        self.product_to_ast(coefficient, monomial).unwrap()
+    }
+
+    pub fn to_ast(&self) -> ASTNode {
+        let mut sum: Option<ASTNode> = None;
+        for (monomial, c) in self.sorted_terms() {
+            let (op, magnitude) = if c.is_negative() { (Operator::Sub, -c.clone()) } else { (Operator::Add, c.clone()) };
+            let term = self.term_to_ast(monomial, &magnitude);
+            sum = Some(match sum {
+                Some(sum) => binary(sum, op, term),
+                None if c.is_negative() => neg_term(term),
+                None => term,
+            });
+        }
+        sum.unwrap_or_else(|| ASTNode::Number(BigInt::zero()))
+    }
+}
+
+/// Negates a product by negating its first factor, so it prints as -3*x or -x*y and not -(3*x).
+pub fn neg_term(term: ASTNode) -> ASTNode {
+    match term {
+        ASTNode::Number(n) => ASTNode::Number(-n),
+        ASTNode::Float(n) => ASTNode::Float(-n),
+        ASTNode::BinaryOp { left, op: Operator::Mul, right } => binary(neg_term(*left), Operator::Mul, *right),
+        other => neg(other),
+    }
+}
@@ -0,0 +1,130 @@
+use nalgebra::DMatrix;
+use num_complex::Complex64;
+use num_rational::BigRational;
+
+use super::factor::rational_roots;
+use super::poly::Coeff;
+
+// Roots of polynomials in one variable. Rational roots of exact polynomials are split off first so
+// they stay exact, the rest is solved numerically: closed forms up to cubics, the eigenvalues of the
+// companion matrix beyond that.
+
+pub enum Root {
+    Exact(Coeff),
+    Approx(Complex64),
+}
+
+/// All distinct roots of the polynomial with the given coefficients, starting with the constant term.
+/// Real roots come first in increasing order, followed by complex roots.
+pub fn roots(coefficients: &[Coeff]) -> Vec<Root> {
+    let mut coefficients = coefficients.to_vec();
+    while coefficients.last().is_some_and(|c| c.is_zero()) {
+        coefficients.pop();
+    }
+
+    // Zero is split off by hand, rational_roots only finds roots that divide the constant term
+    let mut exact = vec![];
+    let zeros = coefficients.iter().take_while(|c| c.is_zero()).count();
+    if zeros > 0 && zeros < coefficients.len() {
+        exact.push(BigRational::from_integer(0.into()));
+        coefficients.drain(..zeros);
+    }
+
+    let exact_coefficients: Option<Vec<BigRational>> = coefficients.iter().map(|c| match c {
+        Coeff::Exact(r) => Some(r.clone()),
+        Coeff::Float(_) => None,
+    }).collect();
+    if let Some(rationals) = exact_coefficients.filter(|c| c.len() > 1) {
+        let (found, remaining, _) = rational_roots(rationals);
+        exact.extend(found.into_iter().map(|(root, _)| root));
+        coefficients = remaining.into_iter().map(Coeff::Exact).collect();
+    }
+
+    let approx = numeric_roots(&coefficients.iter().map(Coeff::to_f64).collect::<Vec<_>>());
+
+    // Merge both kinds, sorted by real part with the complex roots at the end
+    let key = |root: &Root| match root {
+        Root::Exact(c) => (false, c.to_f64(), 0.0),
+        Root::Approx(c) => (c.im != 0.0, c.re, c.im),
+    };
+    let mut roots: Vec<Root> = exact.into_iter().map(|r| Root::Exact(Coeff::Exact(r)))
+        .chain(approx.into_iter().map(Root::Approx))
+        .collect();
+    roots.sort_by(|a, b| {
+        let (a, b) = (key(a), key(b));
+        a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)).then(a.2.total_cmp(&b.2))
+    });
+    roots
+}
+
+fn numeric_roots(coefficients: &[f64]) -> Vec<Complex64> {
+    let roots = match coefficients {
+        [] | [_] => vec![],
+        [c, b] => vec![Complex64::from(-c / b)],
+        [c, b, a] => quadratic(*a, *b, *c),
+        [d, c, b, a] => cubic(*a, *b, *c, *d),
+        _ => companion_roots(coefficients),
+    };
+
+    // Imaginary parts that are just rounding errors are dropped, and repeated roots are only kept once.
+    // Complex roots of real polynomials come in conjugate pairs, so only one of each pair is kept and
+    // the other is its exact conjugate.
+    let scale = roots.iter().map(|c| c.norm()).fold(1.0, f64::max);
+    let mut distinct: Vec<Complex64> = vec![];
+    for root in roots {
+        let root = if root.im.abs() <= 1e-12 * scale { Complex64::from(root.re) } else { root };
+        if root.im >= 0.0 && !distinct.iter().any(|other| (other - root).norm() <= 1e-9 * scale) {
+            distinct.push(root);
+            if root.im > 0.0 {
+                distinct.push(root.conj());
+            }
+        }
+    }
+    distinct
+}
+
+fn quadratic(a: f64, b: f64, c: f64) -> Vec<Complex64> {
+    let discriminant = Complex64::from(b * b - 4.0 * a * c).sqrt();
+    // Avoids cancellation between -b and the square root
+    let q = if b >= 0.0 { -(b + discriminant) / 2.0 } else { -(b - discriminant) / 2.0 };
+    if q == Complex64::from(0.0) {
+        return vec![Complex64::from(0.0)];
+    }
+    vec![q / a, c / q]
+}
+
+// Cardano's formula on the depressed cubic t^3 + p*t + q, where x = t - b/(3a)
+fn cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<Complex64> {
+    let (b, c, d) = (b / a, c / a, d / a);
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+    let shift = -b / 3.0;
+
+    let root = Complex64::from(q * q / 4.0 + p * p * p / 27.0).sqrt();
+    // Of the two choices, the larger one keeps u away from zero
+    let s = if q >= 0.0 { -q / 2.0 - root } else { -q / 2.0 + root };
+    if s.norm() == 0.0 {
+        return vec![Complex64::from(shift)];
+    }
+
+    let u = s.powf(1.0 / 3.0);
+    let omega = Complex64::new(-0.5, 3f64.sqrt() / 2.0);
+    (0..3).map(|k| {
+        let u = u * omega.powi(k);
+        u - p / (3.0 * u) + shift
+    }).collect()
+}
+
+// The roots are the eigenvalues of the companion matrix of the monic polynomial
+fn companion_roots(coefficients: &[f64]) -> Vec<Complex64> {
+    let n = coefficients.len() - 1;
+    let leading = coefficients[n];
+    let mut companion = DMatrix::<f64>::zeros(n, n);
+    for i in 1..n {
+        companion[(i, i - 1)] = 1.0;
+    }
+    for i in 0..n {
+        companion[(i, n - 1)] = -coefficients[i] / leading;
+    }
+    companion.complex_eigenvalues().iter().cloned().collect()
+}
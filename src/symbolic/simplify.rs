@@ -0,0 +1,60 @@
+use crate::ast::parser::ASTNode;
+
+use super::factor::factor_poly;
+use super::poly::Poly;
+
+/// Multiplies out products and integer powers and collects like terms: (x + 1)^2 becomes x^2 + 2*x + 1.
+pub fn expand(expr: &ASTNode) -> Result<ASTNode, String> {
+    Ok(Poly::from_ast(expr)?.to_ast())
+}
+
+/// Brings an expression into canonical form: constants are folded, like terms collected and
+/// products multiplied out. If factoring the result gives a shorter expression, the factored
+/// form is returned instead, so (x + 1)^10 stays as it is.
+pub fn simplify(expr: ASTNode) -> Result<ASTNode, String> {
+    let poly = Poly::from_ast(&expr)?;
+    let expanded = poly.to_ast();
+    let factored = factor_poly(&poly);
+    if factored.to_string().len() < expanded.to_string().len() {
+        Ok(factored)
+    } else {
+        Ok(expanded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simplified(code: &str) -> Result<String, String> {
+        simplify(crate::ast::parse_expression(code)?).map(|expr| expr.to_string())
+    }
+
+    #[test]
+    fn collects_like_terms() {
+        assert_eq!(simplified("x + 2*x - 3"), Ok("3*x - 3".to_string()));
+        assert_eq!(simplified("(x + 1)^2 - 1"), Ok("x^2 + 2*x".to_string()));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(simplified("0/0"), Err("Division by zero".to_string()));
+        assert_eq!(simplified("x/0"), Err("Division by zero".to_string()));
+        assert_eq!(simplified("x/(x - x)"), Err("Division by zero".to_string()));
+        assert_eq!(simplified("0^-2*x"), Err("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn integers_are_divided_like_in_evaluation() {
+        assert_eq!(simplified("7/2*x"), Ok("3*x".to_string()));
+        assert_eq!(simplified("-7/2"), Ok("-3".to_string()));
+        assert_eq!(simplified("7.0/2*x"), Ok("3.5*x".to_string()));
+    }
+
+    #[test]
+    fn large_powers_are_not_evaluated() {
+        assert_eq!(simplified("2^10"), Ok("1024".to_string()));
+        assert_eq!(simplified("2^300000000"), Ok("2^300000000".to_string()));
+        assert_eq!(simplified("1^300000000"), Ok("1".to_string()));
+    }
+}
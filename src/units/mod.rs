@@ -0,0 +1,198 @@
+use std::f64::consts::PI;
+
+use crate::ast::parser::ASTNode;
+
+// Physical units. A unit is a factor that converts it to SI units, and the exponents of the seven SI
+// base units, its dimensions. Quantities are stored in SI units and only converted for display.
+//
+// Units are written after a number, e.g. '5 m' or '9.81 m/s^2'. Compound units are built with '*', '/'
+// and integer powers, without spaces. Prefixable units accept the SI prefixes, e.g. 'km', 'ms', 'kPa'.
+
+pub type Dimensions = [i32; 7];
+
+pub const DIMENSIONLESS: Dimensions = [0; 7];
+
+// The SI base units, in the order of the dimensions
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+// name, factor to SI, dimensions (m, kg, s, A, K, mol, cd), accepts prefixes
+const UNITS: &[(&str, f64, Dimensions, bool)] = &[
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    // Derived units. Results are printed with the ones up to ohm, e.g. kg*m/s^2 as N
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    ("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    ("eV", 1.602176634e-19, [2, 1, -2, 0, 0, 0, 0], true),
+    ("bar", 1e5, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("day", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("ft", 0.3048, [1, 0, 0, 0, 0, 0, 0], false),
+    ("mi", 1609.344, [1, 0, 0, 0, 0, 0, 0], false),
+    ("lb", 0.45359237, [0, 1, 0, 0, 0, 0, 0], false),
+    // Angles have no dimension, '90 deg' is just the number pi/2
+    ("rad", 1.0, DIMENSIONLESS, false),
+    ("deg", PI / 180.0, DIMENSIONLESS, false),
+];
+
+// Results are printed in the first of these units that matches, up to ohm
+const NAMED_RESULTS: usize = 14;
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("Y", 1e24), ("Z", 1e21), ("E", 1e18), ("P", 1e15), ("T", 1e12), ("G", 1e9), ("M", 1e6),
+    ("k", 1e3), ("h", 1e2), ("da", 1e1), ("d", 1e-1), ("c", 1e-2), ("m", 1e-3), ("u", 1e-6),
+    ("n", 1e-9), ("p", 1e-12), ("f", 1e-15), ("a", 1e-18),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unit {
+    pub name: String, // As it's written, e.g. 'km/h'
+    pub scale: f64, // The value of 1 of this unit in SI units
+    pub dims: Dimensions,
+}
+
+// A single unit name, with an optional prefix. Exact names win, so 'min' is a minute and not a milli-inch.
+fn lookup(name: &str) -> Option<(f64, Dimensions)> {
+    if let Some((_, scale, dims, _)) = UNITS.iter().find(|unit| unit.0 == name) {
+        return Some((*scale, *dims));
+    }
+    PREFIXES.iter().find_map(|(prefix, factor)| {
+        let base = name.strip_prefix(prefix)?;
+        let (_, scale, dims, _) = UNITS.iter().find(|unit| unit.0 == base && unit.3)?;
+        Some((factor * scale, *dims))
+    })
+}
+
+fn scale_dims(dims: &Dimensions, factor: i32) -> Dimensions {
+    dims.map(|d| d * factor)
+}
+
+pub fn add_dims(a: &Dimensions, b: &Dimensions) -> Dimensions {
+    std::array::from_fn(|i| a[i] + b[i])
+}
+
+pub fn sub_dims(a: &Dimensions, b: &Dimensions) -> Dimensions {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+
+impl Unit {
+    /// Parses a unit such as 'km', 'm/s^2' or 'kg*m^2/s^2'. Returns `None` if it isn't one.
+    pub fn parse(expr: &str) -> Option<Unit> {
+        let mut scale = 1.0;
+        let mut dims = DIMENSIONLESS;
+        let mut sign = 1;
+        let mut rest = expr;
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let (factor, exponent) = match rest[..end].split_once('^') {
+                Some((factor, exponent)) => (factor, exponent.parse::<i32>().ok()?),
+                None => (&rest[..end], 1),
+            };
+            let (factor_scale, factor_dims) = lookup(factor)?;
+            scale *= factor_scale.powi(sign * exponent);
+            dims = add_dims(&dims, &scale_dims(&factor_dims, sign * exponent));
+
+            if end == rest.len() {
+                break;
+            }
+            sign = if rest[end..].starts_with('/') { -1 } else { 1 };
+            rest = &rest[end + 1..];
+        }
+        Some(Unit { name: expr.to_string(), scale, dims })
+    }
+
+    /// The SI unit with the given dimensions, using a derived unit like N or V if there is one.
+    pub fn si(dims: Dimensions) -> Unit {
+        if let Some((name, ..)) = UNITS[..NAMED_RESULTS].iter().find(|unit| unit.2 == dims && unit.1 == 1.0) {
+            return Unit { name: name.to_string(), scale: 1.0, dims };
+        }
+
+        let power = |name: &str, exponent: i32| match exponent {
+            1 => name.to_string(),
+            _ => format!("{}^{}", name, exponent),
+        };
+        let positive: Vec<String> = BASE_UNITS.iter().zip(dims).filter(|(_, d)| *d > 0).map(|(name, d)| power(name, d)).collect();
+        let negative: Vec<String> = BASE_UNITS.iter().zip(dims).filter(|(_, d)| *d < 0).map(|(name, d)| power(name, -d)).collect();
+        let name = match (positive.is_empty(), negative.is_empty()) {
+            (_, true) => positive.join("*"),
+            (false, false) => format!("{}/{}", positive.join("*"), negative.join("/")),
+            // Only negative powers, e.g. s^-1
+            (true, false) => BASE_UNITS.iter().zip(dims).filter(|(_, d)| *d < 0).map(|(name, d)| power(name, d)).collect::<Vec<_>>().join("*"),
+        };
+        Unit { name, scale: 1.0, dims }
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dims == DIMENSIONLESS
+    }
+
+    /// The unit raised to an integer power. A single unit keeps its name, (2 km)^2 is in km^2.
+    pub fn powi(&self, exponent: i32) -> Unit {
+        if exponent == 1 {
+            return self.clone();
+        }
+        if self.name.contains(['*', '/', '^']) {
+            return Unit::si(scale_dims(&self.dims, exponent));
+        }
+        Unit {
+            name: format!("{}^{}", self.name, exponent),
+            scale: self.scale.powi(exponent),
+            dims: scale_dims(&self.dims, exponent),
+        }
+    }
+
+    /// Formats a value in SI units in this unit, e.g. '5 km'
+    pub fn format(&self, value: f64, format_float: &dyn Fn(f64) -> String) -> String {
+        format!("{} {}", format_float(value / self.scale), self.name)
+    }
+
+    /// A quantity literal for a value in SI units, e.g. to substitute a known quantity into an equation
+    pub fn to_node(&self, value: f64) -> ASTNode {
+        ASTNode::Quantity {
+            value: Box::new(ASTNode::Float(value / self.scale)),
+            unit: self.name.clone(),
+        }
+    }
+}
+
+/// Names the dimensions in error messages
+pub fn describe(dims: &Dimensions) -> String {
+    match *dims {
+        DIMENSIONLESS => "a number without unit".to_string(),
+        dims => Unit::si(dims).name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_compound_units() {
+        let unit = Unit::parse("km/h").unwrap();
+        assert!((unit.scale - 1000.0 / 3600.0).abs() < 1e-12);
+        assert_eq!(unit.dims, Unit::parse("m/s").unwrap().dims);
+        assert_eq!(Unit::parse("kg*m/s^2").unwrap().dims, Unit::parse("N").unwrap().dims);
+        assert!(Unit::parse("furlong").is_none());
+        assert!(Unit::parse("m^x").is_none());
+    }
+
+    #[test]
+    fn results_are_named_in_si_units() {
+        assert_eq!(Unit::si(Unit::parse("kg*m^2/s^2").unwrap().dims).name, "J");
+        assert_eq!(Unit::si(Unit::parse("m/s").unwrap().dims).name, "m/s");
+        assert_eq!(Unit::parse("km").unwrap().powi(2).name, "km^2");
+    }
+}
@@ -1,13 +1,18 @@
-use core::panic;
-use std::{rc::Rc, result};
+use std::rc::Rc;
 use std::cell::RefCell;
 
 use crate::ast::parser::ASTNode;
 
+pub mod number;
+pub mod settings;
 pub mod symbol;
+pub mod uncertain;
+
+use uncertain::Interval;
 
 use symbol::{Register, Scope, Symbol, DataType};
 
+#[allow(dead_code)]
 pub struct VMState {
     pub if_statement_met: bool,
     pub loop_break: bool,
@@ -22,6 +27,7 @@ impl VMState {
     }
 }
 
+#[allow(dead_code)]
 pub struct VM {
     pub pc: usize,
     pub running: bool,
@@ -35,6 +41,7 @@ pub struct VM {
 
 const MEM_SIZE: usize = 1024;
 
+#[allow(dead_code)]
 impl VM {
     pub fn new() -> Self {
         let mut vm_memory = Vec::with_capacity(MEM_SIZE);
@@ -64,11 +71,25 @@ impl VM {
         vm
     }
 
+    /// Whether a value counts as true in a condition. Every type has its own rule:
+    /// - bools are themselves
+    /// - numbers (real or complex) are true if they're not zero
+    /// - strings, vectors, matrices and lists are true if they're not empty
+    /// - functions and symbolic expressions are always true, null is always false
     pub fn truthy_check(&self, value: DataType) -> bool {
         match value {
-            DataType::Number(n) => n != 0,
+            DataType::Bool(b) => b,
+            DataType::Number(n) => !n.is_zero(),
+            DataType::Complex(c) => c.re != 0.0 || c.im != 0.0,
+            DataType::Quantity(value, _) => value != 0.0,
+            DataType::Uncertain(u) => u.value != 0.0,
+            DataType::Interval(i) => i != Interval::exact(0.0),
+            DataType::Vector(v) => !v.is_empty(),
+            DataType::Matrix(m) => !m.is_empty(),
+            DataType::List(items) => !items.is_empty(),
+            DataType::String(s) => !s.is_empty(),
+            DataType::Function(..) | DataType::Expr(_) | DataType::System(..) => true,
             DataType::Null() => false,
-            _ => true, // This WILL break if we add null
         }
     }
 
@@ -80,19 +101,20 @@ impl VM {
         self.scopes.pop();
     }
 
-    fn get_free_address_index(&self) -> usize {
-        self.memory.iter().position(|data| Rc::strong_count(data) == 1).unwrap()
+    // A cell is free when nothing but the memory refers to it
+    fn get_free_address_index(&self) -> Result<usize, String> {
+        match self.memory.iter().position(|data| Rc::strong_count(data) == 1) {
+            Some(address) if address < MEM_SIZE - 1 => Ok(address),
+            _ => Err("Memory full.".to_string()),
+        }
     }
 
-    fn get_free_address(&self) -> Rc<RefCell<DataType>> {
-        self.memory[self.get_free_address_index()].clone()
+    fn get_free_address(&self) -> Result<Rc<RefCell<DataType>>, String> {
+        Ok(self.memory[self.get_free_address_index()?].clone())
     }
 
     pub fn add_to_memory(&mut self, data: DataType) -> Result<Rc<RefCell<DataType>>, String> {
-        let address = self.get_free_address_index();
-        if address == MEM_SIZE - 1 {
-            return Err("Memory full.".to_string());
-        }
+        let address = self.get_free_address_index()?;
         
         let new_data = Rc::new(RefCell::new(data));
         self.memory[address] = new_data.clone();
@@ -109,7 +131,7 @@ impl VM {
     }
 
     pub fn get_or_add_to_memory(&mut self, data: DataType) -> Result<Rc<RefCell<DataType>>, String> {
-        if let Some(address) = self.memory.iter().position(|d| *d.borrow() == data) {
+        if let Some(address) = self.memory.iter().position(|d| d.borrow().is_identical(&data)) {
             Ok(self.memory[address].clone())
         } else {
             self.add_to_memory(data)
@@ -157,16 +179,15 @@ impl VM {
             Ok(address) => {
                 self.declare_variable_from_memory(name, address, mutable)
             },
-            Err(e) => {
-                panic!("{}", e);
-            },
+            Err(e) => Err(e),
         }
     }
 
     pub fn declare_variable_from_memory(&mut self, name: String, address: Rc<RefCell<DataType>>, mutable: bool) -> Result<(), String> {
         if let Some(current_scope) = self.scopes.last_mut() {
-            // Check if the variable is already declared
-            if current_scope.get_all_symbols().contains_key(&name) {
+            // Check if the variable is already declared. Variables of outer scopes can be shadowed,
+            // e.g. by a function parameter with the same name as a global variable
+            if current_scope.symbols.contains_key(&name) {
                 return Err(format!("Variable '{}' already declared in this scope.", name));
             }
 
@@ -227,7 +248,7 @@ impl VM {
     }
 
     pub fn declare_function(&mut self, name: String, params: Vec<String>, instructions: Vec<ASTNode>) -> Result<(), String> {
-        let function_address_index = self.get_free_address_index();
+        let function_address_index = self.get_free_address_index()?;
         let function_placeholder = self.memory[function_address_index].clone();
 
         let function_symbol = Symbol {
@@ -236,13 +257,8 @@ impl VM {
             mutable: false,
         };
 
-        let joined_scopes = self.scopes.iter().rev().cloned().fold(Scope::new(None), |mut acc, scope| {
-            for (name, symbol) in scope.symbols.iter() {
-                acc.symbols.insert(name.clone(), symbol.clone());
-            }
-            acc.symbols.insert(name.clone(), function_symbol.clone()); // Insert the function itself
-            acc
-        });
+        let mut joined_scopes = self.capture_scope();
+        joined_scopes.symbols.insert(name.clone(), function_symbol.clone()); // Insert the function itself
 
         if let Some(current_scope) = self.scopes.last_mut() {
             let function = DataType::Function(params, instructions, joined_scopes);
@@ -252,17 +268,97 @@ impl VM {
         Ok(())
     }
 
+    /// Declares a variable in the global scope, replacing a variable with the same name
+    pub fn replace_global(&mut self, name: String, value: DataType, mutable: bool) -> Result<(), String> {
+        let address = self.get_or_add_to_memory(value)?;
+        let global = self.scopes.first_mut().ok_or("No scope to declare variable in.")?;
+        global.symbols.insert(name.clone(), Symbol { name, address, mutable });
+        Ok(())
+    }
+
+    /// Removes a global symbol, its memory cell is free again once nothing else refers to it
+    pub fn remove_global(&mut self, name: &str) {
+        if let Some(global) = self.scopes.first_mut() {
+            global.symbols.remove(name);
+        }
+    }
+
+    /// Every declared symbol with its value, from the outermost scope to the innermost,
+    /// sorted by name within each scope.
+    pub fn list_symbols(&self) -> Vec<(String, DataType, bool)> {
+        let mut symbols = vec![];
+        for scope in &self.scopes {
+            let mut names: Vec<&String> = scope.symbols.keys().collect();
+            names.sort();
+            for name in names {
+                let symbol = &scope.symbols[name];
+                symbols.push((name.clone(), symbol.address.borrow().clone(), symbol.mutable));
+            }
+        }
+        symbols
+    }
+
+    /// All visible symbols joined into a single scope, for functions to capture when they're created.
+    /// Inner scopes come last, so their symbols shadow those of outer scopes.
+    pub fn capture_scope(&self) -> Scope {
+        self.scopes.iter().cloned().fold(Scope::new(None), |mut acc, scope| {
+            for (name, symbol) in scope.symbols.iter() {
+                acc.symbols.insert(name.clone(), symbol.clone());
+            }
+            acc
+        })
+    }
+
     pub fn get_function(&self, name: &str) -> Result<(Vec<String>, Vec<ASTNode>, Scope), String> {
         match self.get_variable_base(name) {
             Ok(Some(symbol)) => {
                 if let DataType::Function(params, instructions, captured_scope) = Rc::clone(&symbol.address).borrow().clone() {
                     Ok((params, instructions, captured_scope))
                 } else {
-                    Err(format!("Expected function, got: {:?}", Rc::clone(&symbol.address).borrow().clone()))
+                    Err(format!("Expected function, got {}", symbol.address.borrow().type_name()))
                 }
             },
             Ok(None) => Err(format!("Function '{}' not found.", name)),
             Err(e) => Err(e),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use number::Number;
+
+    #[test]
+    fn truthiness_of_every_type() {
+        use nalgebra::{DMatrix, DVector};
+        use num_complex::Complex64;
+        use uncertain::Uncertain;
+        let vm = VM::new();
+        let truthy = |value: DataType| vm.truthy_check(value);
+        assert!(truthy(DataType::Bool(true)) && !truthy(DataType::Bool(false)));
+        assert!(truthy(DataType::Number(Number::Int(-1))) && !truthy(DataType::Number(Number::Float(0.0))));
+        assert!(truthy(DataType::Complex(Complex64::new(0.0, 1.0))) && !truthy(DataType::Complex(Complex64::new(0.0, 0.0))));
+        assert!(!truthy(DataType::Quantity(0.0, crate::units::Unit::parse("m").unwrap())));
+        assert!(truthy(DataType::Uncertain(Uncertain { value: 1.0, error: 0.1 })));
+        assert!(!truthy(DataType::Interval(Interval::exact(0.0))));
+        assert!(truthy(DataType::Vector(DVector::from_element(1, 0.0))) && !truthy(DataType::Vector(DVector::zeros(0))));
+        assert!(!truthy(DataType::Matrix(DMatrix::zeros(0, 0))));
+        assert!(truthy(DataType::List(vec![DataType::Null()])) && !truthy(DataType::List(vec![])));
+        assert!(truthy(DataType::String("0".to_string())) && !truthy(DataType::String(String::new())));
+        assert!(truthy(DataType::Function(vec![], vec![], Scope::new(None))));
+        assert!(!truthy(DataType::Null()));
+    }
+
+    #[test]
+    fn full_memory_is_an_error() {
+        let mut vm = VM::new();
+        let mut result = Ok(());
+        for i in 0..MEM_SIZE {
+            result = vm.declare_variable(format!("v{}", i), DataType::Number(Number::Int(i as i64)), false);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(result, Err("Memory full.".to_string()));
+    }
+}
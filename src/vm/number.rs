@@ -0,0 +1,359 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+
+use super::settings::{self, Notation};
+
+// The numeric tower. Integers are stored as a machine word while they fit and are promoted
+// to an arbitrary precision integer as soon as an operation overflows. Results are always
+// normalized back down, so `Big` never holds a value that would fit in `Int`.
+// Mixing an integer with a float produces a float. Complex numbers live one level up, in `DataType`.
+
+#[derive(Clone, Debug)]
+pub enum Number {
+    Int(i64),
+    Big(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    pub fn from_bigint(n: BigInt) -> Number {
+        match n.to_i64() {
+            Some(n) => Number::Int(n),
+            None => Number::Big(n),
+        }
+    }
+
+    /// Converts the number to a big integer. Floats are truncated towards zero.
+    pub fn to_bigint(&self) -> BigInt {
+        match self {
+            Number::Int(n) => BigInt::from(*n),
+            Number::Big(n) => n.clone(),
+            Number::Float(n) => BigInt::from_f64(n.trunc()).unwrap_or_default(),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Float(n) => *n,
+        }
+    }
+
+    pub fn to_u32(&self) -> Option<u32> {
+        match self {
+            Number::Int(n) => u32::try_from(*n).ok(),
+            Number::Big(_) | Number::Float(_) => None,
+        }
+    }
+
+    /// Converts a finite float to an integer, truncating it. Integers are returned unchanged.
+    pub fn into_integer(self) -> Number {
+        match self {
+            Number::Float(n) if n.is_finite() => Number::from_bigint(self.to_bigint()),
+            other => other,
+        }
+    }
+
+    /// Returns `true` if the number is stored as an integer (not a float).
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Number::Int(_) | Number::Big(_))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(n) => *n == 0,
+            Number::Big(n) => n.is_zero(),
+            Number::Float(n) => *n == 0.0,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Number::Int(n) => *n < 0,
+            Number::Big(n) => n.is_negative(),
+            Number::Float(n) => *n < 0.0,
+        }
+    }
+
+    pub fn pow(&self, exponent: u32) -> Number {
+        match self {
+            Number::Int(n) => match n.checked_pow(exponent) {
+                Some(result) => Number::Int(result),
+                None => Number::from_bigint(num_traits::pow(self.to_bigint(), exponent as usize)),
+            },
+            Number::Big(n) => Number::from_bigint(num_traits::pow(n.clone(), exponent as usize)),
+            Number::Float(n) => Number::Float(n.powf(exponent as f64)),
+        }
+    }
+
+    /// Returns `n!`, or `None` if the number is negative or not an integer.
+    pub fn factorial(&self) -> Option<Number> {
+        if self.is_negative() || !self.is_integer() {
+            return None;
+        }
+
+        let mut result = Number::Int(1);
+        let mut i = Number::Int(2);
+        while i <= *self {
+            result = result * i.clone();
+            i = i + Number::Int(1);
+        }
+        Some(result)
+    }
+
+    // Tries the operation on machine integers first and falls back to big integers on overflow.
+    // If either side is a float, the operation is done on floats instead.
+    fn checked_or_big(
+        self,
+        other: Number,
+        checked: fn(i64, i64) -> Option<i64>,
+        big: fn(BigInt, BigInt) -> BigInt,
+        float: fn(f64, f64) -> f64,
+    ) -> Number {
+        match (&self, &other) {
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                return Number::Float(float(self.to_f64(), other.to_f64()));
+            },
+            (Number::Int(a), Number::Int(b)) => {
+                if let Some(result) = checked(*a, *b) {
+                    return Number::Int(result);
+                }
+            },
+            _ => {},
+        }
+        Number::from_bigint(big(self.to_bigint(), other.to_bigint()))
+    }
+
+    // Bitwise operations are only defined on integers
+    fn bitwise(
+        self,
+        other: Number,
+        small: fn(i64, i64) -> i64,
+        big: fn(BigInt, BigInt) -> BigInt,
+    ) -> Number {
+        match (self, other) {
+            (Number::Float(_), _) | (_, Number::Float(_)) => panic!("Expected integers"),
+            (Number::Int(a), Number::Int(b)) => Number::Int(small(a, b)),
+            (a, b) => Number::from_bigint(big(a.to_bigint(), b.to_bigint())),
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Number {
+        Number::Int(n)
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(n: BigInt) -> Number {
+        Number::from_bigint(n)
+    }
+}
+
+impl std::ops::Add for Number {
+    type Output = Number;
+
+    fn add(self, other: Number) -> Number {
+        self.checked_or_big(other, i64::checked_add, |a, b| a + b, |a, b| a + b)
+    }
+}
+
+impl std::ops::Sub for Number {
+    type Output = Number;
+
+    fn sub(self, other: Number) -> Number {
+        self.checked_or_big(other, i64::checked_sub, |a, b| a - b, |a, b| a - b)
+    }
+}
+
+impl std::ops::Mul for Number {
+    type Output = Number;
+
+    fn mul(self, other: Number) -> Number {
+        self.checked_or_big(other, i64::checked_mul, |a, b| a * b, |a, b| a * b)
+    }
+}
+
+impl std::ops::Div for Number {
+    type Output = Number;
+
+    fn div(self, other: Number) -> Number {
+        self.checked_or_big(other, i64::checked_div, |a, b| a / b, |a, b| a / b)
+    }
+}
+
+impl std::ops::Rem for Number {
+    type Output = Number;
+
+    fn rem(self, other: Number) -> Number {
+        self.checked_or_big(other, i64::checked_rem, |a, b| a % b, |a, b| a % b)
+    }
+}
+
+impl std::ops::Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Number::Int(n) => match n.checked_neg() {
+                Some(n) => Number::Int(n),
+                None => Number::from_bigint(-BigInt::from(n)),
+            },
+            Number::Big(n) => Number::from_bigint(-n),
+            Number::Float(n) => Number::Float(-n),
+        }
+    }
+}
+
+impl std::ops::Not for Number {
+    type Output = Number;
+
+    fn not(self) -> Number {
+        match self {
+            Number::Int(n) => Number::Int(!n),
+            Number::Big(n) => Number::from_bigint(!n),
+            Number::Float(_) => panic!("Expected integer"),
+        }
+    }
+}
+
+impl std::ops::BitAnd for Number {
+    type Output = Number;
+
+    fn bitand(self, other: Number) -> Number {
+        self.bitwise(other, |a, b| a & b, |a, b| a & b)
+    }
+}
+
+impl std::ops::BitOr for Number {
+    type Output = Number;
+
+    fn bitor(self, other: Number) -> Number {
+        self.bitwise(other, |a, b| a | b, |a, b| a | b)
+    }
+}
+
+impl std::ops::BitXor for Number {
+    type Output = Number;
+
+    fn bitxor(self, other: Number) -> Number {
+        self.bitwise(other, |a, b| a ^ b, |a, b| a ^ b)
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(b)),
+            (Number::Float(_), _) | (_, Number::Float(_)) => self.to_f64().partial_cmp(&other.to_f64()),
+            _ => Some(self.to_bigint().cmp(&other.to_bigint())),
+        }
+    }
+}
+
+/// Formats a float the way a calculator would: plain notation for everyday magnitudes and
+/// scientific notation for very large or very small values. The digits and notation settings
+/// change this, see `settings`.
+pub fn format_float(n: f64) -> String {
+    let settings = settings::get();
+    if !n.is_finite() {
+        return format!("{}", n);
+    }
+    let n = match settings.digits {
+        Some(digits) => round_significant(n, digits),
+        None => n,
+    };
+    let magnitude = n.abs();
+    match settings.notation {
+        Notation::Normal if magnitude != 0.0 && !(1e-6..1e16).contains(&magnitude) => format!("{:e}", n),
+        Notation::Normal => format!("{}", n),
+        Notation::Scientific => format!("{:e}", n),
+        Notation::Engineering if magnitude == 0.0 => "0".to_string(),
+        Notation::Engineering => {
+            let exponent = (magnitude.log10().floor() / 3.0).floor() as i32 * 3;
+            // The division adds rounding errors in the last digits, like 1.2000000000000002
+            let mantissa = round_significant(n / 10f64.powi(exponent), settings.digits.unwrap_or(15));
+            format!("{}e{}", mantissa, exponent)
+        },
+    }
+}
+
+fn round_significant(n: f64, digits: usize) -> f64 {
+    format!("{:.*e}", digits.saturating_sub(1), n).parse().unwrap_or(n)
+}
+
+/// Formats an integer in base 2, 8 or 16 with its prefix, e.g. -0xff
+pub fn format_radix(n: &BigInt, base: u32) -> String {
+    let sign = if n.is_negative() { "-" } else { "" };
+    let magnitude = n.magnitude();
+    match base {
+        16 => format!("{}0x{:x}", sign, magnitude),
+        2 => format!("{}0b{:b}", sign, magnitude),
+        8 => format!("{}0o{:o}", sign, magnitude),
+        _ => format!("{}{}", sign, magnitude),
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self, settings::get().base) {
+            (Number::Float(n), _) => write!(f, "{}", format_float(*n)),
+            (Number::Int(n), 10) => write!(f, "{}", n),
+            (Number::Big(n), 10) => write!(f, "{}", n),
+            (n, base) => write!(f, "{}", format_radix(&n.to_bigint(), base)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(digits: &str) -> Number {
+        Number::from_bigint(digits.parse().unwrap())
+    }
+
+    #[test]
+    fn overflowing_integers_are_promoted() {
+        let sum = Number::Int(i64::MAX) + Number::Int(1);
+        assert!(matches!(sum, Number::Big(_)));
+        assert_eq!(sum, big("9223372036854775808"));
+        assert_eq!(Number::Int(i64::MAX) * Number::Int(2), big("18446744073709551614"));
+        assert_eq!(-Number::Int(i64::MIN), big("9223372036854775808"));
+        assert_eq!(Number::Int(2).pow(100), big("1267650600228229401496703205376"));
+    }
+
+    #[test]
+    fn results_that_fit_are_demoted() {
+        let difference = (Number::Int(i64::MAX) + Number::Int(1)) - Number::Int(1);
+        assert!(matches!(difference, Number::Int(i64::MAX)));
+        assert!(matches!(big("100000000000000000000") / big("10000000000000000000"), Number::Int(10)));
+    }
+
+    #[test]
+    fn integer_division_rounds_towards_zero() {
+        assert!(matches!(Number::Int(7) / Number::Int(2), Number::Int(3)));
+        assert!(matches!(Number::Int(-7) / Number::Int(2), Number::Int(-3)));
+        assert!(matches!(Number::Int(-7) % Number::Int(2), Number::Int(-1)));
+        assert_eq!(Number::Int(i64::MIN) / Number::Int(-1), big("9223372036854775808"));
+    }
+
+    #[test]
+    fn floats_make_the_result_a_float() {
+        assert!(matches!(Number::Int(7) / Number::Float(2.0), Number::Float(n) if n == 3.5));
+        assert!(matches!(big("100000000000000000000") + Number::Float(0.5), Number::Float(_)));
+        assert_eq!(Number::Int(1), Number::Float(1.0));
+        assert!(Number::Int(1) < big("100000000000000000000"));
+    }
+}
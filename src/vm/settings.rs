@@ -0,0 +1,133 @@
+use std::cell::Cell;
+
+// Settings of the session: how angles are read by the trig built-ins and how numbers are shown.
+// They are kept per thread instead of in the interpreter, because they are applied by the `Display`
+// impls of the values, which have no access to it. Changed with 'set angle deg' or ':set angle deg'.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Angle {
+    Radians,
+    Degrees,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Notation {
+    Normal, // Plain numbers, scientific notation only for very large or very small ones
+    Scientific, // 1.5e4
+    Engineering, // 15e3, with exponents that are multiples of 3
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub angle: Angle,
+    pub digits: Option<usize>, // Significant digits of floats, None for as many as needed
+    pub notation: Notation,
+    pub base: u32, // The base integers are shown in: 2, 8, 10 or 16
+}
+
+const DEFAULT: Settings = Settings { angle: Angle::Radians, digits: None, notation: Notation::Normal, base: 10 };
+
+// More digits than an f64 has don't show anything new
+const MAX_DIGITS: usize = 17;
+
+pub const NAMES: &[&str] = &["angle", "digits", "notation", "base"];
+
+thread_local! {
+    static SETTINGS: Cell<Settings> = const { Cell::new(DEFAULT) };
+}
+
+pub fn get() -> Settings {
+    SETTINGS.with(|settings| settings.get())
+}
+
+pub fn reset() {
+    SETTINGS.with(|settings| settings.set(DEFAULT));
+}
+
+/// Changes the setting `name`, e.g. `set("angle", "deg")`
+pub fn set(name: &str, value: &str) -> Result<(), String> {
+    let mut settings = get();
+    match (name, value) {
+        ("angle", "rad" | "radians") => settings.angle = Angle::Radians,
+        ("angle", "deg" | "degrees") => settings.angle = Angle::Degrees,
+        ("angle", _) => return Err(format!("Unknown angle unit '{}', expected rad or deg", value)),
+        ("digits", "auto") => settings.digits = None,
+        ("digits", _) => match value.parse::<usize>() {
+            Ok(digits) if (1..=MAX_DIGITS).contains(&digits) => settings.digits = Some(digits),
+            _ => return Err(format!("The digits must be from 1 to {} or auto, got '{}'", MAX_DIGITS, value)),
+        },
+        ("notation", "normal") => settings.notation = Notation::Normal,
+        ("notation", "sci" | "scientific") => settings.notation = Notation::Scientific,
+        ("notation", "eng" | "engineering") => settings.notation = Notation::Engineering,
+        ("notation", _) => return Err(format!("Unknown notation '{}', expected normal, sci or eng", value)),
+        ("base", "dec") => settings.base = 10,
+        ("base", "hex") => settings.base = 16,
+        ("base", "bin") => settings.base = 2,
+        ("base", "oct") => settings.base = 8,
+        ("base", _) => return Err(format!("Unknown base '{}', expected dec, hex, bin or oct", value)),
+        _ => return Err(format!("Unknown setting '{}', the settings are {}", name, NAMES.join(", "))),
+    }
+    SETTINGS.with(|current| current.set(settings));
+    Ok(())
+}
+
+/// The current settings, each written like in a 'set' statement
+pub fn describe() -> Vec<String> {
+    let settings = get();
+    let angle = match settings.angle {
+        Angle::Radians => "rad",
+        Angle::Degrees => "deg",
+    };
+    let digits = settings.digits.map_or("auto".to_string(), |digits| digits.to_string());
+    let notation = match settings.notation {
+        Notation::Normal => "normal",
+        Notation::Scientific => "sci",
+        Notation::Engineering => "eng",
+    };
+    let base = match settings.base {
+        16 => "hex",
+        2 => "bin",
+        8 => "oct",
+        _ => "dec",
+    };
+    vec![format!("angle {}", angle), format!("digits {}", digits), format!("notation {}", notation), format!("base {}", base)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::number::{format_float, Number};
+
+    // Each test runs in its own thread, so it starts with the default settings. Tests still reset
+    // them at the end, in case the test harness reuses a thread.
+    #[test]
+    fn settings_are_changed_by_name() {
+        set("angle", "deg").unwrap();
+        set("digits", "4").unwrap();
+        assert_eq!(get().angle, Angle::Degrees);
+        assert_eq!(describe(), ["angle deg", "digits 4", "notation normal", "base dec"]);
+        reset();
+        assert_eq!(get(), DEFAULT);
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        assert!(set("angle", "grad").is_err());
+        assert!(set("digits", "0").is_err());
+        assert!(set("digits", "18").is_err());
+        assert!(set("colour", "red").is_err());
+        assert_eq!(get(), DEFAULT);
+        reset();
+    }
+
+    #[test]
+    fn numbers_are_shown_with_the_settings() {
+        set("digits", "3").unwrap();
+        assert_eq!(format_float(1.23456), "1.23");
+        set("notation", "eng").unwrap();
+        assert_eq!(format_float(12345.0), "12.3e3");
+        set("base", "hex").unwrap();
+        assert_eq!(Number::Int(-255).to_string(), "-0xff");
+        reset();
+    }
+}
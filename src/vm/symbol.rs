@@ -1,18 +1,27 @@
 use std::collections::HashMap;
 use std::rc::Rc;
-use std::cell::{Ref, RefCell};
+use std::cell::RefCell;
 use std::fmt;
 
+use nalgebra::{DMatrix, DVector};
+use num_complex::Complex64;
+
 use crate::ast::parser::ASTNode;
+use crate::units::Unit;
+
+use super::number::{format_float, Number};
+use super::uncertain::{Interval, Uncertain};
 
-// This programming language is supposed to be number-only. There are no datatypes like strings or booleans.
-// Only numbers and functions.
+// This programming language is mostly about numbers. There are numbers (real and complex), quantities with
+// units, numbers with an uncertainty and intervals, booleans, vectors and matrices of real numbers, lists, functions, strings and symbolic expressions.
+// Strings exist mainly to label output.
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Register {
     pub address: Rc<RefCell<DataType>>,
 }
 
+#[allow(dead_code)]
 impl Register {
     pub fn new(address: Rc<RefCell<DataType>>) -> Register {
         Register {
@@ -20,19 +29,24 @@ impl Register {
         }
     }
 
-    pub fn get_value(&self, memory: &[Rc<RefCell<DataType>>]) -> Option<DataType> {
+    pub fn get_value(&self, _memory: &[Rc<RefCell<DataType>>]) -> Option<DataType> {
         if self.address.borrow().is_null() {
             return None;
         }
         let address = self.address.borrow();
         match &*address {
-            DataType::Number(n) => Some(DataType::Number(*n)),
+            DataType::Number(n) => Some(DataType::Number(n.clone())),
+            DataType::Complex(c) => Some(DataType::Complex(*c)),
+            DataType::Uncertain(u) => Some(DataType::Uncertain(*u)),
+            DataType::Interval(i) => Some(DataType::Interval(*i)),
+            DataType::Bool(b) => Some(DataType::Bool(*b)),
+            DataType::Quantity(..) | DataType::Vector(_) | DataType::Matrix(_) | DataType::List(_) | DataType::String(_) | DataType::Expr(_) | DataType::System(..) => Some(address.clone()),
             DataType::Function(_, _, _) => Some(DataType::Function(vec![], vec![], Scope::new(None))),
             DataType::Null() => None,
         }
     }
 
-    pub fn set_value(&self, memory: &mut Vec<DataType>, value: DataType) {
+    pub fn set_value(&self, _memory: &mut Vec<DataType>, value: DataType) {
         *self.address.borrow_mut() = value;
     }
     
@@ -40,8 +54,19 @@ impl Register {
 
 #[derive(Clone, Debug)]
 pub enum DataType {
-    Number(i32),
+    Number(Number),
+    Bool(bool),
+    Complex(Complex64), // Never has a zero imaginary part after arithmetic, see `DataType::from_complex`
+    Quantity(f64, Unit), // The value in SI units, and the unit it's shown in
+    Uncertain(Uncertain), // A measurement with an error, written as 9.81 +- 0.02
+    Interval(Interval), // All numbers between two bounds, written as [1 .. 2]
+    Vector(DVector<f64>), // Column vector, written as [1, 2, 3]
+    Matrix(DMatrix<f64>), // Written row by row as [[1, 2], [3, 4]]
+    List(Vec<DataType>), // Any other bracketed sequence of values, e.g. complex eigenvalues
+    String(String),
+    Expr(ASTNode), // A symbolic expression, e.g. the result of diff(x^2, x)
     Function(Vec<String>, Vec<ASTNode>, Scope),
+    System(Vec<String>, Vec<ASTNode>), // A named math block: its variables and its equations
     Null(),
 }
 
@@ -49,10 +74,7 @@ impl std::ops::Add for DataType {
     type Output = DataType;
 
     fn add(self, other: DataType) -> DataType {
-        match (self, other) {
-            (DataType::Number(a), DataType::Number(b)) => DataType::Number(a + b),
-            _ => panic!("Expected numbers"),
-        }
+        self.arithmetic(other, |a, b| a + b, |a, b| a + b, |a, b| a + b, |a, b| a + b)
     }
 }
 
@@ -60,10 +82,7 @@ impl std::ops::Sub for DataType {
     type Output = DataType;
 
     fn sub(self, other: DataType) -> DataType {
-        match (self, other) {
-            (DataType::Number(a), DataType::Number(b)) => DataType::Number(a - b),
-            _ => panic!("Expected numbers"),
-        }
+        self.arithmetic(other, |a, b| a - b, |a, b| a - b, |a, b| a - b, |a, b| a - b)
     }
 }
 
@@ -71,10 +90,7 @@ impl std::ops::Mul for DataType {
     type Output = DataType;
 
     fn mul(self, other: DataType) -> DataType {
-        match (self, other) {
-            (DataType::Number(a), DataType::Number(b)) => DataType::Number(a * b),
-            _ => panic!("Expected numbers"),
-        }
+        self.arithmetic(other, |a, b| a * b, |a, b| a * b, |a, b| a * b, |a, b| a * b)
     }
 }
 
@@ -82,10 +98,7 @@ impl std::ops::Div for DataType {
     type Output = DataType;
 
     fn div(self, other: DataType) -> DataType {
-        match (self, other) {
-            (DataType::Number(a), DataType::Number(b)) => DataType::Number(a / b),
-            _ => panic!("Expected numbers"),
-        }
+        self.arithmetic(other, |a, b| a / b, |a, b| a / b, |a, b| a / b, |a, b| a / b)
     }
 }
 
@@ -148,29 +161,44 @@ impl std::cmp::PartialEq for DataType {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (DataType::Number(a), DataType::Number(b)) => a == b,
+            (DataType::Quantity(a, u), DataType::Quantity(b, v)) => u.dims == v.dims && a == b,
+            (DataType::Uncertain(a), DataType::Uncertain(b)) => a == b,
+            (DataType::Interval(a), DataType::Interval(b)) => a == b,
+            (DataType::Vector(a), DataType::Vector(b)) => a == b,
+            (DataType::Matrix(a), DataType::Matrix(b)) => a == b,
+            (DataType::List(a), DataType::List(b)) => a == b,
+            (DataType::String(a), DataType::String(b)) => a == b,
+            (DataType::Expr(a), DataType::Expr(b)) => a.to_string() == b.to_string(),
+            (DataType::Complex(_), _) | (_, DataType::Complex(_)) => match (self.to_complex(), other.to_complex()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
             (DataType::Function(_, _, _), DataType::Function(_, _, _)) => false,
+            (DataType::System(..), DataType::System(..)) => false,
+            (DataType::Bool(a), DataType::Bool(b)) => a == b,
             (DataType::Null(), DataType::Null()) => true,
             _ => false,
         }
     }
 }
 
+// Only real numbers and strings can be ordered. Comparing anything else returns `None`,
+// which the interpreter reports as an error. Uncertain values are compared by their value,
+// intervals only if one lies entirely before the other.
 impl std::cmp::PartialOrd for DataType {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         match (self, other) {
             (DataType::Number(a), DataType::Number(b)) => a.partial_cmp(b),
-            _ => panic!("Expected numbers"),
-        }
-    }
-}
-
-impl std::cmp::Eq for DataType {}
-
-impl std::cmp::Ord for DataType {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (self, other) {
-            (DataType::Number(a), DataType::Number(b)) => a.cmp(b),
-            _ => panic!("Expected numbers"),
+            (DataType::Quantity(a, u), DataType::Quantity(b, v)) if u.dims == v.dims => a.partial_cmp(b),
+            (a, b) if a.is_uncertain() || b.is_uncertain() => match (a.to_uncertain(), b.to_uncertain(), a.to_interval(), b.to_interval()) {
+                (Some(a), Some(b), _, _) => a.value.partial_cmp(&b.value),
+                (_, _, Some(a), Some(b)) if a.upper < b.lower => Some(std::cmp::Ordering::Less),
+                (_, _, Some(a), Some(b)) if a.lower > b.upper => Some(std::cmp::Ordering::Greater),
+                (_, _, Some(a), Some(b)) if a == b && a.lower == a.upper => Some(std::cmp::Ordering::Equal),
+                _ => None,
+            },
+            (DataType::String(a), DataType::String(b)) => a.partial_cmp(b),
+            _ => None,
         }
     }
 }
@@ -182,6 +210,12 @@ impl std::ops::Neg for DataType {
     fn neg(self) -> DataType {
         match self {
             DataType::Number(n) => DataType::Number(-n),
+            DataType::Complex(c) => DataType::Complex(-c),
+            DataType::Quantity(value, unit) => DataType::Quantity(-value, unit),
+            DataType::Uncertain(u) => DataType::Uncertain(-u),
+            DataType::Interval(i) => DataType::Interval(-i),
+            DataType::Vector(v) => DataType::Vector(-v),
+            DataType::Matrix(m) => DataType::Matrix(-m),
             _ => panic!("Expected number"),
         }
     }
@@ -192,6 +226,7 @@ impl DataType {
     ///
     /// [`Null`]: DataType::Null
     #[must_use]
+    #[allow(dead_code)]
     pub fn is_null(&self) -> bool {
         matches!(self, Self::Null(..))
     }
@@ -203,13 +238,274 @@ impl DataType {
     pub fn is_number(&self) -> bool {
         matches!(self, Self::Number(..))
     }
+
+    /// A human readable name of the type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DataType::Number(_) => "number",
+            DataType::Bool(_) => "bool",
+            DataType::Complex(_) => "complex number",
+            DataType::Quantity(..) => "quantity",
+            DataType::Uncertain(_) => "uncertain number",
+            DataType::Interval(_) => "interval",
+            DataType::Vector(_) => "vector",
+            DataType::Matrix(_) => "matrix",
+            DataType::List(_) => "list",
+            DataType::String(_) => "string",
+            DataType::Expr(_) => "expression",
+            DataType::Function(..) => "function",
+            DataType::System(..) => "system",
+            DataType::Null() => "null",
+        }
+    }
+
+    /// Returns `true` if the data type is a [`Vector`] or a [`Matrix`].
+    ///
+    /// [`Vector`]: DataType::Vector
+    /// [`Matrix`]: DataType::Matrix
+    #[must_use]
+    pub fn is_linear_algebra(&self) -> bool {
+        matches!(self, Self::Vector(..) | Self::Matrix(..))
+    }
+
+    /// Returns `true` if the data type is a real or complex number.
+    #[must_use]
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Self::Number(..) | Self::Complex(..))
+    }
+
+    /// Returns `true` if the data type is a number with an uncertainty or an interval.
+    #[must_use]
+    pub fn is_uncertain(&self) -> bool {
+        matches!(self, Self::Uncertain(..) | Self::Interval(..))
+    }
+
+    /// Real numbers are uncertain numbers without error
+    pub fn to_uncertain(&self) -> Option<Uncertain> {
+        match self {
+            DataType::Number(n) => Some(Uncertain::exact(n.to_f64())),
+            DataType::Uncertain(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Real numbers are intervals with equal bounds
+    pub fn to_interval(&self) -> Option<Interval> {
+        match self {
+            DataType::Number(n) => Some(Interval::exact(n.to_f64())),
+            DataType::Interval(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            DataType::Number(n) => Some(n.to_f64()),
+            _ => None,
+        }
+    }
+
+    pub fn to_complex(&self) -> Option<Complex64> {
+        match self {
+            DataType::Number(n) => Some(Complex64::new(n.to_f64(), 0.0)),
+            DataType::Complex(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    /// Wraps a complex number, demoting it to a real float if the imaginary part is zero.
+    pub fn from_complex(c: Complex64) -> DataType {
+        if c.im == 0.0 {
+            DataType::Number(Number::Float(c.re))
+        } else {
+            DataType::Complex(c)
+        }
+    }
+
+    // Applies an arithmetic operation, promoting both operands to complex numbers if either of them is complex.
+    // A real number combined with an uncertain number or an interval is promoted to one without error.
+    fn arithmetic(
+        self,
+        other: DataType,
+        real: fn(Number, Number) -> Number,
+        complex: fn(Complex64, Complex64) -> Complex64,
+        uncertain: fn(Uncertain, Uncertain) -> Uncertain,
+        interval: fn(Interval, Interval) -> Interval,
+    ) -> DataType {
+        match (self, other) {
+            (DataType::Number(a), DataType::Number(b)) => DataType::Number(real(a, b)),
+            (a, b) if a.is_uncertain() || b.is_uncertain() => match (a.to_uncertain(), b.to_uncertain(), a.to_interval(), b.to_interval()) {
+                (Some(a), Some(b), _, _) => DataType::Uncertain(uncertain(a, b)),
+                (_, _, Some(a), Some(b)) => DataType::Interval(interval(a, b)),
+                _ => panic!("Expected numbers"),
+            },
+            (a, b) => match (a.to_complex(), b.to_complex()) {
+                (Some(a), Some(b)) => DataType::from_complex(complex(a, b)),
+                _ => panic!("Expected numbers"),
+            },
+        }
+    }
+
+    pub fn pow(self, exponent: DataType) -> Result<DataType, String> {
+        match (self, exponent) {
+            (base, exponent) if base.is_uncertain() || exponent.is_uncertain() => base.uncertain_pow(exponent),
+            (DataType::Number(base), DataType::Number(exponent)) => {
+                if exponent.is_integer() && !exponent.is_negative() {
+                    return match exponent.to_u32() {
+                        Some(exponent) => Ok(DataType::Number(base.pow(exponent))),
+                        None => Err(format!("Exponent {} is too large", exponent)),
+                    };
+                }
+
+                let (base, exponent) = (base.to_f64(), exponent.to_f64());
+                if base < 0.0 && exponent.fract() != 0.0 {
+                    // A negative base with a fractional exponent has no real result
+                    return Ok(DataType::from_complex(Complex64::new(base, 0.0).powf(exponent)));
+                }
+                Ok(DataType::Number(Number::Float(base.powf(exponent))))
+            },
+            // Integer powers multiply, so (1i)^2 is exactly -1 instead of -1+1.2e-16i
+            (DataType::Complex(base), DataType::Number(exponent)) if exponent.is_integer() && exponent.to_f64().abs() <= i32::MAX as f64 => {
+                Ok(DataType::from_complex(base.powi(exponent.to_f64() as i32)))
+            },
+            (base, exponent) => match (base.to_complex(), exponent.to_complex()) {
+                (Some(base), Some(exponent)) => Ok(DataType::from_complex(base.powc(exponent))),
+                _ => Err(format!("Expected numbers, got {} and {}", base.type_name(), exponent.type_name())),
+            },
+        }
+    }
+
+    fn uncertain_pow(self, exponent: DataType) -> Result<DataType, String> {
+        if let (Some(base), Some(exponent)) = (self.to_uncertain(), exponent.to_uncertain()) {
+            let is_integer = exponent.error == 0.0 && exponent.value.fract() == 0.0;
+            if (base.value < 0.0 && !is_integer) || (base.value <= 0.0 && exponent.error != 0.0) {
+                return Err(format!("Cannot raise {} to the power {}, the base must be positive", base, exponent));
+            }
+            return Ok(DataType::Uncertain(base.powf(exponent)));
+        }
+
+        let error = || Err(format!("Cannot raise {} to the power {}", self, exponent));
+        match (&self, &exponent) {
+            (DataType::Interval(base), DataType::Number(n)) => {
+                let n = n.to_f64();
+                match n {
+                    _ if n.fract() == 0.0 && (n > 0.0 || !base.contains(0.0)) => Ok(DataType::Interval(base.powi(n as i32))),
+                    _ if base.lower >= 0.0 && (n > 0.0 || base.lower > 0.0) => Ok(DataType::Interval(base.monotonic(|x| x.powf(n)))),
+                    _ => error(),
+                }
+            },
+            (DataType::Number(base), DataType::Interval(exponent)) if base.to_f64() > 0.0 => {
+                let base = base.to_f64();
+                Ok(DataType::Interval(exponent.monotonic(|x| base.powf(x))))
+            },
+            _ => error(),
+        }
+    }
+
+    pub fn factorial(self) -> Result<DataType, String> {
+        match self {
+            DataType::Number(n) => match n.factorial() {
+                Some(result) => Ok(DataType::Number(result)),
+                None => Err(format!("Factorial is only defined for non-negative integers, got {}", n)),
+            },
+            other => Err(format!("Expected number, got {}", other.type_name())),
+        }
+    }
+
+    /// Checks whether two values are the exact same value, including their representation.
+    /// Unlike `==`, the integer 1 and the float 1.0 are not the same, and neither are 1000 m and 1 km.
+    pub fn is_identical(&self, other: &DataType) -> bool {
+        match (self, other) {
+            (DataType::Number(Number::Float(a)), DataType::Number(Number::Float(b))) => a.to_bits() == b.to_bits(),
+            (DataType::Number(Number::Float(_)), _) | (_, DataType::Number(Number::Float(_))) => false,
+            (DataType::Complex(a), DataType::Complex(b)) => a.re.to_bits() == b.re.to_bits() && a.im.to_bits() == b.im.to_bits(),
+            (DataType::Complex(_), _) | (_, DataType::Complex(_)) => false,
+            (DataType::Quantity(a, u), DataType::Quantity(b, v)) => a.to_bits() == b.to_bits() && u == v,
+            _ => self == other,
+        }
+    }
+
+    /// Formats complex numbers in polar form as `r*e^(θi)`, which can be read back in as an expression.
+    /// Real numbers are formatted as usual.
+    pub fn display_polar(&self) -> String {
+        match self {
+            DataType::Complex(c) => {
+                let (r, theta) = c.to_polar();
+                format!("{}*e^({}i)", format_float(r), format_float(theta))
+            },
+            other => other.to_string(),
+        }
+    }
+}
+
+// Matrices are printed over multiple lines with their columns aligned:
+// ┌ 1  2 ┐
+// └ 3  4 ┘
+pub fn format_matrix(m: &DMatrix<f64>, format_cell: &dyn Fn(f64) -> String) -> String {
+    if m.is_empty() {
+        return "[]".to_string();
+    }
+
+    let cells: Vec<Vec<String>> = m.row_iter()
+        .map(|row| row.iter().map(|x| format_cell(*x)).collect())
+        .collect();
+    let widths: Vec<usize> = (0..m.ncols())
+        .map(|col| cells.iter().map(|row| row[col].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    let mut lines = Vec::new();
+    for (i, row) in cells.iter().enumerate() {
+        let (left, right) = match i {
+            _ if m.nrows() == 1 => ('[', ']'),
+            0 => ('┌', '┐'),
+            _ if i == m.nrows() - 1 => ('└', '┘'),
+            _ => ('│', '│'),
+        };
+        let row: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:>width$}", cell, width = width)).collect();
+        lines.push(format!("{} {} {}", left, row.join("  "), right));
+    }
+    lines.join("\n")
+}
+
+pub fn format_complex(c: &Complex64, format_part: &dyn Fn(f64) -> String) -> String {
+    let im = if c.im == 1.0 {
+        "i".to_string()
+    } else if c.im == -1.0 {
+        "-i".to_string()
+    } else {
+        format!("{}i", format_part(c.im))
+    };
+    if c.re == 0.0 {
+        im
+    } else if im.starts_with('-') {
+        format!("{}{}", format_part(c.re), im)
+    } else {
+        format!("{}+{}", format_part(c.re), im)
+    }
 }
 
 impl fmt::Display for DataType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             DataType::Number(n) => write!(f, "{}", n),
+            DataType::Complex(c) => write!(f, "{}", format_complex(c, &format_float)),
+            DataType::Quantity(value, unit) => write!(f, "{}", unit.format(*value, &format_float)),
+            DataType::Uncertain(u) => write!(f, "{}", u),
+            DataType::Interval(i) => write!(f, "{}", i),
+            DataType::Vector(v) => {
+                let items: Vec<String> = v.iter().map(|x| format_float(*x)).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            DataType::Matrix(m) => write!(f, "{}", format_matrix(m, &format_float)),
+            DataType::List(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            DataType::String(s) => write!(f, "{}", s),
+            DataType::Expr(expr) => write!(f, "{}", expr),
             DataType::Function(_, _, _) => write!(f, "Function"),
+            DataType::System(vars, _) => write!(f, "System({})", vars.join(", ")),
+            DataType::Bool(b) => write!(f, "{}", b),
             DataType::Null() => write!(f, "Null"),
         }
     }
@@ -233,13 +529,11 @@ impl Scope {
     pub fn new(parent: Option<Scope>) -> Scope {
         Scope {
             symbols: HashMap::new(),
-            parent: match parent {
-                Some(p) => Some(Box::new(p)),
-                None => None,
-            },
+            parent: parent.map(Box::new),
         }
     }
 
+    #[allow(dead_code)]
     pub fn get_all_symbols(&self) -> HashMap<String, Symbol> {
         let mut symbols = self.symbols.clone();
         if let Some(parent) = &self.parent {
@@ -249,4 +543,31 @@ impl Scope {
         }
         symbols
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Unit;
+
+    fn quantity(value: f64, unit: &str) -> DataType {
+        let unit = Unit::parse(unit).unwrap();
+        DataType::Quantity(value * unit.scale, unit)
+    }
+
+    #[test]
+    fn quantities_are_equal_by_their_physical_value() {
+        assert_eq!(quantity(1000.0, "m"), quantity(1.0, "km"));
+        assert_ne!(quantity(1.0, "m"), quantity(1.0, "s"));
+    }
+
+    #[test]
+    fn quantities_in_different_units_are_not_identical() {
+        assert!(!quantity(1000.0, "m").is_identical(&quantity(1.0, "km")));
+        assert!(quantity(1.0, "km").is_identical(&quantity(1.0, "km")));
+    }
+
+    #[test]
+    fn integers_and_floats_are_not_identical() {
+        assert!(!DataType::Number(Number::Int(1)).is_identical(&DataType::Number(Number::Float(1.0))));
+    }
+}
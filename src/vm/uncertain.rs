@@ -0,0 +1,209 @@
+use std::fmt;
+
+use super::number::format_float;
+
+// Numbers that aren't known exactly. A value with an uncertainty, '9.81 +- 0.02', is a measurement
+// with a standard error: errors are propagated to first order, assuming the inputs are independent.
+// An interval, '[1 .. 2]', is a range that contains the exact value: interval arithmetic gives bounds
+// that are guaranteed to contain every possible result.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Uncertain {
+    pub value: f64,
+    pub error: f64, // Never negative
+}
+
+impl Uncertain {
+    pub fn new(value: f64, error: f64) -> Uncertain {
+        Uncertain { value, error: error.abs() }
+    }
+
+    pub fn exact(value: f64) -> Uncertain {
+        Uncertain { value, error: 0.0 }
+    }
+
+    /// Applies a function with the given derivative, f(x ± e) = f(x) ± |f'(x)|·e
+    pub fn apply(self, f: impl Fn(f64) -> f64, derivative: impl Fn(f64) -> f64) -> Uncertain {
+        Uncertain::new(f(self.value), derivative(self.value) * self.error)
+    }
+
+    pub fn powf(self, exponent: Uncertain) -> Uncertain {
+        let value = self.value.powf(exponent.value);
+        // The partial derivatives by the base and by the exponent
+        let by_base = if self.error == 0.0 { 0.0 } else { exponent.value * self.value.powf(exponent.value - 1.0) * self.error };
+        let by_exponent = if exponent.error == 0.0 { 0.0 } else { value * self.value.ln() * exponent.error };
+        Uncertain::new(value, by_base.hypot(by_exponent))
+    }
+
+    /// Formats both parts, e.g. '9.81 ± 0.02'
+    pub fn format(&self, format_part: &dyn Fn(f64) -> String) -> String {
+        format!("{} ± {}", format_part(self.value), format_part(self.error))
+    }
+}
+
+impl std::ops::Add for Uncertain {
+    type Output = Uncertain;
+
+    fn add(self, other: Uncertain) -> Uncertain {
+        Uncertain::new(self.value + other.value, self.error.hypot(other.error))
+    }
+}
+
+impl std::ops::Sub for Uncertain {
+    type Output = Uncertain;
+
+    fn sub(self, other: Uncertain) -> Uncertain {
+        Uncertain::new(self.value - other.value, self.error.hypot(other.error))
+    }
+}
+
+impl std::ops::Mul for Uncertain {
+    type Output = Uncertain;
+
+    fn mul(self, other: Uncertain) -> Uncertain {
+        let error = (other.value * self.error).hypot(self.value * other.error);
+        Uncertain::new(self.value * other.value, error)
+    }
+}
+
+impl std::ops::Div for Uncertain {
+    type Output = Uncertain;
+
+    fn div(self, other: Uncertain) -> Uncertain {
+        let error = (self.error / other.value).hypot(self.value * other.error / (other.value * other.value));
+        Uncertain::new(self.value / other.value, error)
+    }
+}
+
+impl std::ops::Neg for Uncertain {
+    type Output = Uncertain;
+
+    fn neg(self) -> Uncertain {
+        Uncertain::new(-self.value, self.error)
+    }
+}
+
+// The error is shown with at most two significant digits and the value is rounded to the same decimal place
+impl fmt::Display for Uncertain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.error == 0.0 || !self.error.is_finite() {
+            return write!(f, "{}", self.format(&format_float));
+        }
+        let mut decimals = (1 - self.error.log10().floor() as i32).max(0) as usize;
+        if decimals > 0 && format!("{:.*}", decimals, self.error).ends_with('0') {
+            decimals -= 1;
+        }
+        write!(f, "{}", self.format(&|x| format!("{:.*}", decimals, x)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Interval {
+    pub fn new(lower: f64, upper: f64) -> Interval {
+        Interval { lower, upper }
+    }
+
+    pub fn exact(value: f64) -> Interval {
+        Interval { lower: value, upper: value }
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        self.lower <= value && value <= self.upper
+    }
+
+    // The smallest interval that contains all the values
+    fn hull(values: &[f64]) -> Interval {
+        let lower = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let upper = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Interval { lower, upper }
+    }
+
+    /// Applies a function that is increasing (or decreasing) on the whole interval
+    pub fn monotonic(self, f: impl Fn(f64) -> f64) -> Interval {
+        Interval::hull(&[f(self.lower), f(self.upper)])
+    }
+
+    pub fn powi(self, exponent: i32) -> Interval {
+        let interval = self.monotonic(|x| x.powi(exponent));
+        // Even powers have their minimum at zero
+        if exponent % 2 == 0 && exponent > 0 && self.contains(0.0) {
+            return Interval::new(0.0, interval.upper);
+        }
+        interval
+    }
+
+    /// sin over the interval. Its extremes are at the endpoints, unless the interval contains a peak.
+    pub fn sin(self) -> Interval {
+        let Interval { mut lower, mut upper } = self.monotonic(f64::sin);
+        let contains_peak = |offset: f64| {
+            let peak = offset + 2.0 * std::f64::consts::PI * ((self.lower - offset) / (2.0 * std::f64::consts::PI)).ceil();
+            peak <= self.upper
+        };
+        if contains_peak(std::f64::consts::FRAC_PI_2) {
+            upper = 1.0;
+        }
+        if contains_peak(-std::f64::consts::FRAC_PI_2) {
+            lower = -1.0;
+        }
+        Interval { lower, upper }
+    }
+
+    pub fn format(&self, format_part: &dyn Fn(f64) -> String) -> String {
+        format!("[{} .. {}]", format_part(self.lower), format_part(self.upper))
+    }
+}
+
+impl std::ops::Add for Interval {
+    type Output = Interval;
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(self.lower + other.lower, self.upper + other.upper)
+    }
+}
+
+impl std::ops::Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, other: Interval) -> Interval {
+        Interval::new(self.lower - other.upper, self.upper - other.lower)
+    }
+}
+
+impl std::ops::Mul for Interval {
+    type Output = Interval;
+
+    fn mul(self, other: Interval) -> Interval {
+        Interval::hull(&[
+            self.lower * other.lower, self.lower * other.upper,
+            self.upper * other.lower, self.upper * other.upper,
+        ])
+    }
+}
+
+// Dividing by an interval that contains zero has no bounds, the interpreter reports it as an error
+impl std::ops::Div for Interval {
+    type Output = Interval;
+
+    fn div(self, other: Interval) -> Interval {
+        self * Interval::new(1.0 / other.upper, 1.0 / other.lower)
+    }
+}
+
+impl std::ops::Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval::new(-self.upper, -self.lower)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(&format_float))
+    }
+}